@@ -1,4 +1,4 @@
-use edf::Style;
+use edf::{Header, PageGeometry, Style};
 use embedded_graphics::{
     geometry::{Point, Size},
     primitives::rectangle::Rectangle,
@@ -33,6 +33,42 @@ impl DeviceConfig {
         let height = self.height_px - self.top_margin_px - self.bottom_margin_px;
         Rectangle::new(Point::new(0, 0), Size::new(width, height))
     }
+
+    pub fn page_geometry(&self) -> Result<PageGeometry, Box<dyn Error>> {
+        Ok(PageGeometry {
+            width: self.width_px.try_into()?,
+            height: self.height_px.try_into()?,
+            top_margin: self.top_margin_px.try_into()?,
+            right_margin: self.right_margin_px.try_into()?,
+            bottom_margin: self.bottom_margin_px.try_into()?,
+            left_margin: self.left_margin_px.try_into()?,
+        })
+    }
+}
+
+/// Returns the page geometry used to display a document: that of the device config at
+/// `device_config`, if any, or else the geometry recorded in the document. Warns if the two
+/// disagree, as pages laid out for one geometry are clipped or misplaced on another.
+pub fn display_geometry(
+    header: &Header,
+    device_config: Option<&str>,
+) -> Result<PageGeometry, Box<dyn Error>> {
+    let config = match device_config {
+        None => None,
+        Some(path) => Some(toml_from_file::<DeviceConfig>(path)?.page_geometry()?),
+    };
+    match (config, header.page) {
+        (Some(config), Some(page)) => {
+            if config != page {
+                eprintln!("warning: the document was laid out for a different page: {page:?}");
+            }
+            Ok(config)
+        }
+        (Some(geometry), None) | (None, Some(geometry)) => Ok(geometry),
+        (None, None) => Err(
+            "the document does not record its page geometry; a device config is required".into(),
+        ),
+    }
 }
 
 #[derive(Deserialize)]
@@ -75,6 +111,30 @@ impl StyleConfig {
     }
 }
 
+/// Returns the fonts used to display a document: those given by the font config at
+/// `font_config`, if any, or else those embedded in the document, or else the bundled regular
+/// font.
+pub fn document_fonts(
+    header: &Header,
+    font_config: Option<&str>,
+) -> Result<HashMap<String, Vec<u8>>, Box<dyn Error>> {
+    match font_config {
+        Some(cfg) => {
+            let font_dir = Path::new(cfg).parent().unwrap_or(Path::new("/"));
+            toml_from_file::<FontConfig>(cfg)?.load_fonts(font_dir)
+        }
+        None if !header.fonts.is_empty() => Ok(header
+            .fonts
+            .iter()
+            .map(|f| (f.name.clone(), f.data.clone()))
+            .collect()),
+        None => Ok(HashMap::from([(
+            String::from("regular"),
+            Vec::from(LITERATA_REGULAR),
+        )])),
+    }
+}
+
 pub fn toml_from_file<T: serde::de::DeserializeOwned>(path: &str) -> Result<T, Box<dyn Error>> {
     Ok(toml::from_str(std::str::from_utf8(&fs::read(path)?)?)?)
 }
@@ -1,9 +1,15 @@
-use crate::{io::Input, DumpArgs};
+use crate::{common::*, io::Input, DumpArgs};
+use edf::Command;
 use std::error::Error;
 use std::fs::File;
 use std::io::{self, Cursor, Read};
 
 pub fn dump(args: DumpArgs) -> Result<(), Box<dyn Error>> {
+    let bounds = match args.device_config {
+        None => None,
+        Some(path) => Some(toml_from_file::<DeviceConfig>(&path)?.bounding_box().size),
+    };
+
     let mut input = match args.input_path {
         None => Input::Stdin(io::stdin()),
         Some(path) => Input::File(File::open(path)?),
@@ -16,25 +22,52 @@ pub fn dump(args: DumpArgs) -> Result<(), Box<dyn Error>> {
 
     let header = edf::read::header(&mut cursor)?;
     edf::read::seek_trailer(&mut cursor)?;
-    let trailer = edf::read::trailer(&mut cursor)?;
+    let trailer = edf::read::trailer(&header, &mut cursor)?;
+    edf::read::validate(&header, &trailer, bytes.len())?;
+    let warnings = edf::read::verify(&header, &trailer, &bytes)?;
 
     println!("# Header");
     println!("Title: ${:?}", header.title);
+    if let Some(language) = &header.language {
+        println!("Language: {language}");
+    }
+    if let Some(revision) = header.revision {
+        println!("Revision: {revision:016x}");
+    }
     println!("Styles:");
     for style in &header.styles {
         println!("- `{style:?}`");
     }
+    if !header.fonts.is_empty() {
+        println!("Fonts:");
+        for font in &header.fonts {
+            println!("- `{}` ({} bytes)", font.name, font.data.len());
+        }
+    }
     println!();
 
     println!("# Pages");
     for (num, offset) in trailer.pages.iter().enumerate() {
         println!();
         println!("## Page {} @{}", num + 1, offset);
+        for (_, warning) in warnings.iter().filter(|(page, _)| *page as usize == num) {
+            println!("Warning: {warning}");
+        }
 
         let page = edf::read::page(&header, &bytes[*offset as usize..])?;
 
         for command in page {
-            println!("- `{command:?}`");
+            match (&command, bounds) {
+                (Command::SetCursor { x, y }, Some(size))
+                    if *x as u32 > size.width || *y as u32 > size.height =>
+                {
+                    println!(
+                        "- `{command:?}` (outside page bounds {}x{})",
+                        size.width, size.height
+                    );
+                }
+                _ => println!("- `{command:?}`"),
+            }
         }
     }
 
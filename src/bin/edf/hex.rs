@@ -0,0 +1,121 @@
+use crate::{io::Input, HexArgs};
+use edf::Command;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, Cursor, Read};
+
+/// Number of bytes printed on each line.
+const ROW_LEN: usize = 16;
+
+/// Prints `bytes`, which start at `offset` within the file, as rows of hex followed by
+/// `annotation` on the first row.
+fn print_hex(offset: usize, bytes: &[u8], annotation: &str) {
+    if bytes.is_empty() {
+        println!(
+            "{offset:08x}  {:width$}  {annotation}",
+            "",
+            width = ROW_LEN * 3 - 1
+        );
+        return;
+    }
+    for (i, row) in bytes.chunks(ROW_LEN).enumerate() {
+        let digits = row
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let annotation = if i == 0 { annotation } else { "" };
+        println!(
+            "{:08x}  {digits:width$}  {annotation}",
+            offset + i * ROW_LEN,
+            width = ROW_LEN * 3 - 1
+        );
+    }
+}
+
+pub fn hex(args: HexArgs) -> Result<(), Box<dyn Error>> {
+    let mut input = match args.input_path {
+        None => Input::Stdin(io::stdin()),
+        Some(path) => Input::File(File::open(path)?),
+    };
+
+    let mut bytes = Vec::new();
+    input.read_to_end(&mut bytes)?;
+
+    let mut cursor = Cursor::new(&bytes);
+
+    let header = edf::read::header(&mut cursor)?;
+    let header_end = cursor.position() as usize;
+    let trailer_start = edf::read::seek_trailer(&mut cursor)? as usize;
+    let trailer = edf::read::trailer(&header, &mut cursor)?;
+    edf::read::validate(&header, &trailer, bytes.len())?;
+
+    println!("# Header");
+    print_hex(
+        0,
+        &bytes[..4],
+        &format!("magic, version {}", header.version),
+    );
+    let mut fields = vec![
+        format!("title {:?}", header.title),
+        format!("{} styles", header.styles.len()),
+    ];
+    if header.version >= 2 {
+        fields.push(format!(
+            "{} bytes of images",
+            header.images.as_bytes().len()
+        ));
+        fields.push(format!("language {:?}", header.language));
+    }
+    if header.version >= 3 {
+        fields.push(format!("revision {:?}", header.revision));
+    }
+    if header.version >= 4 {
+        fields.push(format!("{} fonts", header.fonts.len()));
+    }
+    if header.version >= 5 {
+        fields.push(format!("metadata {:?}", header.metadata));
+    }
+    if header.version >= 6 {
+        fields.push(format!("page {:?}", header.page));
+    }
+    print_hex(4, &bytes[4..header_end], &fields.join(", "));
+
+    for (num, offset) in trailer.pages.iter().enumerate() {
+        let offset = *offset as usize;
+        println!();
+        println!("# Page {}", num + 1);
+
+        let page = edf::read::page_with_offsets(&header, &bytes[offset..])?;
+        for (i, (start, command)) in page.iter().enumerate() {
+            let end = match (page.get(i + 1), command) {
+                (Some((next, _)), _) => *next,
+                // Pages end with a single-byte page break or end command.
+                (None, Command::PageBreak | Command::End) => start + 1,
+                (None, _) => trailer_start - offset,
+            };
+            print_hex(
+                offset + start,
+                &bytes[offset + start..offset + end],
+                &format!("{command:?}"),
+            );
+        }
+    }
+
+    println!();
+    println!("# Trailer");
+    let back_pointer = bytes.len() - 4;
+    print_hex(
+        trailer_start,
+        &bytes[trailer_start..back_pointer],
+        &format!(
+            "{} pages, {} bytes of URLs, {} anchors",
+            trailer.pages.len(),
+            trailer.urls.as_bytes().len(),
+            trailer.anchors.len()
+        ),
+    );
+    print_hex(back_pointer, &bytes[back_pointer..], "trailer offset");
+
+    Ok(())
+}
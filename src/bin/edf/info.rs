@@ -0,0 +1,103 @@
+use crate::{io::Input, InfoArgs};
+use edf::Command;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, Cursor, Read};
+
+/// Returns the name of a command's variant.
+fn command_name<S: Clone>(command: &Command<S>) -> &'static str {
+    match command {
+        Command::Nop => "Nop",
+        Command::HTab => "HTab",
+        Command::LineBreak => "LineBreak",
+        Command::VTab => "VTab",
+        Command::PageBreak => "PageBreak",
+        Command::Show { .. } => "Show",
+        Command::ShowPositioned { .. } => "ShowPositioned",
+        Command::Advance { .. } => "Advance",
+        Command::SetCursor { .. } => "SetCursor",
+        Command::MoveBy { .. } => "MoveBy",
+        Command::FillRect { .. } => "FillRect",
+        Command::SetStyle { .. } => "SetStyle",
+        Command::SetAdjustmentRatio { .. } => "SetAdjustmentRatio",
+        Command::SetLineMetrics { .. } => "SetLineMetrics",
+        Command::SetColor { .. } => "SetColor",
+        Command::SetDecoration { .. } => "SetDecoration",
+        Command::DrawImage { .. } => "DrawImage",
+        Command::Link { .. } => "Link",
+        Command::Anchor { .. } => "Anchor",
+        Command::End => "End",
+    }
+}
+
+pub fn info(args: InfoArgs) -> Result<(), Box<dyn Error>> {
+    let mut input = match args.input_path {
+        None => Input::Stdin(io::stdin()),
+        Some(path) => Input::File(File::open(path)?),
+    };
+
+    let mut bytes = Vec::new();
+    input.read_to_end(&mut bytes)?;
+
+    let mut cursor = Cursor::new(&bytes);
+
+    let header = edf::read::header(&mut cursor)?;
+    edf::read::seek_trailer(&mut cursor)?;
+    let trailer = edf::read::trailer(&header, &mut cursor)?;
+    edf::read::validate(&header, &trailer, bytes.len())?;
+
+    let mut counts: HashMap<&'static str, usize> = HashMap::new();
+    let mut total = 0;
+    for offset in &trailer.pages {
+        let page = edf::read::page(&header, &bytes[*offset as usize..])?;
+        total += page.len();
+        for command in &page {
+            *counts.entry(command_name(command)).or_default() += 1;
+        }
+    }
+
+    println!("Version: {}", header.version);
+    println!("Title: {:?}", header.title);
+    if let Some(author) = &header.metadata.author {
+        println!("Author: {author:?}");
+    }
+    if let Some(language) = &header.language {
+        println!("Language: {language}");
+    }
+    if let Some(date) = &header.metadata.date {
+        println!("Date: {date}");
+    }
+    for (key, value) in &header.metadata.other {
+        println!("Metadata {key:?}: {} bytes", value.len());
+    }
+    println!("File size: {} bytes", bytes.len());
+    if let Some(page) = &header.page {
+        println!(
+            "Page: {}x{}px, margins {}/{}/{}/{}px (top/right/bottom/left)",
+            page.width,
+            page.height,
+            page.top_margin,
+            page.right_margin,
+            page.bottom_margin,
+            page.left_margin
+        );
+    }
+    println!("Pages: {}", trailer.pages.len());
+    println!("Styles: {}", header.styles.len());
+    for (i, style) in header.styles.iter().enumerate() {
+        println!("- {i}: `{}` at {}px", style.font_name, style.em_px);
+    }
+    println!("Commands: {total}");
+
+    // The most frequent commands are listed first.
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by(|(a_name, a_count), (b_name, b_count)| {
+        b_count.cmp(a_count).then(a_name.cmp(b_name))
+    });
+    for (name, count) in counts {
+        println!("- {name}: {count}");
+    }
+
+    Ok(())
+}
@@ -3,13 +3,27 @@ use std::error::Error;
 
 mod common;
 mod dump;
+mod hex;
+mod info;
 mod io;
+mod merge;
 mod mk;
+mod render;
 mod show;
+mod split;
+mod text;
+mod validate;
 
 use dump::dump;
+use hex::hex;
+use info::info;
+use merge::merge;
 use mk::mk;
+use render::render;
 use show::show;
+use split::split;
+use text::text;
+use validate::validate;
 
 #[derive(Debug, Parser)]
 #[command(name = "edf")]
@@ -22,8 +36,15 @@ struct Cli {
 #[derive(Debug, Subcommand)]
 enum Commands {
     Dump(DumpArgs),
+    Hex(HexArgs),
+    Info(InfoArgs),
+    Merge(MergeArgs),
     Mk(MkArgs),
+    Render(RenderArgs),
     Show(ShowArgs),
+    Split(SplitArgs),
+    Text(TextArgs),
+    Validate(ValidateArgs),
 }
 
 #[derive(Debug, Args)]
@@ -45,6 +66,15 @@ struct MkArgs {
 
     #[arg(short, required = false)]
     output_path: Option<String>,
+
+    #[arg(long, required = false)]
+    language: Option<String>,
+
+    #[arg(long)]
+    embed_fonts: bool,
+
+    #[arg(long)]
+    toc: bool,
 }
 
 #[derive(Debug, Clone, ValueEnum)]
@@ -59,15 +89,39 @@ enum MkFormat {
 struct DumpArgs {
     #[arg(index = 1, required = false)]
     input_path: Option<String>,
+
+    #[arg(short, required = false)]
+    device_config: Option<String>,
 }
 
 #[derive(Debug, Args)]
-struct ShowArgs {
+struct HexArgs {
     #[arg(index = 1, required = false)]
     input_path: Option<String>,
+}
+
+#[derive(Debug, Args)]
+struct InfoArgs {
+    #[arg(index = 1, required = false)]
+    input_path: Option<String>,
+}
+
+#[derive(Debug, Args)]
+struct MergeArgs {
+    #[arg(index = 1, required = true, num_args = 1..)]
+    input_paths: Vec<String>,
 
     #[arg(short, required = true)]
-    device_config: String,
+    output_path: String,
+}
+
+#[derive(Debug, Args)]
+struct ShowArgs {
+    #[arg(index = 1, required = false)]
+    input_path: Option<String>,
+
+    #[arg(short, required = false)]
+    device_config: Option<String>,
 
     #[arg(short, required = false)]
     font_config: Option<String>,
@@ -76,12 +130,76 @@ struct ShowArgs {
     page_num: u32,
 }
 
+#[derive(Debug, Args)]
+struct RenderArgs {
+    #[arg(index = 1, required = false)]
+    input_path: Option<String>,
+
+    #[arg(short, required = false)]
+    device_config: Option<String>,
+
+    #[arg(short, required = false)]
+    font_config: Option<String>,
+
+    #[arg(short, required_unless_present = "all", conflicts_with = "all")]
+    page_num: Option<u32>,
+
+    #[arg(long)]
+    all: bool,
+
+    #[arg(short, required = true)]
+    output_dir: String,
+
+    #[arg(long, required = false, value_enum, default_value = "png")]
+    format: RenderFormat,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+enum RenderFormat {
+    Png,
+    Ppm,
+}
+
+#[derive(Debug, Args)]
+struct SplitArgs {
+    #[arg(index = 1, required = false)]
+    input_path: Option<String>,
+
+    #[arg(long, required = true)]
+    pages: String,
+
+    #[arg(short, required = true)]
+    output_path: String,
+}
+
+#[derive(Debug, Args)]
+struct TextArgs {
+    #[arg(index = 1, required = false)]
+    input_path: Option<String>,
+
+    #[arg(short, long = "page", required = false)]
+    page_num: Option<u32>,
+}
+
+#[derive(Debug, Args)]
+struct ValidateArgs {
+    #[arg(index = 1, required = false)]
+    input_path: Option<String>,
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
     let args = Cli::parse();
 
     match args.command {
         Commands::Dump(args) => dump(args),
+        Commands::Hex(args) => hex(args),
+        Commands::Info(args) => info(args),
+        Commands::Merge(args) => merge(args),
         Commands::Mk(args) => mk(args),
+        Commands::Render(args) => render(args),
         Commands::Show(args) => show(args),
+        Commands::Split(args) => split(args),
+        Commands::Text(args) => text(args),
+        Commands::Validate(args) => validate(args),
     }
 }
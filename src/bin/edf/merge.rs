@@ -0,0 +1,46 @@
+use crate::MergeArgs;
+use edf::{Command, Header, StringPool, Trailer};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufWriter, Cursor, Write};
+
+pub fn merge(args: MergeArgs) -> Result<(), Box<dyn Error>> {
+    let inputs = args
+        .input_paths
+        .iter()
+        .map(fs::read)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut docs: Vec<(Header, Trailer)> = Vec::new();
+    for bytes in &inputs {
+        let mut cursor = Cursor::new(bytes);
+        let header = edf::read::header(&mut cursor)?;
+        edf::read::seek_trailer(&mut cursor)?;
+        let trailer = edf::read::trailer(&header, &mut cursor)?;
+        edf::read::validate(&header, &trailer, bytes.len())?;
+        docs.push((header, trailer));
+    }
+
+    let mut pages: Vec<Vec<Vec<Command<&str>>>> = Vec::new();
+    for ((header, trailer), bytes) in docs.iter().zip(&inputs) {
+        let doc_pages = trailer
+            .pages
+            .iter()
+            .map(|offset| edf::read::page(header, &bytes[*offset as usize..]))
+            .collect::<Result<Vec<_>, _>>()?;
+        pages.push(doc_pages);
+    }
+
+    let parts: Vec<(&Header, &StringPool, &[Vec<Command<&str>>])> = docs
+        .iter()
+        .zip(&pages)
+        .map(|((header, trailer), pages)| (header, &trailer.urls, pages.as_slice()))
+        .collect();
+    let (header, urls, commands) = edf::write::merge(&parts)?;
+
+    let mut w = BufWriter::new(File::create(&args.output_path)?);
+    edf::write::doc(&mut w, header.version, &header, &urls, &commands)?;
+    w.flush()?;
+
+    Ok(())
+}
@@ -3,26 +3,115 @@ use crate::{
     io::{Input, Output},
     MkArgs, MkFormat,
 };
-use edf::{font_db::Fonts, layout};
+use edf::{font_db::Fonts, layout, Command, EmbeddedFont, Header, StringPool};
 use hyphenation::{Hyphenator, Language, Load, Standard};
 use serde::Deserialize;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::error::Error;
 use std::fs::File;
 use std::io::{self, Cursor, Read, Write};
 use std::num::NonZeroUsize;
 use std::path::Path;
 
-struct StandardHyphenator(Standard);
+/// Hyphenates words using the embedded patterns for a language. Words are not hyphenated if
+/// there are no patterns for the language.
+struct StandardHyphenator(Option<Standard>);
+
+impl StandardHyphenator {
+    /// Loads the patterns for a BCP-47 language tag, defaulting to US English if no tag is given.
+    fn new(language: Option<&str>) -> Self {
+        let patterns = match language {
+            None => Some(Language::EnglishUS),
+            Some(tag) => hyphenation_language(tag),
+        };
+        let standard = patterns.and_then(|language| Standard::from_embedded(language).ok());
+        if let (None, Some(tag)) = (&standard, language) {
+            eprintln!("warning: no hyphenation patterns for language {tag:?}");
+        }
+        StandardHyphenator(standard)
+    }
+}
 
 impl layout::Hyphenator for &StandardHyphenator {
     fn hyphenate(&self, word: &str, breaks: &mut Vec<usize>) {
-        let hyphenated = self.0.hyphenate(word);
         breaks.clear();
-        breaks.extend(hyphenated.breaks);
+        if let Some(standard) = &self.0 {
+            breaks.extend(standard.hyphenate(word).breaks);
+        }
     }
 }
 
+/// Returns the hyphenation patterns for a BCP-47 language tag, if any. Tags are matched against
+/// the patterns' codes, e.g. `en-gb`, and then by their primary language subtag alone.
+fn hyphenation_language(tag: &str) -> Option<Language> {
+    let tag = tag.to_ascii_lowercase().replace('_', "-");
+    if let Some(language) = Language::try_from_code(&tag) {
+        return Some(language);
+    }
+
+    // Some languages only have patterns for particular variants.
+    let primary = tag.split('-').next().unwrap_or_default();
+    match primary {
+        "en" => Some(Language::EnglishUS),
+        "de" => Some(Language::German1996),
+        "el" => Some(Language::GreekMono),
+        "sr" => Some(Language::SerbianCyrillic),
+        _ => Language::try_from_code(primary),
+    }
+}
+
+/// Computes a document revision from the source's contents using 64-bit FNV-1a, so that the
+/// same source always produces the same revision.
+fn revision(source: &[u8]) -> u64 {
+    source.iter().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Embeds the fonts used by a document in its header. Each font is subset to the characters the
+/// document shows in it; fonts that cannot be subset are embedded whole.
+fn embed_fonts(
+    header: &mut Header,
+    commands: &[Command<String>],
+    fonts: &Fonts,
+    font_data: &HashMap<String, Vec<u8>>,
+) -> Result<(), Box<dyn Error>> {
+    let mut used: BTreeMap<&str, BTreeSet<char>> = BTreeMap::new();
+    let mut style = 0;
+    for command in commands {
+        let shown: Vec<char> = match command {
+            Command::SetStyle { s } => {
+                style = *s as usize;
+                continue;
+            }
+            Command::Show { str } => str.chars().collect(),
+            Command::ShowPositioned { glyphs } => glyphs.iter().map(|(c, _)| *c).collect(),
+            _ => continue,
+        };
+        if let Some(s) = header.styles.get(style) {
+            used.entry(s.font_name.as_str()).or_default().extend(shown);
+        }
+    }
+
+    let mut embedded = Vec::new();
+    for (name, chars) in used {
+        let data = match fonts.subset(name, chars) {
+            Ok(data) => data,
+            Err(edf::font_db::SubsetError::Unsupported) => match font_data.get(name) {
+                None => continue,
+                Some(data) => data.clone(),
+            },
+            Err(err) => return Err(err.into()),
+        };
+        embedded.push(EmbeddedFont {
+            name: String::from(name),
+            data,
+        });
+    }
+    header.fonts = embedded;
+    Ok(())
+}
+
 mod mk_markdown {
     use super::*;
     use markdown::*;
@@ -33,6 +122,8 @@ mod mk_markdown {
         pub emphasis: Option<StyleConfig>,
         pub strong: Option<StyleConfig>,
         pub heading: Option<Vec<StyleConfig>>,
+        pub code: Option<StyleConfig>,
+        pub language: Option<String>,
     }
 
     impl Config {
@@ -44,6 +135,8 @@ mod mk_markdown {
                     self.heading
                         .map(|v| v.iter().map(|s| s.device_style(device)).collect()),
                 )
+                .with_code(self.code.map(|s| s.device_style(device)))
+                .with_language(self.language)
         }
     }
 
@@ -51,22 +144,29 @@ mod mk_markdown {
         input: &mut R,
         output: &mut W,
         fonts: &Fonts,
-        hyphenator: &StandardHyphenator,
         device_config: &DeviceConfig,
         markdown_config: Config,
+        font_data: Option<&HashMap<String, Vec<u8>>>,
     ) -> Result<(), Box<dyn Error>> {
         let mut markdown_bytes = Vec::new();
         input.read_to_end(&mut markdown_bytes)?;
 
-        let opts = Default::default();
+        let opts = ParseOptions {
+            constructs: Constructs {
+                gfm_table: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
         let (events, state) = parser::parse(std::str::from_utf8(&markdown_bytes)?, &opts)?;
+        let hyphenator = StandardHyphenator::new(markdown_config.language.as_deref());
 
-        let (header, commands) = match layout::markdown::build(
+        let (mut header, commands) = match layout::markdown::build(
             &events,
             state.bytes,
             device_config.bounding_box(),
             fonts,
-            hyphenator,
+            &hyphenator,
             markdown_config.into_device_options(device_config),
         ) {
             Ok(ok) => ok,
@@ -74,8 +174,13 @@ mod mk_markdown {
                 layout::markdown::Error::Generic(msg) => return Err(msg.into()),
             },
         };
+        header.page = Some(device_config.page_geometry()?);
+        header.revision = Some(revision(&markdown_bytes));
+        if let Some(font_data) = font_data {
+            embed_fonts(&mut header, &commands, fonts, font_data)?;
+        }
 
-        edf::write::doc(output, &header, &commands)?;
+        edf::write::doc(output, edf::VERSION, &header, &StringPool::new(), &commands)?;
         Ok(())
     }
 }
@@ -91,6 +196,17 @@ mod mk_epub {
         pub emphasis: Option<StyleConfig>,
         pub strong: Option<StyleConfig>,
         pub heading: Option<Vec<StyleConfig>>,
+        pub code: Option<StyleConfig>,
+        pub language: Option<String>,
+        #[serde(default)]
+        pub toc: bool,
+        /// The indent of definitions in definition lists, in points.
+        pub definition_indent: Option<f32>,
+        /// Cut off long lines of preformatted text at the page's edge instead of wrapping them.
+        #[serde(default)]
+        pub pre_clip: bool,
+        /// The column after which long lines of preformatted text wrap, if any.
+        pub pre_wrap_column: Option<usize>,
     }
 
     impl Config {
@@ -102,6 +218,20 @@ mod mk_epub {
                     self.heading
                         .map(|v| v.iter().map(|s| s.device_style(device)).collect()),
                 )
+                .with_code(self.code.map(|s| s.device_style(device)))
+                .with_language(self.language)
+                .with_toc(self.toc)
+                .with_definition_indent(
+                    self.definition_indent
+                        .map(|pt| device.point_size_to_px(pt) as f32),
+                )
+                .with_pre_overflow(if self.pre_clip {
+                    layout::VerbatimOverflow::Clip
+                } else {
+                    layout::VerbatimOverflow::Wrap {
+                        column: self.pre_wrap_column,
+                    }
+                })
         }
     }
 
@@ -109,24 +239,37 @@ mod mk_epub {
         input: &mut R,
         output: &mut W,
         fonts: &Fonts,
-        hyphenator: &StandardHyphenator,
         device_config: &DeviceConfig,
         epub_config: Config,
+        font_data: Option<&HashMap<String, Vec<u8>>>,
     ) -> Result<(), Box<dyn Error>> {
         let mut epub_bytes = Vec::new();
         input.read_to_end(&mut epub_bytes)?;
 
+        let revision = revision(&epub_bytes);
         let mut doc = EpubDoc::from_reader(Cursor::new(epub_bytes))?;
 
-        let (header, commands) = layout::epub::build(
+        // Books are hyphenated in their own language unless another is given.
+        let language = epub_config
+            .language
+            .clone()
+            .or_else(|| doc.mdata("language"));
+        let hyphenator = StandardHyphenator::new(language.as_deref());
+
+        let (mut header, commands) = layout::epub::build(
             &mut doc,
             device_config.bounding_box(),
             fonts,
-            hyphenator,
+            &hyphenator,
             epub_config.into_device_options(device_config),
         )?;
+        header.page = Some(device_config.page_geometry()?);
+        header.revision = Some(revision);
+        if let Some(font_data) = font_data {
+            embed_fonts(&mut header, &commands, fonts, font_data)?;
+        }
 
-        edf::write::doc(output, &header, &commands)?;
+        edf::write::doc(output, edf::VERSION, &header, &StringPool::new(), &commands)?;
         Ok(())
     }
 }
@@ -155,11 +298,15 @@ pub fn mk(args: MkArgs) -> Result<(), Box<dyn Error>> {
         Some(path) => Output::File(File::create(path)?),
     };
 
-    let hyphenator = StandardHyphenator(Standard::from_embedded(Language::EnglishUS)?);
+    let embedded_font_data = if args.embed_fonts {
+        Some(&font_data)
+    } else {
+        None
+    };
 
     match args.format {
         Some(MkFormat::Markdown) | None => {
-            let config = match args.format_config {
+            let mut config = match args.format_config {
                 Some(path) => toml_from_file(&path)?,
                 None => mk_markdown::Config {
                     regular: StyleConfig {
@@ -169,20 +316,23 @@ pub fn mk(args: MkArgs) -> Result<(), Box<dyn Error>> {
                     emphasis: None,
                     strong: None,
                     heading: None,
+                    code: None,
+                    language: None,
                 },
             };
+            config.language = args.language.or(config.language);
             mk_markdown::mk(
                 &mut input,
                 &mut output,
                 &fonts,
-                &hyphenator,
                 &device_config,
                 config,
+                embedded_font_data,
             )
         }
         #[cfg(feature = "epub")]
         Some(MkFormat::Epub) => {
-            let config = match args.format_config {
+            let mut config = match args.format_config {
                 Some(path) => toml_from_file(&path)?,
                 None => mk_epub::Config {
                     regular: StyleConfig {
@@ -192,15 +342,23 @@ pub fn mk(args: MkArgs) -> Result<(), Box<dyn Error>> {
                     emphasis: None,
                     strong: None,
                     heading: None,
+                    code: None,
+                    language: None,
+                    toc: false,
+                    definition_indent: None,
+                    pre_clip: false,
+                    pre_wrap_column: None,
                 },
             };
+            config.language = args.language.or(config.language);
+            config.toc = args.toc || config.toc;
             mk_epub::mk(
                 &mut input,
                 &mut output,
                 &fonts,
-                &hyphenator,
                 &device_config,
                 config,
+                embedded_font_data,
             )
         }
     }
@@ -0,0 +1,149 @@
+use crate::{common::*, io::Input, RenderArgs, RenderFormat};
+use edf::{display, font_db};
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::{OriginDimensions, Size},
+    pixelcolor::{Gray8, GrayColor},
+    Pixel,
+};
+use std::convert::Infallible;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Cursor, Read, Write};
+use std::num::NonZeroUsize;
+use std::path::Path;
+
+/// An in-memory grayscale framebuffer with one byte per pixel.
+struct Framebuffer {
+    size: Size,
+    pixels: Vec<u8>,
+}
+
+impl Framebuffer {
+    fn new(size: Size) -> Self {
+        Framebuffer {
+            size,
+            pixels: vec![Gray8::WHITE.luma(); (size.width * size.height) as usize],
+        }
+    }
+
+    fn write_png(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let w = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(w, self.size.width, self.size.height);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels)?;
+        Ok(())
+    }
+
+    fn write_ppm(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let mut w = BufWriter::new(File::create(path)?);
+        write!(w, "P6\n{} {}\n255\n", self.size.width, self.size.height)?;
+        for luma in &self.pixels {
+            w.write_all(&[*luma, *luma, *luma])?;
+        }
+        w.flush()?;
+        Ok(())
+    }
+}
+
+impl OriginDimensions for Framebuffer {
+    fn size(&self) -> Size {
+        self.size
+    }
+}
+
+impl DrawTarget for Framebuffer {
+    type Color = Gray8;
+    type Error = Infallible;
+
+    fn draw_iter<I: IntoIterator<Item = Pixel<Gray8>>>(
+        &mut self,
+        pixels: I,
+    ) -> Result<(), Infallible> {
+        for Pixel(point, color) in pixels {
+            let (x, y) = (point.x, point.y);
+            if x >= 0 && y >= 0 && (x as u32) < self.size.width && (y as u32) < self.size.height {
+                self.pixels[y as usize * self.size.width as usize + x as usize] = color.luma();
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn render(args: RenderArgs) -> Result<(), Box<dyn Error>> {
+    let mut input = match args.input_path {
+        None => Input::Stdin(io::stdin()),
+        Some(path) => Input::File(File::open(path)?),
+    };
+
+    let mut bytes = Vec::new();
+    input.read_to_end(&mut bytes)?;
+
+    let mut cursor = Cursor::new(&bytes);
+
+    let header = edf::read::header(&mut cursor)?;
+    edf::read::seek_trailer(&mut cursor)?;
+    let trailer = edf::read::trailer(&header, &mut cursor)?;
+    edf::read::validate(&header, &trailer, bytes.len())?;
+    let geometry = display_geometry(&header, args.device_config.as_deref())?;
+
+    let font_data = document_fonts(&header, args.font_config.as_deref())?;
+    let mut fonts = font_db::Fonts::new(NonZeroUsize::new(256).unwrap());
+    for (name, data) in font_data.iter() {
+        fonts.add(name.as_str(), data)?;
+    }
+
+    let default_style = match fonts.get_style(&header.styles[0]) {
+        None => return Err("missing font for default style".into()),
+        Some(s) => s,
+    };
+
+    let page_count = trailer.pages.len();
+    let page_nums = match args.page_num {
+        None => (1..=page_count).collect(),
+        Some(num) if num == 0 || num as usize > page_count => {
+            return Err(format!("page {num} is out of range (1-{page_count})").into())
+        }
+        Some(num) => vec![num as usize],
+    };
+
+    let output_dir = Path::new(&args.output_dir);
+    fs::create_dir_all(output_dir)?;
+
+    let (device, content) = (geometry.bounds(), geometry.content_box());
+    for num in page_nums {
+        let offset = trailer.pages[num - 1];
+        let page = edf::read::page(&header, &bytes[offset as usize..])?;
+
+        let mut framebuffer =
+            Framebuffer::new(Size::new(geometry.width as u32, geometry.height as u32));
+        let clipped = display::page(
+            &mut framebuffer,
+            device,
+            content,
+            false,
+            Gray8::BLACK,
+            Gray8::WHITE,
+            &fonts,
+            default_style.clone(),
+            &header,
+            &page,
+        )?;
+        if clipped {
+            eprintln!("warning: page {num} has content outside of its margins");
+        }
+
+        match args.format {
+            RenderFormat::Png => {
+                framebuffer.write_png(&output_dir.join(format!("page-{num:04}.png")))?
+            }
+            RenderFormat::Ppm => {
+                framebuffer.write_ppm(&output_dir.join(format!("page-{num:04}.ppm")))?
+            }
+        }
+    }
+
+    Ok(())
+}
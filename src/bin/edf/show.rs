@@ -1,24 +1,22 @@
 use crate::{common::*, io::Input, ShowArgs};
-use edf::{display, font_db};
+use edf::{
+    display::{self, NavigationKey, Navigator},
+    font_db,
+};
 use embedded_graphics::{
-    draw_target::DrawTarget,
-    geometry::{Point, Size},
+    geometry::Size,
     pixelcolor::{Gray8, GrayColor},
 };
 use embedded_graphics_simulator::{
-    sdl2::Keycode, OutputSettingsBuilder, SimulatorDisplay, SimulatorEvent, Window,
+    sdl2::{Keycode, Mod},
+    OutputSettingsBuilder, SimulatorDisplay, SimulatorEvent, Window,
 };
-use std::cmp;
-use std::collections::HashMap;
 use std::error::Error;
 use std::fs::File;
 use std::io::{self, Cursor, Read};
 use std::num::NonZeroUsize;
-use std::path::Path;
 
 pub fn show(args: ShowArgs) -> Result<(), Box<dyn Error>> {
-    let device_config: DeviceConfig = toml_from_file(&args.device_config)?;
-
     let mut input = match args.input_path {
         None => Input::Stdin(io::stdin()),
         Some(path) => Input::File(File::open(path)?),
@@ -31,15 +29,11 @@ pub fn show(args: ShowArgs) -> Result<(), Box<dyn Error>> {
 
     let header = edf::read::header(&mut cursor)?;
     edf::read::seek_trailer(&mut cursor)?;
-    let trailer = edf::read::trailer(&mut cursor)?;
+    let trailer = edf::read::trailer(&header, &mut cursor)?;
+    edf::read::validate(&header, &trailer, bytes.len())?;
+    let geometry = display_geometry(&header, args.device_config.as_deref())?;
 
-    let font_data = match args.font_config {
-        Some(cfg) => {
-            let font_dir = Path::new(&cfg).parent().unwrap_or(Path::new("/"));
-            toml_from_file::<FontConfig>(&cfg)?.load_fonts(font_dir)?
-        }
-        None => HashMap::from([(String::from("regular"), Vec::from(LITERATA_REGULAR))]),
-    };
+    let font_data = document_fonts(&header, args.font_config.as_deref())?;
     let mut fonts = font_db::Fonts::new(NonZeroUsize::new(256).unwrap());
     for (name, data) in font_data.iter() {
         fonts.add(name.as_str(), data)?;
@@ -51,27 +45,26 @@ pub fn show(args: ShowArgs) -> Result<(), Box<dyn Error>> {
     };
 
     let mut sim =
-        SimulatorDisplay::<Gray8>::new(Size::new(device_config.width_px, device_config.height_px));
+        SimulatorDisplay::<Gray8>::new(Size::new(geometry.width as u32, geometry.height as u32));
 
     let mut debug = false;
-    let mut page_num = args.page_num as usize;
-    let offset = trailer.pages[args.page_num as usize - 1];
+    let mut nav = Navigator::new(args.page_num as usize, trailer.pages.len());
+    let offset = trailer.pages[nav.page_num() - 1];
     let page = edf::read::page(&header, &bytes[offset as usize..])?;
-    let origin = Point::new(
-        device_config.left_margin_px as i32,
-        device_config.top_margin_px as i32,
-    );
+    let (device, content) = (geometry.bounds(), geometry.content_box());
 
-    sim.clear(Gray8::WHITE)?;
     display::page(
         &mut sim,
-        origin,
+        device,
+        content,
         debug,
+        Gray8::BLACK,
+        Gray8::WHITE,
         &fonts,
         default_style.clone(),
         &header,
         &page,
-    );
+    )?;
 
     let output_settings = OutputSettingsBuilder::new().build();
     let mut window = Window::new("edf", &output_settings);
@@ -82,27 +75,33 @@ pub fn show(args: ShowArgs) -> Result<(), Box<dyn Error>> {
         for event in window.events() {
             match event {
                 SimulatorEvent::Quit => break 'main,
-                SimulatorEvent::KeyUp { keycode, .. } => {
-                    match keycode {
-                        Keycode::D => page_num = cmp::min(page_num + 1, trailer.pages.len()),
-                        Keycode::A => page_num = cmp::max(1, page_num - 1),
-                        Keycode::S => debug = !debug,
-                        _ => continue,
+                SimulatorEvent::KeyUp {
+                    keycode, keymod, ..
+                } => {
+                    if keycode == Keycode::S {
+                        debug = !debug;
+                    } else {
+                        let shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+                        match navigation_key(keycode, shift) {
+                            Some(key) if nav.key(key) => {}
+                            _ => continue,
+                        }
                     }
 
-                    let _ = sim.clear(Gray8::WHITE);
-
-                    let offset = trailer.pages[page_num - 1];
+                    let offset = trailer.pages[nav.page_num() - 1];
                     let page = edf::read::page(&header, &bytes[offset as usize..])?;
                     display::page(
                         &mut sim,
-                        origin,
+                        device,
+                        content,
                         debug,
+                        Gray8::BLACK,
+                        Gray8::WHITE,
                         &fonts,
                         default_style.clone(),
                         &header,
                         &page,
-                    );
+                    )?;
                 }
                 _ => {}
             }
@@ -111,3 +110,32 @@ pub fn show(args: ShowArgs) -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+/// Maps a key press to page navigation:
+///
+/// - D/A move to the next/previous page
+/// - Home or g moves to the first page, End or G to the last
+/// - a page number followed by Return jumps to that page; Escape cancels the entry
+fn navigation_key(keycode: Keycode, shift: bool) -> Option<NavigationKey> {
+    let key = match keycode {
+        Keycode::Num0 | Keycode::Kp0 => NavigationKey::Digit(0),
+        Keycode::Num1 | Keycode::Kp1 => NavigationKey::Digit(1),
+        Keycode::Num2 | Keycode::Kp2 => NavigationKey::Digit(2),
+        Keycode::Num3 | Keycode::Kp3 => NavigationKey::Digit(3),
+        Keycode::Num4 | Keycode::Kp4 => NavigationKey::Digit(4),
+        Keycode::Num5 | Keycode::Kp5 => NavigationKey::Digit(5),
+        Keycode::Num6 | Keycode::Kp6 => NavigationKey::Digit(6),
+        Keycode::Num7 | Keycode::Kp7 => NavigationKey::Digit(7),
+        Keycode::Num8 | Keycode::Kp8 => NavigationKey::Digit(8),
+        Keycode::Num9 | Keycode::Kp9 => NavigationKey::Digit(9),
+        Keycode::D => NavigationKey::Next,
+        Keycode::A => NavigationKey::Previous,
+        Keycode::Home => NavigationKey::First,
+        Keycode::G if !shift => NavigationKey::First,
+        Keycode::End | Keycode::G => NavigationKey::Last,
+        Keycode::Return | Keycode::KpEnter => NavigationKey::Enter,
+        Keycode::Escape => NavigationKey::Cancel,
+        _ => return None,
+    };
+    Some(key)
+}
@@ -0,0 +1,70 @@
+use crate::{io::Input, SplitArgs};
+use edf::Command;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Cursor, Read, Write};
+use std::ops::RangeInclusive;
+
+/// Parses a 1-based, inclusive page range such as `3` or `1-10`.
+fn page_range(pages: &str) -> Result<RangeInclusive<usize>, Box<dyn Error>> {
+    let (first, last) = match pages.split_once('-') {
+        None => (pages, pages),
+        Some(range) => range,
+    };
+    let (first, last): (usize, usize) = (first.trim().parse()?, last.trim().parse()?);
+    if first == 0 || last < first {
+        return Err(format!("invalid page range {pages:?}").into());
+    }
+    Ok(first..=last)
+}
+
+pub fn split(args: SplitArgs) -> Result<(), Box<dyn Error>> {
+    let range = page_range(&args.pages)?;
+
+    let mut input = match args.input_path {
+        None => Input::Stdin(io::stdin()),
+        Some(path) => Input::File(File::open(path)?),
+    };
+
+    let mut bytes = Vec::new();
+    input.read_to_end(&mut bytes)?;
+
+    let mut cursor = Cursor::new(&bytes);
+
+    let header = edf::read::header(&mut cursor)?;
+    edf::read::seek_trailer(&mut cursor)?;
+    let trailer = edf::read::trailer(&header, &mut cursor)?;
+    edf::read::validate(&header, &trailer, bytes.len())?;
+
+    let offsets = match trailer.pages.get(range.start() - 1..*range.end()) {
+        None => {
+            return Err(format!(
+                "pages {}-{} are out of range (1-{})",
+                range.start(),
+                range.end(),
+                trailer.pages.len()
+            )
+            .into())
+        }
+        Some(offsets) => offsets,
+    };
+
+    // Each page ends with a page break, except the last, which is ended by the writer.
+    let mut commands = Vec::new();
+    for (i, offset) in offsets.iter().enumerate() {
+        if i > 0 {
+            commands.push(Command::PageBreak);
+        }
+        let page = edf::read::page(&header, &bytes[*offset as usize..])?;
+        commands.extend(
+            page.into_iter()
+                .take_while(|c| !matches!(c, Command::PageBreak | Command::End)),
+        );
+    }
+
+    let mut w = BufWriter::new(File::create(&args.output_path)?);
+    edf::write::doc(&mut w, header.version, &header, &trailer.urls, &commands)?;
+    w.flush()?;
+
+    Ok(())
+}
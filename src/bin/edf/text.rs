@@ -0,0 +1,52 @@
+use crate::{io::Input, TextArgs};
+use edf::Command;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Cursor, Read, Write};
+
+pub fn text(args: TextArgs) -> Result<(), Box<dyn Error>> {
+    let mut input = match args.input_path {
+        None => Input::Stdin(io::stdin()),
+        Some(path) => Input::File(File::open(path)?),
+    };
+
+    let mut bytes = Vec::new();
+    input.read_to_end(&mut bytes)?;
+
+    let mut cursor = Cursor::new(&bytes);
+
+    let header = edf::read::header(&mut cursor)?;
+    edf::read::seek_trailer(&mut cursor)?;
+    let trailer = edf::read::trailer(&header, &mut cursor)?;
+    edf::read::validate(&header, &trailer, bytes.len())?;
+
+    let offsets = match args.page_num {
+        None => &trailer.pages[..],
+        Some(num) => match trailer.pages.get((num as usize).wrapping_sub(1)) {
+            None => return Err(format!("page {num} is out of range").into()),
+            Some(offset) => core::slice::from_ref(offset),
+        },
+    };
+
+    let mut out = BufWriter::new(io::stdout().lock());
+    for offset in offsets {
+        for command in edf::read::page_iter(&header, &bytes[*offset as usize..]) {
+            match command? {
+                Command::Show { str } => write!(out, "{str}")?,
+                Command::ShowPositioned { glyphs } => {
+                    for (c, _) in glyphs {
+                        write!(out, "{c}")?;
+                    }
+                }
+                Command::HTab => write!(out, " ")?,
+                Command::LineBreak => writeln!(out)?,
+                Command::PageBreak => write!(out, "\n\x0c")?,
+                Command::End => writeln!(out)?,
+                _ => {}
+            }
+        }
+    }
+    out.flush()?;
+
+    Ok(())
+}
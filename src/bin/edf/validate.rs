@@ -0,0 +1,63 @@
+use crate::{io::Input, ValidateArgs};
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, Cursor, Read};
+
+/// Returns an error describing a problem at `offset` within the file.
+fn problem(offset: usize, message: impl std::fmt::Display) -> Box<dyn Error> {
+    format!("{offset:08x}: {message}").into()
+}
+
+pub fn validate(args: ValidateArgs) -> Result<(), Box<dyn Error>> {
+    let mut input = match args.input_path {
+        None => Input::Stdin(io::stdin()),
+        Some(path) => Input::File(File::open(path)?),
+    };
+
+    let mut bytes = Vec::new();
+    input.read_to_end(&mut bytes)?;
+
+    let mut cursor = Cursor::new(&bytes);
+
+    let header = edf::read::header(&mut cursor).map_err(|err| problem(0, err))?;
+    let header_end = cursor.position() as usize;
+    let trailer_start = match edf::read::seek_trailer(&mut cursor) {
+        Ok(start) => start as usize,
+        Err(err) => return Err(problem(bytes.len().saturating_sub(4), err)),
+    };
+    let trailer =
+        edf::read::trailer(&header, &mut cursor).map_err(|err| problem(trailer_start, err))?;
+
+    // Pages lie between the header and the trailer, in order.
+    let mut prev = None;
+    for (num, offset) in trailer.pages.iter().enumerate() {
+        let offset = *offset as usize;
+        if offset < header_end || offset >= trailer_start {
+            return Err(problem(
+                offset,
+                format!("page {} starts outside of the page data", num + 1),
+            ));
+        }
+        if prev.is_some_and(|prev| offset <= prev) {
+            return Err(problem(
+                offset,
+                format!("page {} does not follow the previous page", num + 1),
+            ));
+        }
+        prev = Some(offset);
+    }
+
+    for (num, offset) in trailer.pages.iter().enumerate() {
+        let offset = *offset as usize;
+        let end = match trailer.pages.get(num + 1) {
+            Some(next) => *next as usize,
+            None => trailer_start,
+        };
+        if let Err((at, err)) = edf::read::check_page(&header, &bytes[offset..end]) {
+            return Err(problem(offset + at, format!("page {}: {err}", num + 1)));
+        }
+    }
+
+    println!("OK: {} pages", trailer.pages.len());
+    Ok(())
+}
@@ -1,5 +1,15 @@
 mod fonts;
+mod highlight;
+mod image;
+mod navigator;
 mod page;
+#[cfg(test)]
+mod testing;
+mod trace;
 
 pub use fonts::*;
-pub use page::page;
+pub use highlight::highlight;
+pub use image::draw_image;
+pub use navigator::{NavigationKey, Navigator};
+pub use page::{page, page_with_gamma};
+pub use trace::{trace, PageSink, Trace, TraceEvent};
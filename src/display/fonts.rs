@@ -8,8 +8,8 @@ use embedded_graphics::{
         renderer::{TextMetrics, TextRenderer},
         Baseline,
     },
-    Pixel,
 };
+#[cfg(feature = "font_db")]
 use zeno::Placement;
 
 pub trait Color: PixelColor {
@@ -66,21 +66,66 @@ pub trait Fonts {
 
 pub trait FontStyle: crate::fonts::FontStyle {
     fn glyph_advance(&self, code_point: char) -> i32;
+
+    /// Draws a glyph. Antialiased glyphs apply `gamma` to their coverage before blending `color`
+    /// over `over`; see [`gamma_correct`].
     fn draw_glyph<C: Color, Draw: DrawTarget<Color = C>>(
         &self,
         draw: &mut Draw,
         origin: Point,
         color: C,
         over: C,
+        gamma: f32,
         code_point: char,
     ) -> Result<Point, Draw::Error>;
+
+    /// Draws a glyph `phase / SUBPIXEL_PHASES` of a pixel to the right of `origin`, returning the
+    /// same point as [`draw_glyph`](FontStyle::draw_glyph). Fonts that cannot position glyphs
+    /// between pixels draw the glyph at `origin`.
+    #[allow(clippy::too_many_arguments)]
+    fn draw_glyph_subpixel<C: Color, Draw: DrawTarget<Color = C>>(
+        &self,
+        draw: &mut Draw,
+        origin: Point,
+        _phase: u8,
+        color: C,
+        over: C,
+        gamma: f32,
+        code_point: char,
+    ) -> Result<Point, Draw::Error> {
+        self.draw_glyph(draw, origin, color, over, gamma, code_point)
+    }
 }
 
+/// Applies gamma correction to a glyph's coverage: `alpha' = (alpha / 255)^(1 / gamma) * 255`.
+/// Gammas above 1.0 darken partially covered pixels, which thickens text on low-contrast displays
+/// such as e-ink.
+///
+/// Without the `std` or `libm` features, coverage is returned unchanged.
+pub fn gamma_correct(alpha: u8, gamma: f32) -> u8 {
+    if gamma == 1.0 {
+        return alpha;
+    }
+
+    let coverage = alpha as f32 / 255.0;
+    #[cfg(feature = "std")]
+    let corrected = coverage.powf(1.0 / gamma);
+    #[cfg(all(not(feature = "std"), feature = "libm"))]
+    let corrected = libm::powf(coverage, 1.0 / gamma);
+    #[cfg(not(any(feature = "std", feature = "libm")))]
+    let corrected = coverage;
+
+    (corrected * 255.0 + 0.5) as u8
+}
+
+/// Draws a glyph rasterized by `zeno`.
+#[cfg(feature = "font_db")]
 pub fn draw_glyph<C: Color, Draw: DrawTarget<Color = C>>(
     draw: &mut Draw,
     origin: Point,
     color: C,
     over: C,
+    gamma: f32,
     placement: Placement,
     mut data: &[u8],
 ) -> Result<Point, Draw::Error> {
@@ -90,9 +135,9 @@ pub fn draw_glyph<C: Color, Draw: DrawTarget<Color = C>>(
         let row = &data[..(placement.width as usize)];
 
         let pixels = row.iter().enumerate().map(|(x, alpha)| {
-            Pixel(
+            embedded_graphics::Pixel(
                 Point::new(glyph_origin.x + x as i32, glyph_origin.y - y as i32),
-                color.blend(*alpha, over),
+                color.blend(gamma_correct(*alpha, gamma), over),
             )
         });
 
@@ -112,6 +157,9 @@ pub struct CharacterStyle<S, C> {
     pub whitespace_px: i32,
     pub color: C,
     pub over: C,
+    /// The gamma applied to glyph coverage before blending. Defaults to 1.0, which leaves
+    /// coverage unchanged.
+    pub gamma: f32,
 }
 
 impl<S: FontStyle, C> CharacterStyle<S, C> {
@@ -122,6 +170,21 @@ impl<S: FontStyle, C> CharacterStyle<S, C> {
             whitespace_px: whitespace_px.into(),
             color,
             over,
+            gamma: 1.0,
+        }
+    }
+
+    /// Returns the vertical distance from a position with the given baseline to the alphabetic
+    /// baseline, where glyphs are drawn. The style's `baseline` is the distance from the bottom of
+    /// a line to its alphabetic baseline.
+    fn baseline_offset(&self, baseline: Baseline) -> i32 {
+        let line_height = self.style.line_height() as i32;
+        let alphabetic = line_height - self.style.baseline() as i32;
+        match baseline {
+            Baseline::Top => alphabetic,
+            Baseline::Middle => alphabetic - line_height / 2,
+            Baseline::Bottom => alphabetic - line_height,
+            Baseline::Alphabetic => 0,
         }
     }
 }
@@ -133,12 +196,10 @@ impl<S: FontStyle, C: Color> TextRenderer for &CharacterStyle<S, C> {
         &self,
         text: &str,
         position: Point,
-        _baseline: Baseline,
+        baseline: Baseline,
         target: &mut D,
     ) -> Result<Point, D::Error> {
-        let mut origin = position;
-
-        // TODO: baseline
+        let mut origin = position + Point::new(0, self.baseline_offset(baseline));
 
         for c in text.chars() {
             if c.is_whitespace() {
@@ -146,11 +207,11 @@ impl<S: FontStyle, C: Color> TextRenderer for &CharacterStyle<S, C> {
             } else {
                 origin = self
                     .style
-                    .draw_glyph(target, origin, self.color, self.over, c)?;
+                    .draw_glyph(target, origin, self.color, self.over, self.gamma, c)?;
             }
         }
 
-        Ok(origin)
+        Ok(Point::new(origin.x, position.y))
     }
 
     fn draw_whitespace<D: DrawTarget<Color = C>>(
@@ -163,11 +224,9 @@ impl<S: FontStyle, C: Color> TextRenderer for &CharacterStyle<S, C> {
         Ok(position + Point::new(width as i32, 0))
     }
 
-    fn measure_string(&self, text: &str, position: Point, _baseline: Baseline) -> TextMetrics {
+    fn measure_string(&self, text: &str, position: Point, baseline: Baseline) -> TextMetrics {
         let mut origin = position;
 
-        // TODO: baseline
-
         for c in text.chars() {
             if c.is_whitespace() {
                 origin.x += self.whitespace_px;
@@ -176,8 +235,11 @@ impl<S: FontStyle, C: Color> TextRenderer for &CharacterStyle<S, C> {
             }
         }
 
+        // The bounding box spans the line, whose top is above the alphabetic baseline.
+        let alphabetic = self.style.line_height() as i32 - self.style.baseline() as i32;
+        let top = position.y + self.baseline_offset(baseline) - alphabetic;
         let bounding_box = Rectangle::new(
-            position,
+            Point::new(position.x, top),
             Size::new(
                 (origin.x - position.x) as u32,
                 self.style.line_height() as u32,
@@ -193,3 +255,44 @@ impl<S: FontStyle, C: Color> TextRenderer for &CharacterStyle<S, C> {
         self.style.line_height() as u32
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::testing::{Canvas, TestStyle};
+    use alloc::vec::Vec;
+    use embedded_graphics::pixelcolor::Gray8;
+
+    #[test]
+    fn baselines_offset_text() {
+        let style = CharacterStyle::new(TestStyle, Gray8::BLACK, Gray8::WHITE);
+        let position = Point::new(0, 20);
+        let baselines = [
+            Baseline::Top,
+            Baseline::Middle,
+            Baseline::Bottom,
+            Baseline::Alphabetic,
+        ];
+
+        // Returns the top row of the text drawn at the given baseline.
+        let top = |baseline| {
+            let mut canvas = Canvas::new(40, 40);
+            (&style)
+                .draw_string("ab", position, baseline, &mut canvas)
+                .unwrap();
+            (0..40)
+                .find(|y| (0..40).any(|x| canvas.get(Point::new(x, *y)) != Gray8::WHITE))
+                .unwrap()
+        };
+        let tops: Vec<i32> = baselines.iter().map(|b| top(*b)).collect();
+
+        // The test font's glyphs are 6px tall and sit 12px below the top of its 15px line.
+        assert_eq!(tops, [26, 19, 11, 14]);
+
+        // Measurements agree with drawing: the line's top is 12px above the alphabetic baseline.
+        for (baseline, top) in baselines.iter().zip(tops) {
+            let metrics = (&style).measure_string("ab", position, *baseline);
+            assert_eq!(metrics.bounding_box.top_left.y, top + 6 - 12);
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use crate::{
+    display::{page::walk, FontStyle, Fonts, PageSink, TraceEvent},
+    Command, Header,
+};
+use alloc::vec::Vec;
+use core::{convert::Infallible, ops::Range};
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::Point,
+    pixelcolor::{PixelColor, Rgb888},
+    primitives::{rectangle::Rectangle, PointsIter},
+    Pixel,
+};
+
+/// A sink that discards a page's events. Walking a page into it measures the page without drawing
+/// it.
+struct Measure;
+
+impl<S: FontStyle, C> PageSink<S, C> for Measure {
+    type Error = Infallible;
+
+    fn event(&mut self, _: Point, _: TraceEvent, _: &S, _: C) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+/// Highlights ranges of a page's text, such as a selection or search results, by drawing a
+/// translucent overlay in `color` over the characters in each range. The page must already have
+/// been drawn to `draw` at `origin` using the same fonts.
+///
+/// Ranges index the characters shown by the page's `Show` and `ShowPositioned` commands, in
+/// order. As the draw target cannot be read back, translucency is approximated by filling every
+/// other pixel of each highlighted area.
+#[allow(clippy::too_many_arguments)]
+pub fn highlight<Draw, C, S, F, T>(
+    draw: &mut Draw,
+    origin: Point,
+    fonts: F,
+    default_style: S,
+    header: &Header,
+    page: &[Command<T>],
+    ranges: &[Range<usize>],
+    color: C,
+) -> Result<(), Draw::Error>
+where
+    Draw: DrawTarget<Color = C>,
+    C: PixelColor + From<Rgb888>,
+    S: FontStyle,
+    F: Fonts<Style = S>,
+    T: AsRef<str> + core::fmt::Debug + Clone,
+{
+    // Collect the bounds of the highlighted characters, merging neighbors on the same line.
+    let mut areas: Vec<Rectangle> = Vec::new();
+    let on_char = |index: usize, bounds: Rectangle| {
+        if !ranges.iter().any(|r| r.contains(&index)) {
+            return;
+        }
+        if let Some(last) = areas.last_mut() {
+            let end = last.top_left.x + last.size.width as i32;
+            if last.top_left.y == bounds.top_left.y && end == bounds.top_left.x {
+                last.size.width += bounds.size.width;
+                last.size.height = last.size.height.max(bounds.size.height);
+                return;
+            }
+        }
+        areas.push(bounds);
+    };
+    let measured = walk(
+        &mut Measure,
+        origin,
+        draw.bounding_box(),
+        false,
+        color,
+        fonts,
+        default_style,
+        header,
+        page,
+        on_char,
+    );
+    match measured {
+        Ok(()) => {}
+        Err(never) => match never {},
+    }
+
+    for area in areas {
+        let pixels = area
+            .points()
+            .filter(|p| (p.x + p.y).rem_euclid(2) == 0)
+            .map(|p| Pixel(p, color));
+        draw.draw_iter(pixels)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::testing::{header, Canvas, TestFonts, TestStyle, ADVANCE};
+    use embedded_graphics::{
+        geometry::Size,
+        pixelcolor::{Gray8, GrayColor},
+    };
+
+    #[test]
+    fn highlights_cover_their_characters() {
+        let commands = [
+            Command::SetLineMetrics {
+                height: 15,
+                baseline: 3,
+            },
+            Command::Show { str: "ab cd ef" },
+        ];
+        let ranges = [Range { start: 3, end: 5 }];
+        let shade = Gray8::new(128);
+        let mut canvas = Canvas::new(60, 20);
+        highlight(
+            &mut canvas,
+            Point::zero(),
+            TestFonts,
+            TestStyle,
+            &header(),
+            &commands,
+            &ranges,
+            shade,
+        )
+        .unwrap();
+
+        // "cd" follows two glyphs and a space a third of an em wide, and spans the whole line.
+        let word = Rectangle::new(
+            Point::new(2 * ADVANCE + 4, 0),
+            Size::new(2 * ADVANCE as u32, 15),
+        );
+        assert_eq!(canvas.ink(shade), Some(word));
+        assert!(canvas
+            .pixels
+            .iter()
+            .all(|p| *p == shade || *p == Gray8::WHITE));
+    }
+}
@@ -0,0 +1,79 @@
+use crate::{Image, ImageFormat};
+use alloc::{borrow::Cow, vec::Vec};
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::Point,
+    pixelcolor::{Gray8, PixelColor},
+    Pixel,
+};
+
+/// Decodes an image into 8-bit grayscale pixels, returning the pixels and the image's width and
+/// height.
+fn decode<'a>(image: &Image<'a>, width: u32, height: u32) -> Option<(Cow<'a, [u8]>, u32, u32)> {
+    match image.format {
+        ImageFormat::Gray8 => {
+            if image.data.len() < (width * height) as usize {
+                return None;
+            }
+            Some((Cow::Borrowed(image.data), width, height))
+        }
+        #[cfg(feature = "std")]
+        ImageFormat::Png => decode_png(image.data).map(|(data, w, h)| (Cow::Owned(data), w, h)),
+        #[cfg(not(feature = "std"))]
+        ImageFormat::Png => None,
+    }
+}
+
+#[cfg(feature = "std")]
+fn decode_png(data: &[u8]) -> Option<(Vec<u8>, u32, u32)> {
+    let mut decoder = png::Decoder::new(data);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info().ok()?;
+    let mut buf = alloc::vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).ok()?;
+    let buf = &buf[..info.buffer_size()];
+
+    let channels = info.color_type.samples();
+    let pixels = buf
+        .chunks_exact(channels)
+        .map(|p| match p.len() {
+            1 | 2 => p[0],
+            _ => ((p[0] as u16 * 77 + p[1] as u16 * 150 + p[2] as u16 * 29) >> 8) as u8,
+        })
+        .collect::<Vec<_>>();
+    Some((pixels, info.width, info.height))
+}
+
+/// Draws an image with its top-left corner at `origin`, scaled to the given size. Images are
+/// grayscale, and are converted to the target's color type.
+pub fn draw_image<C: PixelColor + From<Gray8>, Draw: DrawTarget<Color = C>>(
+    draw: &mut Draw,
+    origin: Point,
+    width: u16,
+    height: u16,
+    image: &Image,
+) -> Result<(), Draw::Error> {
+    let (width, height) = (width as u32, height as u32);
+    let (pixels, image_width, image_height) = match decode(image, width, height) {
+        None => return Ok(()),
+        Some(decoded) => decoded,
+    };
+    if image_width == 0 || image_height == 0 {
+        return Ok(());
+    }
+
+    // Nearest-neighbor scaling.
+    let pixels: &[u8] = &pixels;
+    let pixels = (0..height).flat_map(move |y| {
+        let sy = y * image_height / height;
+        (0..width).map(move |x| {
+            let sx = x * image_width / width;
+            let luma = pixels[(sy * image_width + sx) as usize];
+            Pixel(
+                origin + Point::new(x as i32, y as i32),
+                C::from(Gray8::new(luma)),
+            )
+        })
+    });
+    draw.draw_iter(pixels)
+}
@@ -0,0 +1,111 @@
+/// A key that moves between the pages of a document.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NavigationKey {
+    /// Move to the next page.
+    Next,
+    /// Move to the previous page.
+    Previous,
+    /// Move to the first page.
+    First,
+    /// Move to the last page.
+    Last,
+    /// Append a digit to the page number being entered.
+    Digit(u8),
+    /// Jump to the page number that was entered.
+    Enter,
+    /// Discard the page number being entered.
+    Cancel,
+}
+
+/// Tracks the current page of a document as keys are pressed. Pages are numbered from 1.
+#[derive(Clone, Debug)]
+pub struct Navigator {
+    page_num: usize,
+    page_count: usize,
+    entry: Option<usize>,
+}
+
+impl Navigator {
+    pub fn new(page_num: usize, page_count: usize) -> Self {
+        Navigator {
+            page_num,
+            page_count,
+            entry: None,
+        }
+    }
+
+    /// The current page number.
+    pub fn page_num(&self) -> usize {
+        self.page_num
+    }
+
+    /// Handles a key press, returning true if the current page changed. Jumps past either end
+    /// of the document stop at that end. A document without pages never changes page.
+    pub fn key(&mut self, key: NavigationKey) -> bool {
+        let page_num = match key {
+            NavigationKey::Digit(digit) => {
+                let entry = self.entry.unwrap_or(0);
+                self.entry = Some(entry.saturating_mul(10).saturating_add(digit as usize));
+                return false;
+            }
+            NavigationKey::Next => self.page_num.saturating_add(1),
+            NavigationKey::Previous => self.page_num.saturating_sub(1),
+            NavigationKey::First => 1,
+            NavigationKey::Last => self.page_count,
+            NavigationKey::Enter => match self.entry.take() {
+                None => return false,
+                Some(n) => n,
+            },
+            NavigationKey::Cancel => {
+                self.entry = None;
+                return false;
+            }
+        };
+        if self.page_count == 0 {
+            return false;
+        }
+
+        let page_num = page_num.clamp(1, self.page_count);
+        let changed = page_num != self.page_num;
+        self.page_num = page_num;
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn last_moves_to_the_final_page() {
+        let mut nav = Navigator::new(2, 7);
+        assert!(nav.key(NavigationKey::Last));
+        assert_eq!(nav.page_num(), 7);
+        assert!(!nav.key(NavigationKey::Last));
+        assert!(!nav.key(NavigationKey::Next));
+        assert_eq!(nav.page_num(), 7);
+    }
+
+    #[test]
+    fn entered_page_numbers_are_clamped() {
+        let mut nav = Navigator::new(1, 7);
+        assert!(!nav.key(NavigationKey::Digit(4)));
+        assert!(!nav.key(NavigationKey::Digit(2)));
+        assert!(nav.key(NavigationKey::Enter));
+        assert_eq!(nav.page_num(), 7);
+    }
+
+    #[test]
+    fn empty_documents_never_change_page() {
+        let mut nav = Navigator::new(1, 0);
+        for key in [
+            NavigationKey::Next,
+            NavigationKey::Previous,
+            NavigationKey::First,
+            NavigationKey::Last,
+        ] {
+            assert!(!nav.key(key));
+        }
+        assert_eq!(nav.page_num(), 1);
+    }
+}
@@ -1,30 +1,244 @@
 use crate::{
-    display::{CharacterStyle, FontStyle, Fonts},
+    display::{draw_image, Color, FontStyle, Fonts, PageSink, TraceEvent},
+    fonts::SUBPIXEL_PHASES,
     Command, Header,
 };
 use core::convert::AsRef;
 use embedded_graphics::{
     draw_target::DrawTarget,
-    geometry::Point,
-    pixelcolor::{Gray8, GrayColor},
-    primitives::{triangle::Triangle, Primitive, PrimitiveStyle},
-    text::Text,
-    Drawable,
+    geometry::{Dimensions, Point, Size},
+    pixelcolor::{Gray8, PixelColor, Rgb888},
+    primitives::{
+        line::Line, rectangle::Rectangle, triangle::Triangle, ContainsPoint, Primitive,
+        PrimitiveStyle,
+    },
+    Drawable, Pixel,
 };
 
-pub fn page<Draw, S, F, T>(
+/// Draws a page. The device's bounds are first filled with `background`, and the page is then
+/// drawn with its origin at the top-left corner of `content`. Content is drawn in `foreground`
+/// until the page sets its own color, and antialiased glyphs are blended over `background`.
+///
+/// Anything drawn outside of `content` is clipped. Returns true if any content was clipped.
+#[allow(clippy::too_many_arguments)]
+pub fn page<Draw, C, S, F, T>(
     draw: &mut Draw,
+    device: Rectangle,
+    content: Rectangle,
+    debug: bool,
+    foreground: C,
+    background: C,
+    fonts: F,
+    default_style: S,
+    header: &Header,
+    page: &[Command<T>],
+) -> Result<bool, Draw::Error>
+where
+    Draw: DrawTarget<Color = C>,
+    C: Color + From<Gray8> + From<Rgb888>,
+    S: FontStyle,
+    F: Fonts<Style = S>,
+    T: AsRef<str> + core::fmt::Debug + Clone,
+{
+    page_with_gamma(
+        draw,
+        device,
+        content,
+        debug,
+        foreground,
+        background,
+        fonts,
+        default_style,
+        header,
+        page,
+        1.0,
+    )
+}
+
+/// Draws a page like [`page`], applying `gamma` to the coverage of antialiased glyphs. See
+/// [`gamma_correct`](crate::display::gamma_correct).
+#[allow(clippy::too_many_arguments)]
+pub fn page_with_gamma<Draw, C, S, F, T>(
+    draw: &mut Draw,
+    device: Rectangle,
+    content: Rectangle,
+    debug: bool,
+    foreground: C,
+    background: C,
+    fonts: F,
+    default_style: S,
+    header: &Header,
+    page: &[Command<T>],
+    gamma: f32,
+) -> Result<bool, Draw::Error>
+where
+    Draw: DrawTarget<Color = C>,
+    C: Color + From<Gray8> + From<Rgb888>,
+    S: FontStyle,
+    F: Fonts<Style = S>,
+    T: AsRef<str> + core::fmt::Debug + Clone,
+{
+    draw.fill_solid(&device, background)?;
+
+    let mut sink = Drawing {
+        target: Clip {
+            draw,
+            area: content,
+            clipped: false,
+        },
+        header,
+        foreground,
+        background,
+        gamma,
+    };
+    walk(
+        &mut sink,
+        content.top_left,
+        device,
+        debug,
+        foreground,
+        fonts,
+        default_style,
+        header,
+        page,
+        |_, _| {},
+    )?;
+    Ok(sink.target.clipped)
+}
+
+/// A draw target that discards pixels outside of `area`, recording whether any were discarded.
+struct Clip<'a, Draw> {
+    draw: &'a mut Draw,
+    area: Rectangle,
+    clipped: bool,
+}
+
+impl<Draw: DrawTarget> Dimensions for Clip<'_, Draw> {
+    fn bounding_box(&self) -> Rectangle {
+        self.area
+    }
+}
+
+impl<Draw: DrawTarget> DrawTarget for Clip<'_, Draw> {
+    type Color = Draw::Color;
+    type Error = Draw::Error;
+
+    fn draw_iter<I: IntoIterator<Item = Pixel<Draw::Color>>>(
+        &mut self,
+        pixels: I,
+    ) -> Result<(), Draw::Error> {
+        let area = self.area;
+        let clipped = &mut self.clipped;
+        let pixels = pixels.into_iter().filter(|Pixel(p, _)| {
+            let inside = area.contains(*p);
+            *clipped |= !inside;
+            inside
+        });
+        self.draw.draw_iter(pixels)
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Draw::Color) -> Result<(), Draw::Error> {
+        let visible = area.intersection(&self.area);
+        self.clipped |= visible != *area;
+        self.draw.fill_solid(&visible, color)
+    }
+}
+
+/// A sink that draws the events of a page to a draw target.
+struct Drawing<'a, Draw, C> {
+    /// The target, clipped to the page's content.
+    target: Clip<'a, Draw>,
+    /// The header of the page's document, for its images.
+    header: &'a Header,
+    /// The color of the debug markers.
+    foreground: C,
+    /// The color antialiased glyphs are blended over.
+    background: C,
+    /// The gamma applied to glyph coverage.
+    gamma: f32,
+}
+
+impl<S, Draw, C> PageSink<S, C> for Drawing<'_, Draw, C>
+where
+    S: FontStyle,
+    Draw: DrawTarget<Color = C>,
+    C: Color + From<Gray8>,
+{
+    type Error = Draw::Error;
+
+    fn event(
+        &mut self,
+        at: Point,
+        event: TraceEvent,
+        style: &S,
+        color: C,
+    ) -> Result<(), Draw::Error> {
+        match event {
+            TraceEvent::CommandAt => {
+                Triangle::new(at, at + Point::new(-3, -7), at + Point::new(3, -7))
+                    .into_styled(PrimitiveStyle::with_stroke(self.foreground, 1))
+                    .draw(self.target.draw)?;
+            }
+            TraceEvent::GlyphAt { c, phase } => {
+                style.draw_glyph_subpixel(
+                    &mut self.target,
+                    at,
+                    phase,
+                    color,
+                    self.background,
+                    self.gamma,
+                    c,
+                )?;
+            }
+            TraceEvent::FillRectAt { size } => {
+                Rectangle::new(at, size)
+                    .into_styled(PrimitiveStyle::with_fill(color))
+                    .draw(&mut self.target)?;
+            }
+            TraceEvent::ImageAt {
+                width,
+                height,
+                data_offset,
+            } => {
+                if let Some(image) = self.header.images.get(data_offset) {
+                    draw_image(&mut self.target, at, width, height, &image)?;
+                }
+            }
+            TraceEvent::DecorationAt { to } => {
+                Line::new(at, to)
+                    .into_styled(PrimitiveStyle::with_stroke(color, 1))
+                    .draw(&mut self.target)?;
+            }
+            TraceEvent::WhitespaceAt { .. } | TraceEvent::LineBreakAt => {}
+        }
+        Ok(())
+    }
+}
+
+/// Walks a page, sending its events to `sink` and calling `on_char` with the index and bounds of
+/// each character it shows. Indices count the characters of the page's `Show` and
+/// `ShowPositioned` commands in order, and bounds span the character's advance and its line's
+/// height. `MoveBy` commands keep the cursor within `bounds`.
+#[allow(clippy::too_many_arguments)]
+pub(super) fn walk<K, C, S, F, T, O>(
+    sink: &mut K,
     origin: Point,
+    bounds: Rectangle,
     debug: bool,
+    foreground: C,
     fonts: F,
     default_style: S,
     header: &Header,
     page: &[Command<T>],
-) where
-    Draw: DrawTarget<Color = Gray8>,
+    mut on_char: O,
+) -> Result<(), K::Error>
+where
+    K: PageSink<S, C>,
+    C: PixelColor + From<Rgb888>,
     S: FontStyle,
     F: Fonts<Style = S>,
     T: AsRef<str> + core::fmt::Debug + Clone,
+    O: FnMut(usize, Rectangle),
 {
     let mut style = default_style.clone();
 
@@ -36,31 +250,55 @@ pub fn page<Draw, S, F, T>(
     let mut line_baseline = style.baseline() as i32;
     let mut baseline_offset = 0;
     let mut cursor = origin;
-    let mut whitespace_width = 0.0;
-    let mut whitespace_width_quantized = 0;
+    let mut whitespace_width = glue_width;
+    let mut whitespace_width_quantized = unsafe { whitespace_width.to_int_unchecked::<i32>() };
     let mut error = 0f32;
+    let mut color = foreground;
+    let mut underline = false;
+    let mut strikethrough = false;
+    let mut index = 0;
 
     for command in page {
         if debug {
-            let _ = Triangle::new(
-                cursor,
-                cursor + Point::new(-3, -7),
-                cursor + Point::new(3, -7),
-            )
-            .into_styled(PrimitiveStyle::with_stroke(Gray8::BLACK, 1))
-            .draw(draw);
+            sink.event(cursor, TraceEvent::CommandAt, &style, color)?;
         }
 
         match command {
             Command::LineBreak => {
                 error = 0.0;
                 cursor = Point::new(origin.x, cursor.y + line_height);
+
+                // Each line is unadjusted unless it sets its own adjustment ratio, so ragged lines
+                // such as preformatted text do not inherit the spacing of a justified line.
+                whitespace_width = glue_width;
+                whitespace_width_quantized = unsafe { whitespace_width.to_int_unchecked::<i32>() };
+
+                sink.event(cursor, TraceEvent::LineBreakAt, &style, color)?;
             }
             Command::PageBreak => {
-                return;
+                return Ok(());
+            }
+            Command::HTab => {
+                error = 0.0;
+                let tab_stop = style.tab_stop().max(1) as i32;
+                let x = (cursor.x - origin.x).div_euclid(tab_stop) + 1;
+                cursor.x = origin.x + x * tab_stop;
+            }
+            Command::VTab => {
+                let tab_stop = style.tab_stop().max(1) as i32;
+                let y = (cursor.y - origin.y).div_euclid(tab_stop) + 1;
+                cursor.y = origin.y + y * tab_stop;
             }
             Command::Advance { dx } => cursor += Point::new(*dx as i32, 0),
             Command::SetCursor { x, y } => cursor = origin + Point::new(*x as i32, *y as i32),
+            Command::MoveBy { dx, dy } => {
+                let min = bounds.top_left;
+                let max = bounds.bottom_right().unwrap_or(min);
+                cursor = Point::new(
+                    (cursor.x + *dx as i32).clamp(min.x, max.x),
+                    (cursor.y + *dy as i32).clamp(min.y, max.y),
+                );
+            }
             Command::SetAdjustmentRatio { r } => {
                 let r = *r;
                 whitespace_width = if r < 0.0 {
@@ -82,32 +320,68 @@ pub fn page<Draw, S, F, T>(
                     0
                 };
             }
+            Command::FillRect { w, h } => {
+                let size = Size::new(*w as u32, *h as u32);
+                sink.event(cursor, TraceEvent::FillRectAt { size }, &style, color)?;
+            }
+            Command::DrawImage {
+                width,
+                height,
+                data_offset,
+            } => {
+                if header.images.get(*data_offset).is_some() {
+                    let image = TraceEvent::ImageAt {
+                        width: *width,
+                        height: *height,
+                        data_offset: *data_offset,
+                    };
+                    sink.event(cursor, image, &style, color)?;
+                }
+                cursor += Point::new(*width as i32, 0);
+            }
+            Command::SetDecoration {
+                underline: u,
+                strikethrough: s,
+            } => {
+                underline = *u;
+                strikethrough = *s;
+            }
             Command::Show { str } => {
                 let mut text_cursor =
                     cursor + Point::new(0, line_height - line_baseline - baseline_offset);
-                let character_style = CharacterStyle {
-                    style: style.clone(),
-                    whitespace_px: whitespace_width_quantized,
-                    color: Gray8::BLACK,
-                    over: Gray8::WHITE,
-                };
+                let text_origin = text_cursor;
+                let mut previous = None;
                 for c in str.as_ref().chars() {
-                    let (next_cursor, expected_width, can_charge) = if c.is_whitespace() {
-                        (
-                            text_cursor + Point::new(whitespace_width_quantized, 0),
-                            whitespace_width,
-                            true,
-                        )
+                    let char_origin = text_cursor;
+                    if let Some(previous) = previous {
+                        text_cursor.x += style.kerning(previous, c);
+                    }
+                    previous = Some(c);
+
+                    // Non-breaking spaces are not adjusted.
+                    let non_breaking = matches!(c, '\u{a0}' | '\u{202f}');
+                    let (next_cursor, expected_width, can_charge) = if non_breaking {
+                        let width = glue_width as i32;
+                        let whitespace = TraceEvent::WhitespaceAt { c, width };
+                        sink.event(text_cursor, whitespace, &style, color)?;
+                        (text_cursor + Point::new(width, 0), glue_width, true)
+                    } else if c.is_whitespace() {
+                        let width = whitespace_width_quantized;
+                        let whitespace = TraceEvent::WhitespaceAt { c, width };
+                        sink.event(text_cursor, whitespace, &style, color)?;
+                        (text_cursor + Point::new(width, 0), whitespace_width, true)
                     } else {
-                        let mut buf = [0; 4];
-                        let next_cursor =
-                            match Text::new(c.encode_utf8(&mut buf), text_cursor, &character_style)
-                                .draw(draw)
-                            {
-                                Ok(point) => point,
-                                Err(_) => text_cursor,
-                            };
-                        (next_cursor, (next_cursor - text_cursor).x as f32, false)
+                        // Glyphs are drawn at the fraction of a pixel that glue has yet to
+                        // charge to the cursor.
+                        let phase = if (0.0..1.0).contains(&error) {
+                            (error * SUBPIXEL_PHASES as f32) as u8
+                        } else {
+                            0
+                        };
+                        let glyph = TraceEvent::GlyphAt { c, phase };
+                        sink.event(text_cursor, glyph, &style, color)?;
+                        let advance = style.glyph_advance(c);
+                        (text_cursor + Point::new(advance, 0), advance as f32, false)
                     };
 
                     error += expected_width - (next_cursor.x - text_cursor.x) as f32;
@@ -118,10 +392,49 @@ pub fn page<Draw, S, F, T>(
                     } else {
                         next_cursor
                     };
+
+                    on_char(
+                        index,
+                        char_bounds(char_origin, text_cursor, cursor, line_height),
+                    );
+                    index += 1;
                 }
 
+                let decorations = (underline, strikethrough);
+                draw_decorations(sink, &style, color, decorations, text_origin, text_cursor)?;
+
                 cursor = Point::new(text_cursor.x, cursor.y);
             }
+            Command::ShowPositioned { glyphs } => {
+                let text_origin =
+                    cursor + Point::new(0, line_height - line_baseline - baseline_offset);
+                let mut text_cursor = text_origin;
+                for (c, dx) in glyphs {
+                    let event = if c.is_whitespace() {
+                        TraceEvent::WhitespaceAt {
+                            c: *c,
+                            width: *dx as i32,
+                        }
+                    } else {
+                        TraceEvent::GlyphAt { c: *c, phase: 0 }
+                    };
+                    sink.event(text_cursor, event, &style, color)?;
+                    let char_origin = text_cursor;
+                    text_cursor += Point::new(*dx as i32, 0);
+
+                    on_char(
+                        index,
+                        char_bounds(char_origin, text_cursor, cursor, line_height),
+                    );
+                    index += 1;
+                }
+
+                let decorations = (underline, strikethrough);
+                draw_decorations(sink, &style, color, decorations, text_origin, text_cursor)?;
+
+                cursor = Point::new(text_cursor.x, cursor.y);
+            }
+            Command::SetColor { r, g, b } => color = C::from(Rgb888::new(*r, *g, *b)),
             Command::SetStyle { s } => {
                 style = match fonts.get_style(&header.styles[*s as usize]) {
                     Some(s) => s,
@@ -141,4 +454,169 @@ pub fn page<Draw, S, F, T>(
             _ => {}
         };
     }
+
+    Ok(())
+}
+
+/// Returns the bounds of a character that advanced the text cursor from `from` to `to` on the line
+/// that begins at `line`.
+fn char_bounds(from: Point, to: Point, line: Point, line_height: i32) -> Rectangle {
+    let width = (to.x - from.x).max(0) as u32;
+    Rectangle::new(
+        Point::new(from.x, line.y),
+        Size::new(width, line_height.max(0) as u32),
+    )
+}
+
+/// Sends the underline and strikethrough decorations for a run of text between `from` and `to`.
+fn draw_decorations<K, C, S>(
+    sink: &mut K,
+    style: &S,
+    color: C,
+    (underline, strikethrough): (bool, bool),
+    from: Point,
+    to: Point,
+) -> Result<(), K::Error>
+where
+    K: PageSink<S, C>,
+    C: Copy,
+    S: FontStyle,
+{
+    if underline {
+        let offset = Point::new(0, 1);
+        let to = to + offset;
+        sink.event(from + offset, TraceEvent::DecorationAt { to }, style, color)?;
+    }
+    if strikethrough {
+        let x_height = style.x_height().unwrap_or(style.em_px() / 2) as i32;
+        let offset = Point::new(0, -x_height / 2);
+        let to = to + offset;
+        sink.event(from + offset, TraceEvent::DecorationAt { to }, style, color)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::{
+        testing::{header, Canvas, TestFonts, TestStyle, ADVANCE},
+        Trace,
+    };
+    use alloc::vec;
+    use embedded_graphics::pixelcolor::GrayColor;
+
+    /// Draws `commands` to `canvas`, whose content area is the whole canvas.
+    fn draw(canvas: &mut Canvas, commands: &[Command<&str>]) -> Result<bool, Point> {
+        let bounds = Rectangle::new(Point::zero(), canvas.size);
+        page(
+            canvas,
+            bounds,
+            bounds,
+            false,
+            Gray8::BLACK,
+            Gray8::WHITE,
+            TestFonts,
+            TestStyle,
+            &header(),
+            commands,
+        )
+    }
+
+    #[test]
+    fn draw_errors_are_returned() {
+        let commands = [
+            Command::SetLineMetrics {
+                height: 15,
+                baseline: 3,
+            },
+            Command::Show { str: "ab" },
+        ];
+
+        // Glyphs sit on the baseline, 12px below the top of the line.
+        let mut canvas = Canvas::new(40, 20);
+        assert_eq!(draw(&mut canvas, &commands), Ok(false));
+        assert_eq!(canvas.get(Point::new(ADVANCE, 11)), Gray8::BLACK);
+
+        let mut canvas = Canvas::new(40, 20);
+        canvas.fail_at = Some(Point::new(ADVANCE, 11));
+        assert_eq!(draw(&mut canvas, &commands), Err(Point::new(ADVANCE, 11)));
+    }
+
+    #[test]
+    fn line_breaks_reset_adjustment() {
+        // A justified line followed by a preformatted line that does not set its own ratio.
+        let commands = [
+            Command::SetAdjustmentRatio { r: 1.0 },
+            Command::Show { str: "a b" },
+            Command::LineBreak,
+            Command::Show { str: "c  d" },
+        ];
+        let bounds = Rectangle::new(Point::zero(), Size::new(100, 100));
+        let mut trace = Trace::default();
+        crate::display::trace(
+            &mut trace,
+            Point::zero(),
+            bounds,
+            false,
+            Gray8::BLACK,
+            TestFonts,
+            TestStyle,
+            &header(),
+            &commands,
+        )
+        .unwrap();
+
+        // Spaces are a third of the 12px em, and a ratio of 1 stretches them by half.
+        let widths: Vec<i32> = trace
+            .events
+            .iter()
+            .filter_map(|(event, _)| match event {
+                TraceEvent::WhitespaceAt { width, .. } => Some(*width),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(widths, vec![6, 4, 4]);
+    }
+
+    #[test]
+    fn positioned_glyphs_ignore_kerning() {
+        let glyphs = |commands: &[Command<&str>]| {
+            let bounds = Rectangle::new(Point::zero(), Size::new(100, 100));
+            let mut trace = Trace::default();
+            crate::display::trace(
+                &mut trace,
+                Point::zero(),
+                bounds,
+                false,
+                Gray8::BLACK,
+                TestFonts,
+                TestStyle,
+                &header(),
+                commands,
+            )
+            .unwrap();
+            trace
+                .events
+                .iter()
+                .filter_map(|(event, at)| match event {
+                    TraceEvent::GlyphAt { c, .. } => Some((*c, at.x)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Shown text is kerned...
+        let shown = glyphs(&[Command::Show { str: "AVA" }]);
+        assert_eq!(
+            shown,
+            vec![('A', 0), ('V', ADVANCE - 2), ('A', 2 * ADVANCE - 2)]
+        );
+
+        // ...but positioned glyphs land exactly where they are placed.
+        let positioned = glyphs(&[Command::ShowPositioned {
+            glyphs: vec![('A', 9), ('V', 7), ('A', 9)],
+        }]);
+        assert_eq!(positioned, vec![('A', 0), ('V', 9), ('A', 16)]);
+    }
 }
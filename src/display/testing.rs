@@ -0,0 +1,165 @@
+//! Fonts and draw targets for testing the display of pages.
+
+use crate::{
+    display::{Color, FontStyle, Fonts},
+    Header, ImageTable, Metadata, Style, VERSION,
+};
+use alloc::{vec, vec::Vec};
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::{OriginDimensions, Point, Size},
+    pixelcolor::{Gray8, GrayColor},
+    primitives::{PointsIter, Rectangle},
+    Pixel,
+};
+
+/// A 12px font whose glyphs are 6px wide. Each glyph is drawn as a 5px wide, 6px tall block
+/// that sits on the baseline. "A" and "V" are kerned 2px closer together.
+#[derive(Clone)]
+pub struct TestStyle;
+
+/// The advance of each of [`TestStyle`]'s glyphs.
+pub const ADVANCE: i32 = 6;
+
+impl crate::fonts::FontStyle for TestStyle {
+    fn font_name(&self) -> &str {
+        "Test"
+    }
+
+    fn em_px(&self) -> u16 {
+        12
+    }
+
+    fn line_height(&self) -> u16 {
+        15
+    }
+
+    fn baseline(&self) -> u16 {
+        3
+    }
+
+    fn kerning(&self, left: char, right: char) -> i32 {
+        match (left, right) {
+            ('A', 'V') => -2,
+            _ => 0,
+        }
+    }
+}
+
+impl FontStyle for TestStyle {
+    fn glyph_advance(&self, _code_point: char) -> i32 {
+        ADVANCE
+    }
+
+    fn draw_glyph<C: Color, Draw: DrawTarget<Color = C>>(
+        &self,
+        draw: &mut Draw,
+        origin: Point,
+        color: C,
+        _over: C,
+        _gamma: f32,
+        _code_point: char,
+    ) -> Result<Point, Draw::Error> {
+        let pixels = (1..=6)
+            .flat_map(|dy| (0..ADVANCE - 1).map(move |dx| Point::new(dx, -dy)))
+            .map(|offset| Pixel(origin + offset, color));
+        draw.draw_iter(pixels)?;
+        Ok(origin + Point::new(ADVANCE, 0))
+    }
+}
+
+pub struct TestFonts;
+
+impl Fonts for TestFonts {
+    type Style = TestStyle;
+
+    fn get_style(&self, _style: &Style) -> Option<TestStyle> {
+        Some(TestStyle)
+    }
+}
+
+/// Returns the header of a document whose only style is [`TestStyle`].
+pub fn header() -> Header {
+    Header {
+        version: VERSION,
+        title: "Test".into(),
+        styles: vec![Style {
+            font_name: "Test".into(),
+            em_px: 12,
+        }],
+        images: ImageTable::new(),
+        language: None,
+        revision: None,
+        fonts: Vec::new(),
+        metadata: Metadata::default(),
+        page: None,
+    }
+}
+
+/// A grayscale draw target that remembers its pixels. Drawing anything other than white to the
+/// pixel at `fail_at` fails with that pixel's position.
+pub struct Canvas {
+    pub size: Size,
+    pub pixels: Vec<Gray8>,
+    pub fail_at: Option<Point>,
+}
+
+impl Canvas {
+    pub fn new(width: u32, height: u32) -> Self {
+        Canvas {
+            size: Size::new(width, height),
+            pixels: vec![Gray8::WHITE; (width * height) as usize],
+            fail_at: None,
+        }
+    }
+
+    /// Returns the color of the pixel at `p`.
+    pub fn get(&self, p: Point) -> Gray8 {
+        self.pixels[(p.y as u32 * self.size.width + p.x as u32) as usize]
+    }
+
+    /// Returns the smallest rectangle that contains every pixel of the given color, if any.
+    pub fn ink(&self, color: Gray8) -> Option<Rectangle> {
+        let points = Rectangle::new(Point::zero(), self.size)
+            .points()
+            .filter(|p| self.get(*p) == color);
+        let mut corners: Option<(Point, Point)> = None;
+        for p in points {
+            corners = match corners {
+                None => Some((p, p)),
+                Some((min, max)) => Some((
+                    Point::new(min.x.min(p.x), min.y.min(p.y)),
+                    Point::new(max.x.max(p.x), max.y.max(p.y)),
+                )),
+            };
+        }
+        corners.map(|(min, max)| {
+            let size = Size::new((max.x - min.x + 1) as u32, (max.y - min.y + 1) as u32);
+            Rectangle::new(min, size)
+        })
+    }
+}
+
+impl OriginDimensions for Canvas {
+    fn size(&self) -> Size {
+        self.size
+    }
+}
+
+impl DrawTarget for Canvas {
+    type Color = Gray8;
+    type Error = Point;
+
+    fn draw_iter<I: IntoIterator<Item = Pixel<Gray8>>>(&mut self, pixels: I) -> Result<(), Point> {
+        for Pixel(p, color) in pixels {
+            if Some(p) == self.fail_at && color != Gray8::WHITE {
+                return Err(p);
+            }
+            let (width, height) = (self.size.width as i32, self.size.height as i32);
+            if (0..width).contains(&p.x) && (0..height).contains(&p.y) {
+                self.pixels[(p.y * width + p.x) as usize] = color;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,106 @@
+use crate::{
+    display::{page::walk, FontStyle, Fonts},
+    Command, Header,
+};
+use alloc::vec::Vec;
+use core::convert::Infallible;
+use embedded_graphics::{
+    geometry::{Point, Size},
+    pixelcolor::{PixelColor, Rgb888},
+    primitives::rectangle::Rectangle,
+};
+
+/// Something a page does at a point. Glyph and decoration points lie on the text's baseline;
+/// other points are the top-left corner of what they describe.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceEvent {
+    /// A command is about to run with the cursor at the point. Only traced in debug mode.
+    CommandAt,
+    /// A glyph is drawn `phase / SUBPIXEL_PHASES` of a pixel to the right of the point.
+    GlyphAt { c: char, phase: u8 },
+    /// A whitespace character advances the text cursor by `width` pixels.
+    WhitespaceAt { c: char, width: i32 },
+    /// A line break moves the cursor to the start of the next line.
+    LineBreakAt,
+    /// A rectangle is filled.
+    FillRectAt { size: Size },
+    /// An image from the header's image table is drawn.
+    ImageAt {
+        width: u16,
+        height: u16,
+        data_offset: u32,
+    },
+    /// An underline or strikethrough is drawn from the point to `to`.
+    DecorationAt { to: Point },
+}
+
+/// Receives the events of a page as it is walked. Drawing a page is one kind of sink; recording
+/// the events is another.
+pub trait PageSink<S: FontStyle, C> {
+    type Error;
+
+    /// Receives an event at `at`. `style` and `color` are the style and color in effect.
+    fn event(
+        &mut self,
+        at: Point,
+        event: TraceEvent,
+        style: &S,
+        color: C,
+    ) -> Result<(), Self::Error>;
+}
+
+/// A sink that records the events of a page in order.
+#[derive(Clone, Debug, Default)]
+pub struct Trace {
+    pub events: Vec<(TraceEvent, Point)>,
+}
+
+impl<S: FontStyle, C> PageSink<S, C> for Trace {
+    type Error = Infallible;
+
+    fn event(
+        &mut self,
+        at: Point,
+        event: TraceEvent,
+        _style: &S,
+        _color: C,
+    ) -> Result<(), Infallible> {
+        self.events.push((event, at));
+        Ok(())
+    }
+}
+
+/// Walks a page as [`page`](crate::display::page) would draw it to a target with the given
+/// bounds, sending each event to `sink` instead of drawing it.
+#[allow(clippy::too_many_arguments)]
+pub fn trace<K, C, S, F, T>(
+    sink: &mut K,
+    origin: Point,
+    bounds: Rectangle,
+    debug: bool,
+    foreground: C,
+    fonts: F,
+    default_style: S,
+    header: &Header,
+    page: &[Command<T>],
+) -> Result<(), K::Error>
+where
+    K: PageSink<S, C>,
+    C: PixelColor + From<Rgb888>,
+    S: FontStyle,
+    F: Fonts<Style = S>,
+    T: AsRef<str> + core::fmt::Debug + Clone,
+{
+    walk(
+        sink,
+        origin,
+        bounds,
+        debug,
+        foreground,
+        fonts,
+        default_style,
+        header,
+        page,
+        |_, _| {},
+    )
+}
@@ -1,4 +1,7 @@
-use crate::{fonts, Style};
+use crate::{
+    fonts::{self, SUBPIXEL_PHASES},
+    Style,
+};
 
 #[cfg(feature = "layout")]
 use crate::layout;
@@ -15,13 +18,22 @@ use embedded_graphics::draw_target::DrawTarget;
 #[cfg(any(feature = "layout", feature = "display"))]
 use embedded_graphics::geometry::Point;
 
+mod subset;
+
+pub use subset::SubsetError;
+pub use ttf_parser::Tag;
+
 extern crate alloc;
-use alloc::vec::Vec;
-use core::cell::{RefCell, RefMut};
+use alloc::{string::String, vec::Vec};
+use core::cell::{Cell, RefCell, RefMut};
 use core::num::NonZeroUsize;
 use hashbrown::HashMap;
 use lru::LruCache;
-use ttf_parser::{Face, FaceParsingError, OutlineBuilder};
+use ttf_parser::{
+    gpos::{PairAdjustment, PositioningSubtable},
+    gsub::{SingleSubstitution, SubstitutionSubtable},
+    Face, FaceParsingError, GlyphId, OutlineBuilder,
+};
 use zeno::{Command, Mask, Origin, Placement, Transform, Vector};
 
 pub struct Glyph {
@@ -29,11 +41,133 @@ pub struct Glyph {
     pub data: Vec<u8>,
 }
 
+impl Glyph {
+    fn empty() -> Self {
+        Glyph {
+            placement: Default::default(),
+            data: Vec::new(),
+        }
+    }
+
+    /// Pads the glyph with empty columns on the right so that its extent matches `advance`.
+    /// Glyphs are measured and drawn by their extents, so this gives the glyph its full advance.
+    fn pad_to_advance(self, advance: i32) -> Self {
+        let width = advance - self.placement.left;
+        if width <= self.placement.width as i32 {
+            return self;
+        }
+
+        let (old_width, new_width) = (self.placement.width as usize, width as usize);
+        let height = self.placement.height as usize;
+        let mut data = Vec::with_capacity(new_width * height);
+        for row in 0..height {
+            data.extend_from_slice(&self.data[row * old_width..(row + 1) * old_width]);
+            data.resize((row + 1) * new_width, 0);
+        }
+
+        let mut placement = self.placement;
+        placement.width = new_width as u32;
+        Glyph { placement, data }
+    }
+}
+
+/// Returns the glyph that the font's `tnum` feature substitutes for `glyph_id`, if any.
+fn tabular_glyph(face: &Face, glyph_id: GlyphId) -> Option<GlyphId> {
+    let gsub = face.tables().gsub?;
+    let feature = gsub.features.find(Tag::from_bytes(b"tnum"))?;
+    for lookup_index in feature.lookup_indices {
+        let lookup = match gsub.lookups.get(lookup_index) {
+            None => continue,
+            Some(lookup) => lookup,
+        };
+        for subtable in lookup.subtables.into_iter::<SubstitutionSubtable>() {
+            match subtable {
+                SubstitutionSubtable::Single(SingleSubstitution::Format1 { coverage, delta }) => {
+                    if coverage.contains(glyph_id) {
+                        return Some(GlyphId((glyph_id.0 as i32 + delta as i32) as u16));
+                    }
+                }
+                SubstitutionSubtable::Single(SingleSubstitution::Format2 {
+                    coverage,
+                    substitutes,
+                }) => {
+                    if let Some(index) = coverage.get(glyph_id) {
+                        return substitutes.get(index);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    None
+}
+
+/// Returns the adjustment in font units between `left` and `right` given by the font's `kern`
+/// feature or, if the font has no `GPOS` table, by its `kern` table.
+fn pair_kerning(face: &Face, left: GlyphId, right: GlyphId) -> Option<i16> {
+    let tables = face.tables();
+    match tables.gpos {
+        Some(gpos) => {
+            let feature = gpos.features.find(Tag::from_bytes(b"kern"))?;
+            for lookup_index in feature.lookup_indices {
+                let lookup = match gpos.lookups.get(lookup_index) {
+                    None => continue,
+                    Some(lookup) => lookup,
+                };
+                for subtable in lookup.subtables.into_iter::<PositioningSubtable>() {
+                    let adjustment = match subtable {
+                        PositioningSubtable::Pair(PairAdjustment::Format1 { coverage, sets }) => {
+                            coverage
+                                .get(left)
+                                .and_then(|index| sets.get(index))
+                                .and_then(|set| set.get(right))
+                        }
+                        PositioningSubtable::Pair(PairAdjustment::Format2 {
+                            coverage,
+                            classes,
+                            matrix,
+                        }) => {
+                            if coverage.contains(left) {
+                                matrix.get((classes.0.get(left), classes.1.get(right)))
+                            } else {
+                                None
+                            }
+                        }
+                        _ => None,
+                    };
+                    if let Some((record, _)) = adjustment {
+                        return Some(record.x_advance);
+                    }
+                }
+            }
+            None
+        }
+        None => tables
+            .kern?
+            .subtables
+            .into_iter()
+            .filter(|s| s.horizontal && !s.variable && !s.has_cross_stream)
+            .find_map(|s| s.glyphs_kerning(left, right)),
+    }
+}
+
+/// Converts a vertical metric in font units to whole pixels, or returns `None` if the result is
+/// not a valid pixel size.
+fn metric_px(units: i32, pixels_per_unit: f32) -> Option<u16> {
+    let px = units as f32 * pixels_per_unit;
+    if px.is_finite() && (0.0..=u16::MAX as f32).contains(&px) {
+        Some(px as u16)
+    } else {
+        None
+    }
+}
+
 #[derive(PartialEq, Eq, Hash)]
 struct GlyphCacheKey {
     font_id: usize,
     size_px: u16,
     code_point: char,
+    phase: u8,
 }
 
 struct Font<'data> {
@@ -42,75 +176,295 @@ struct Font<'data> {
     face: Face<'data>,
 }
 
+/// How characters that are missing from a font are rendered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MissingGlyphPolicy {
+    /// Missing characters are invisible and take up no space.
+    Skip,
+    /// Missing characters are drawn as the font's `.notdef` glyph, or as a hollow box if the
+    /// font's `.notdef` glyph is empty.
+    Tofu,
+    /// Missing characters are drawn using the first other font that has them, in the order the
+    /// fonts were added. Characters that are missing from every font are skipped.
+    Fallback,
+}
+
+/// Statistics about a [`Fonts`]'s glyph cache.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CacheStats {
+    /// The number of glyphs that were found in the cache.
+    pub hits: u64,
+    /// The number of glyphs that were rendered because they were not in the cache.
+    pub misses: u64,
+    /// The number of glyphs currently in the cache.
+    pub len: usize,
+    /// The most glyphs the cache holds.
+    pub capacity: usize,
+}
+
 pub struct Fonts<'data> {
     fonts: HashMap<&'data str, Font<'data>>,
+    /// Names of the fonts to search for characters that are missing from a font, by the font's
+    /// name, in the order they are searched.
+    fallbacks: HashMap<String, Vec<String>>,
     glyph_cache: RefCell<LruCache<GlyphCacheKey, Glyph>>,
+    cache_hits: Cell<u64>,
+    cache_misses: Cell<u64>,
+    missing_glyph_policy: MissingGlyphPolicy,
+    tabular_figures: bool,
+    kerning: bool,
 }
 
 impl<'data> Fonts<'data> {
     pub fn new(glyph_cache_size: NonZeroUsize) -> Self {
         Fonts {
             fonts: HashMap::new(),
+            fallbacks: HashMap::new(),
             glyph_cache: RefCell::new(LruCache::new(glyph_cache_size)),
+            cache_hits: Cell::new(0),
+            cache_misses: Cell::new(0),
+            missing_glyph_policy: MissingGlyphPolicy::Skip,
+            tabular_figures: false,
+            kerning: true,
+        }
+    }
+
+    /// Returns the glyph cache's hit and miss counts since the fonts were created, along with its
+    /// current size.
+    pub fn cache_stats(&self) -> CacheStats {
+        let cache = self.glyph_cache.borrow();
+        CacheStats {
+            hits: self.cache_hits.get(),
+            misses: self.cache_misses.get(),
+            len: cache.len(),
+            capacity: cache.cap().get(),
         }
     }
 
+    /// Drops all cached glyphs. The hit and miss counts are unchanged.
+    pub fn clear_cache(&self) {
+        self.glyph_cache.borrow_mut().clear();
+    }
+
+    /// Sets how characters that are missing from a font are rendered. Defaults to
+    /// [`MissingGlyphPolicy::Skip`].
+    pub fn with_missing_glyph_policy(mut self, policy: MissingGlyphPolicy) -> Self {
+        self.missing_glyph_policy = policy;
+        self.clear_cache();
+        self
+    }
+
+    /// Sets whether digits are drawn using tabular figures. If enabled, digits are replaced by
+    /// the glyphs given by each font's `tnum` feature, if any, and are measured and drawn using
+    /// their advances so that columns of numbers line up.
+    pub fn with_tabular_figures(mut self, enabled: bool) -> Self {
+        self.tabular_figures = enabled;
+        self.clear_cache();
+        self
+    }
+
+    /// Sets whether pairs of characters are kerned using each font's `kern` feature or `kern`
+    /// table. Defaults to enabled; disabling kerning speeds up measurement and drawing at the cost
+    /// of looser text.
+    pub fn with_kerning(mut self, enabled: bool) -> Self {
+        self.kerning = enabled;
+        self
+    }
+
+    /// Adds `fallback` to the end of the list of fonts searched for characters that are missing
+    /// from `primary`. Characters that are missing from every fallback are rendered according to
+    /// the missing glyph policy. Fallbacks do not chain: the fallbacks of a fallback font are not
+    /// searched.
+    pub fn add_fallback(&mut self, primary: &str, fallback: &str) {
+        self.fallbacks
+            .entry(String::from(primary))
+            .or_default()
+            .push(String::from(fallback));
+        self.clear_cache();
+    }
+
     pub fn add(&mut self, name: &'data str, data: &'data [u8]) -> Result<usize, FaceParsingError> {
-        let id = self.fonts.len();
-        self.fonts.insert(
-            name,
-            Font {
-                id,
-                name,
-                face: Face::parse(data, 0)?,
-            },
-        );
+        self.add_variable(name, data, &[])
+    }
+
+    /// Adds an instance of a variable font with the given variation coordinates, e.g. a `wght`
+    /// of 700 for a bold instance. The same data may be added under several names with
+    /// different coordinates; each name is a separate font, so the instances never share cached
+    /// glyphs. Axes that the font does not have are ignored.
+    pub fn add_variable(
+        &mut self,
+        name: &'data str,
+        data: &'data [u8],
+        coordinates: &[(Tag, f32)],
+    ) -> Result<usize, FaceParsingError> {
+        let mut face = Face::parse(data, 0)?;
+        for (axis, value) in coordinates {
+            face.set_variation(*axis, *value);
+        }
+
+        // A font that replaces another takes its id, so ids stay unique. Its glyphs replace the
+        // other font's cached glyphs.
+        let id = match self.fonts.get(name) {
+            None => self.fonts.len(),
+            Some(replaced) => {
+                self.clear_cache();
+                replaced.id
+            }
+        };
+        self.fonts.insert(name, Font { id, name, face });
         Ok(id)
     }
 
-    fn render_glyph(font: &Font, pixels_per_em: f32, code_point: char) -> Glyph {
-        let glyph_id = match font.face.glyph_index(code_point) {
-            None => {
-                return Glyph {
-                    placement: Default::default(),
-                    data: Vec::new(),
+    /// Returns a copy of the named font that only contains the outlines of the glyphs needed to
+    /// render the given characters. Glyph IDs, character maps, and metrics are unchanged, so the
+    /// result lays out and renders those characters exactly as the original font does.
+    ///
+    /// Only TrueType fonts are supported.
+    pub fn subset<I: IntoIterator<Item = char>>(
+        &self,
+        name: &str,
+        used_chars: I,
+    ) -> Result<Vec<u8>, SubsetError> {
+        let font = match self.fonts.get(name) {
+            None => return Err(SubsetError::UnknownFont),
+            Some(f) => f,
+        };
+
+        let glyph_ids: Vec<u16> = used_chars
+            .into_iter()
+            .filter_map(|c| font.face.glyph_index(c).map(|id| (c, id)))
+            .map(|(c, id)| {
+                if self.tabular_figures && c.is_ascii_digit() {
+                    tabular_glyph(&font.face, id).unwrap_or(id).0
+                } else {
+                    id.0
                 }
-            }
-            Some(id) => id,
+            })
+            .collect();
+        subset::glyphs(font.face.raw_face().data, &glyph_ids)
+    }
+
+    /// Renders a glyph shifted right by `phase / SUBPIXEL_PHASES` of a pixel.
+    fn render_glyph(&self, font: &Font, pixels_per_em: f32, code_point: char, phase: u8) -> Glyph {
+        let fallback = || {
+            let names = self.fallbacks.get(font.name)?;
+            names
+                .iter()
+                .filter_map(|name| self.fonts.get(name.as_str()))
+                .find_map(|f| f.face.glyph_index(code_point).map(|id| (f, id)))
+        };
+        let (font, glyph_id) = match font.face.glyph_index(code_point) {
+            Some(id) => (font, id),
+            None => match fallback() {
+                Some(fallback) => fallback,
+                None => match self.missing_glyph_policy {
+                    MissingGlyphPolicy::Skip => return Glyph::empty(),
+                    MissingGlyphPolicy::Tofu => return Fonts::render_tofu(font, pixels_per_em),
+                    MissingGlyphPolicy::Fallback => {
+                        let fallback = self
+                            .fonts
+                            .values()
+                            .filter(|f| f.id != font.id)
+                            .filter_map(|f| f.face.glyph_index(code_point).map(|id| (f, id)))
+                            .min_by_key(|(f, _)| f.id);
+                        match fallback {
+                            None => return Glyph::empty(),
+                            Some(fallback) => fallback,
+                        }
+                    }
+                },
+            },
+        };
+
+        let tabular = self.tabular_figures && code_point.is_ascii_digit();
+        let glyph_id = if tabular {
+            tabular_glyph(&font.face, glyph_id).unwrap_or(glyph_id)
+        } else {
+            glyph_id
         };
 
         let mut path = Path::new();
         if font.face.outline_glyph(glyph_id, &mut path).is_none() {
-            return Glyph {
-                placement: Default::default(),
-                data: Vec::new(),
-            };
+            return Glyph::empty();
+        }
+
+        let offset_px = phase as f32 / SUBPIXEL_PHASES as f32;
+        let glyph = Fonts::render_path(font, pixels_per_em, offset_px, &path);
+        match (tabular, font.face.glyph_hor_advance(glyph_id)) {
+            (true, Some(advance)) => {
+                let units_per_em: f32 = font.face.units_per_em().into();
+                let advance = advance as f32 * pixels_per_em / units_per_em;
+                glyph.pad_to_advance(advance as i32)
+            }
+            _ => glyph,
+        }
+    }
+
+    /// Renders the font's `.notdef` glyph, or a hollow box if the glyph has no outline.
+    fn render_tofu(font: &Font, pixels_per_em: f32) -> Glyph {
+        let mut path = Path::new();
+        if font.face.outline_glyph(GlyphId(0), &mut path).is_none() {
+            let face = &font.face;
+            let width = face.units_per_em() as f32 / 2.0;
+            let height = face.capital_height().unwrap_or(face.ascender()) as f32;
+            let stroke = face.units_per_em() as f32 / 20.0;
+
+            // The inner rectangle is wound in the opposite direction so that it is left empty.
+            path.move_to(0.0, 0.0);
+            path.line_to(width, 0.0);
+            path.line_to(width, height);
+            path.line_to(0.0, height);
+            path.close();
+            path.move_to(stroke, stroke);
+            path.line_to(stroke, height - stroke);
+            path.line_to(width - stroke, height - stroke);
+            path.line_to(width - stroke, stroke);
+            path.close();
         }
 
+        Fonts::render_path(font, pixels_per_em, 0.0, &path)
+    }
+
+    fn render_path(font: &Font, pixels_per_em: f32, offset_px: f32, path: &Path) -> Glyph {
         let units_per_em: f32 = font.face.units_per_em().into();
         let pixels_per_unit = pixels_per_em / units_per_em;
 
         let (data, placement) = Mask::new(&path.commands)
             .origin(Origin::TopLeft)
-            .transform(Some(Transform::scale(pixels_per_unit, pixels_per_unit)))
+            .transform(Some(
+                Transform::scale(pixels_per_unit, pixels_per_unit).then_translate(offset_px, 0.0),
+            ))
             .render();
 
         Glyph { placement, data }
     }
 
     fn glyph(&self, style: &FontStyle, code_point: char) -> RefMut<Glyph> {
+        self.glyph_at_phase(style, code_point, 0)
+    }
+
+    fn glyph_at_phase(&self, style: &FontStyle, code_point: char, phase: u8) -> RefMut<Glyph> {
         let cache_key = GlyphCacheKey {
             font_id: style.font.id,
             size_px: style.size_px,
             code_point,
+            phase,
         };
         RefMut::map(self.glyph_cache.borrow_mut(), |cache| {
+            let counter = if cache.contains(&cache_key) {
+                &self.cache_hits
+            } else {
+                &self.cache_misses
+            };
+            counter.set(counter.get() + 1);
             cache.get_or_insert_mut(cache_key, || {
-                Fonts::render_glyph(style.font, style.size_px.into(), code_point)
+                self.render_glyph(style.font, style.size_px.into(), code_point, phase)
             })
         })
     }
 
+    /// Returns the named font at the style's size, or `None` if there is no such font or the
+    /// font's metrics are invalid.
     pub fn get_style<'s>(&'s self, style: &Style) -> Option<FontStyle<'s, 'data>>
     where
         'data: 's,
@@ -124,11 +478,12 @@ impl<'data> Fonts<'data> {
         let pixels_per_em: f32 = style.em_px.into();
         let units_per_em: f32 = face.units_per_em().into();
         let pixels_per_unit = pixels_per_em / units_per_em;
-        let line_units: i16 = face.ascender() - face.descender() + face.line_gap();
-        let line_height_px: u16 =
-            unsafe { (line_units as f32 * pixels_per_unit).to_int_unchecked() };
-        let baseline_px: u16 =
-            unsafe { ((line_units - face.ascender()) as f32 * pixels_per_unit).to_int_unchecked() };
+        let line_units = face.ascender() as i32 - face.descender() as i32 + face.line_gap() as i32;
+
+        // Malformed fonts may yield metrics that are negative or too large to represent. Such
+        // fonts are treated as unavailable.
+        let line_height_px = metric_px(line_units, pixels_per_unit)?;
+        let baseline_px = metric_px(line_units - face.ascender() as i32, pixels_per_unit)?;
 
         Some(FontStyle {
             fonts: self,
@@ -183,6 +538,13 @@ where
     pub fn glyph(&self, code_point: char) -> RefMut<Glyph> {
         self.fonts.glyph(self, code_point)
     }
+
+    /// Converts a distance in font units to pixels at this style's size.
+    fn units_to_px(&self, units: i16) -> u16 {
+        let pixels_per_em: f32 = self.size_px.into();
+        let units_per_em: f32 = self.font.face.units_per_em().into();
+        (units as f32 * pixels_per_em / units_per_em) as u16
+    }
 }
 
 impl<'a, 'b> fonts::FontStyle for FontStyle<'a, 'b>
@@ -204,6 +566,53 @@ where
     fn baseline(&self) -> u16 {
         self.baseline_px
     }
+
+    fn x_height(&self) -> Option<u16> {
+        self.font
+            .face
+            .x_height()
+            .map(|units| self.units_to_px(units))
+    }
+
+    fn cap_height(&self) -> Option<u16> {
+        self.font
+            .face
+            .capital_height()
+            .map(|units| self.units_to_px(units))
+    }
+
+    fn ascent(&self) -> Option<u16> {
+        Some(self.units_to_px(self.font.face.ascender()))
+    }
+
+    fn descent(&self) -> Option<u16> {
+        Some(self.units_to_px(self.font.face.descender().saturating_abs()))
+    }
+
+    fn kerning(&self, left: char, right: char) -> i32 {
+        if !self.fonts.kerning || left.is_whitespace() || right.is_whitespace() {
+            return 0;
+        }
+        // Kerning would misalign columns of tabular figures.
+        if self.fonts.tabular_figures && (left.is_ascii_digit() || right.is_ascii_digit()) {
+            return 0;
+        }
+
+        let face = &self.font.face;
+        let units = match (face.glyph_index(left), face.glyph_index(right)) {
+            (Some(left), Some(right)) => pair_kerning(face, left, right).unwrap_or(0),
+            _ => return 0,
+        };
+        let pixels_per_em: f32 = self.size_px.into();
+        let units_per_em: f32 = face.units_per_em().into();
+        let px = units as f32 * pixels_per_em / units_per_em;
+        // Round to the nearest pixel.
+        if px < 0.0 {
+            (px - 0.5) as i32
+        } else {
+            (px + 0.5) as i32
+        }
+    }
 }
 
 #[cfg(feature = "layout")]
@@ -215,7 +624,13 @@ where
         let origin = Point::new(0, 0);
         let mut cursor = origin;
 
+        let mut previous = None;
         for c in text.chars() {
+            if let Some(previous) = previous {
+                cursor.x += fonts::FontStyle::kerning(self, previous, c);
+            }
+            previous = Some(c);
+
             let glyph = self.fonts.glyph(self, c);
             let glyph_origin = cursor + Point::new(glyph.placement.left, glyph.placement.top);
             cursor.x = glyph_origin.x + glyph.placement.width as i32;
@@ -246,10 +661,51 @@ where
         origin: Point,
         color: C,
         over: C,
+        gamma: f32,
         c: char,
     ) -> Result<Point, Draw::Error> {
         let glyph = self.fonts.glyph(self, c);
-        display::draw_glyph(draw, origin, color, over, glyph.placement, &glyph.data)
+        display::draw_glyph(
+            draw,
+            origin,
+            color,
+            over,
+            gamma,
+            glyph.placement,
+            &glyph.data,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn draw_glyph_subpixel<C: display::Color, Draw: DrawTarget<Color = C>>(
+        &self,
+        draw: &mut Draw,
+        origin: Point,
+        phase: u8,
+        color: C,
+        over: C,
+        gamma: f32,
+        c: char,
+    ) -> Result<Point, Draw::Error> {
+        if phase == 0 {
+            return self.draw_glyph(draw, origin, color, over, gamma, c);
+        }
+
+        {
+            let glyph = self.fonts.glyph_at_phase(self, c, phase % SUBPIXEL_PHASES);
+            display::draw_glyph(
+                draw,
+                origin,
+                color,
+                over,
+                gamma,
+                glyph.placement,
+                &glyph.data,
+            )?;
+        }
+        // The shifted glyph may be wider than the unshifted one, so advance by the latter.
+        let advance = display::FontStyle::glyph_advance(self, c);
+        Ok(origin + Point::new(advance, 0))
     }
 }
 
@@ -291,3 +747,119 @@ impl OutlineBuilder for Path {
         self.commands.push(Command::Close);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LITERATA: &[u8] = include_bytes!("bin/edf/assets/Literata-Regular.ttf");
+
+    fn fonts(name: &'static str, data: &'static [u8]) -> Fonts<'static> {
+        let mut fonts = Fonts::new(NonZeroUsize::new(64).unwrap());
+        fonts.add(name, data).unwrap();
+        fonts
+    }
+
+    fn style<'s, 'data>(fonts: &'s Fonts<'data>, name: &str, em_px: u16) -> FontStyle<'s, 'data> {
+        let style = Style {
+            font_name: String::from(name),
+            em_px,
+        };
+        fonts.get_style(&style).unwrap()
+    }
+
+    /// Returns the placement and coverage of a character's glyph at 20px.
+    fn glyph(fonts: &Fonts, name: &str, c: char) -> ((i32, i32, u32, u32), Vec<u8>) {
+        let style = style(fonts, name, 20);
+        let glyph = style.glyph(c);
+        let placement = glyph.placement;
+        (
+            (
+                placement.left,
+                placement.top,
+                placement.width,
+                placement.height,
+            ),
+            glyph.data.clone(),
+        )
+    }
+
+    /// Returns a copy of the fixture with its character map renamed, so that it has no
+    /// characters. The new tag sorts in the same place, so the table directory stays ordered.
+    fn without_characters() -> Vec<u8> {
+        let mut data = LITERATA.to_vec();
+        let tables = u16::from_be_bytes([data[4], data[5]]) as usize;
+        let record = (0..tables)
+            .map(|i| 12 + 16 * i)
+            .find(|r| &data[*r..*r + 4] == b"cmap")
+            .unwrap();
+        data[record..record + 4].copy_from_slice(b"cmaq");
+        data
+    }
+
+    #[test]
+    fn subsets_render_their_characters() {
+        let full = fonts("Literata", LITERATA);
+        let data = full.subset("Literata", "hello".chars()).unwrap();
+        assert!(data.len() < LITERATA.len());
+
+        let mut subset = Fonts::new(NonZeroUsize::new(64).unwrap());
+        subset.add("Literata", &data).unwrap();
+        for c in "helo".chars() {
+            let (placement, coverage) = glyph(&subset, "Literata", c);
+            assert!(coverage.iter().any(|a| *a != 0), "{c:?} is blank");
+            assert_eq!((placement, coverage), glyph(&full, "Literata", c), "{c:?}");
+        }
+    }
+
+    #[test]
+    fn missing_glyph_policies() {
+        let blank = without_characters();
+        let x = glyph(&fonts("Literata", LITERATA), "Literata", 'x');
+        let render = |policy| {
+            let mut fonts =
+                Fonts::new(NonZeroUsize::new(64).unwrap()).with_missing_glyph_policy(policy);
+            fonts.add("Blank", &blank).unwrap();
+            fonts.add("Literata", LITERATA).unwrap();
+            glyph(&fonts, "Blank", 'x')
+        };
+
+        let (placement, coverage) = render(MissingGlyphPolicy::Skip);
+        assert_eq!(placement.2, 0);
+        assert!(coverage.is_empty());
+
+        let tofu = render(MissingGlyphPolicy::Tofu);
+        assert!(tofu.1.iter().any(|a| *a != 0));
+        assert_ne!(tofu, x);
+
+        assert_eq!(render(MissingGlyphPolicy::Fallback), x);
+    }
+
+    #[cfg(feature = "layout")]
+    #[test]
+    fn tabular_figures_share_a_width() {
+        use layout::FontStyle as _;
+
+        let width = |fonts: &Fonts, text| {
+            let style = style(fonts, "Literata", 20);
+            style.measure_string(text).bounding_box.size.width
+        };
+
+        let proportional = fonts("Literata", LITERATA);
+        assert!(width(&proportional, "111") < width(&proportional, "000"));
+
+        let tabular = fonts("Literata", LITERATA).with_tabular_figures(true);
+        assert_eq!(width(&tabular, "111"), width(&tabular, "000"));
+    }
+
+    #[test]
+    fn cap_height_exceeds_x_height() {
+        use fonts::FontStyle as _;
+
+        let fonts = fonts("Literata", LITERATA);
+        let style = style(&fonts, "Literata", 100);
+        let (cap_height, x_height) = (style.cap_height().unwrap(), style.x_height().unwrap());
+        assert!(x_height > 0);
+        assert!(cap_height > x_height, "{cap_height} <= {x_height}");
+    }
+}
@@ -0,0 +1,304 @@
+use alloc::{vec, vec::Vec};
+use core::fmt;
+
+/// An error encountered while subsetting a font.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SubsetError {
+    /// No font with the given name has been added.
+    UnknownFont,
+    /// The font is a collection or does not use TrueType outlines.
+    Unsupported,
+    /// The font's tables are truncated or inconsistent.
+    Malformed,
+}
+
+impl fmt::Display for SubsetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubsetError::UnknownFont => write!(f, "unknown font"),
+            SubsetError::Unsupported => write!(f, "unsupported font format"),
+            SubsetError::Malformed => write!(f, "malformed font"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SubsetError {}
+
+// Composite glyph flags.
+const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+const WE_HAVE_A_SCALE: u16 = 0x0008;
+const MORE_COMPONENTS: u16 = 0x0020;
+const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+
+struct TableRecord {
+    tag: [u8; 4],
+    offset: usize,
+    length: usize,
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Result<u16, SubsetError> {
+    match data.get(offset..offset + 2) {
+        None => Err(SubsetError::Malformed),
+        Some(b) => Ok(u16::from_be_bytes([b[0], b[1]])),
+    }
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32, SubsetError> {
+    match data.get(offset..offset + 4) {
+        None => Err(SubsetError::Malformed),
+        Some(b) => Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]])),
+    }
+}
+
+fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0u32, |sum, chunk| {
+        let mut word = [0; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        sum.wrapping_add(u32::from_be_bytes(word))
+    })
+}
+
+/// Returns a copy of the TrueType font in `data` that only contains outlines for the given
+/// glyphs and the glyphs they are composed of. Glyph IDs are preserved, so the font's character
+/// map and metrics remain valid; the outlines of all other glyphs are empty.
+pub fn glyphs(data: &[u8], glyph_ids: &[u16]) -> Result<Vec<u8>, SubsetError> {
+    match u32_at(data, 0)? {
+        0x00010000 | 0x74727565 => {}
+        _ => return Err(SubsetError::Unsupported),
+    }
+
+    let num_tables = u16_at(data, 4)? as usize;
+    let mut tables = Vec::with_capacity(num_tables);
+    for i in 0..num_tables {
+        let record = 12 + i * 16;
+        let tag = match data.get(record..record + 4) {
+            None => return Err(SubsetError::Malformed),
+            Some(tag) => [tag[0], tag[1], tag[2], tag[3]],
+        };
+        let offset = u32_at(data, record + 8)? as usize;
+        let length = u32_at(data, record + 12)? as usize;
+        if data.get(offset..offset + length).is_none() {
+            return Err(SubsetError::Malformed);
+        }
+        tables.push(TableRecord {
+            tag,
+            offset,
+            length,
+        });
+    }
+    let table = |tag: &[u8; 4]| match tables.iter().find(|t| &t.tag == tag) {
+        None => Err(SubsetError::Unsupported),
+        Some(t) => Ok(&data[t.offset..t.offset + t.length]),
+    };
+
+    let head = table(b"head")?;
+    let maxp = table(b"maxp")?;
+    let loca = table(b"loca")?;
+    let glyf = table(b"glyf")?;
+
+    let long_offsets = u16_at(head, 50)? != 0;
+    let num_glyphs = u16_at(maxp, 4)? as usize;
+
+    let glyph_range = |id: usize| {
+        let (start, end) = if long_offsets {
+            (
+                u32_at(loca, id * 4)? as usize,
+                u32_at(loca, id * 4 + 4)? as usize,
+            )
+        } else {
+            (
+                u16_at(loca, id * 2)? as usize * 2,
+                u16_at(loca, id * 2 + 2)? as usize * 2,
+            )
+        };
+        if start > end || end > glyf.len() {
+            return Err(SubsetError::Malformed);
+        }
+        Ok(start..end)
+    };
+
+    // Find the glyphs to keep, including the components of any composite glyphs.
+    let mut keep = vec![false; num_glyphs];
+    let mut pending: Vec<u16> = glyph_ids.to_vec();
+    pending.push(0);
+    while let Some(id) = pending.pop() {
+        let id = id as usize;
+        if id >= num_glyphs || keep[id] {
+            continue;
+        }
+        keep[id] = true;
+
+        let glyph = &glyf[glyph_range(id)?];
+        if glyph.is_empty() || (u16_at(glyph, 0)? as i16) >= 0 {
+            continue;
+        }
+
+        let mut offset = 10;
+        loop {
+            let flags = u16_at(glyph, offset)?;
+            pending.push(u16_at(glyph, offset + 2)?);
+
+            offset += 4;
+            offset += if flags & ARG_1_AND_2_ARE_WORDS != 0 {
+                4
+            } else {
+                2
+            };
+            offset += if flags & WE_HAVE_A_SCALE != 0 {
+                2
+            } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+                4
+            } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+                8
+            } else {
+                0
+            };
+
+            if flags & MORE_COMPONENTS == 0 {
+                break;
+            }
+        }
+    }
+
+    // Build the new glyf and loca tables.
+    let mut new_glyf = Vec::new();
+    let mut new_loca = Vec::with_capacity(loca.len());
+    for (id, keep) in keep.iter().enumerate() {
+        if long_offsets {
+            new_loca.extend_from_slice(&(new_glyf.len() as u32).to_be_bytes());
+        } else {
+            new_loca.extend_from_slice(&((new_glyf.len() / 2) as u16).to_be_bytes());
+        }
+        if *keep {
+            new_glyf.extend_from_slice(&glyf[glyph_range(id)?]);
+            if !long_offsets && new_glyf.len() % 2 != 0 {
+                new_glyf.push(0);
+            }
+        }
+    }
+    if long_offsets {
+        new_loca.extend_from_slice(&(new_glyf.len() as u32).to_be_bytes());
+    } else {
+        new_loca.extend_from_slice(&((new_glyf.len() / 2) as u16).to_be_bytes());
+    }
+
+    // The checksum adjustment is recomputed once the font has been written.
+    let mut new_head = head.to_vec();
+    new_head[8..12].fill(0);
+
+    // Write the font, keeping the original table directory order.
+    let directory_len = 12 + num_tables * 16;
+    let mut out = Vec::with_capacity(data.len());
+    out.extend_from_slice(&data[..12]);
+    out.resize(directory_len, 0);
+
+    let mut head_offset = 0;
+    for (i, record) in tables.iter().enumerate() {
+        let contents = match &record.tag {
+            b"glyf" => new_glyf.as_slice(),
+            b"loca" => new_loca.as_slice(),
+            b"head" => {
+                head_offset = out.len();
+                new_head.as_slice()
+            }
+            _ => &data[record.offset..record.offset + record.length],
+        };
+
+        let offset = out.len();
+        out.extend_from_slice(contents);
+        out.resize((out.len() + 3) & !3, 0);
+
+        let entry = 12 + i * 16;
+        out[entry..entry + 4].copy_from_slice(&record.tag);
+        out[entry + 4..entry + 8].copy_from_slice(&checksum(contents).to_be_bytes());
+        out[entry + 8..entry + 12].copy_from_slice(&(offset as u32).to_be_bytes());
+        out[entry + 12..entry + 16].copy_from_slice(&(contents.len() as u32).to_be_bytes());
+    }
+
+    let adjustment = 0xB1B0AFBAu32.wrapping_sub(checksum(&out));
+    out[head_offset + 8..head_offset + 12].copy_from_slice(&adjustment.to_be_bytes());
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Glyphs are a contour count and an empty bounding box, followed by their data.
+    const SIMPLE: [u8; 12] = [0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0xaa, 0xbb];
+    const OTHER: [u8; 12] = [0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0xcc, 0xdd];
+    // A composite of glyph 1, offset by byte-sized arguments.
+    const COMPOSITE: [u8; 16] = [0xff, 0xff, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0];
+
+    /// Builds a font with short offsets whose `loca` table holds the first `loca_len` offsets of
+    /// the given glyphs.
+    fn font(glyphs: &[&[u8]], loca_len: usize) -> Vec<u8> {
+        let mut glyf = Vec::new();
+        let mut loca = Vec::new();
+        for glyph in glyphs {
+            loca.extend_from_slice(&((glyf.len() / 2) as u16).to_be_bytes());
+            glyf.extend_from_slice(glyph);
+        }
+        loca.extend_from_slice(&((glyf.len() / 2) as u16).to_be_bytes());
+        loca.truncate(loca_len * 2);
+
+        let head = [0; 54];
+        let mut maxp = vec![0, 0, 0x50, 0];
+        maxp.extend_from_slice(&(glyphs.len() as u16).to_be_bytes());
+
+        let tables: [(&[u8; 4], &[u8]); 4] = [
+            (b"glyf", &glyf),
+            (b"head", &head),
+            (b"loca", &loca),
+            (b"maxp", &maxp),
+        ];
+        let mut out = vec![0, 1, 0, 0, 0, tables.len() as u8, 0, 0, 0, 0, 0, 0];
+        let mut offset = 12 + tables.len() * 16;
+        for (tag, contents) in &tables {
+            out.extend_from_slice(*tag);
+            out.extend_from_slice(&[0; 4]);
+            out.extend_from_slice(&(offset as u32).to_be_bytes());
+            out.extend_from_slice(&(contents.len() as u32).to_be_bytes());
+            offset += (contents.len() + 3) & !3;
+        }
+        for (_, contents) in &tables {
+            out.extend_from_slice(contents);
+            out.resize((out.len() + 3) & !3, 0);
+        }
+        out
+    }
+
+    /// Returns the outline of a glyph in a font with short offsets.
+    fn outline(font: &[u8], id: usize) -> &[u8] {
+        let table = |tag: &[u8; 4]| {
+            let record = (0..u16_at(font, 4).unwrap() as usize)
+                .map(|i| 12 + i * 16)
+                .find(|r| &font[*r..*r + 4] == tag)
+                .unwrap();
+            let offset = u32_at(font, record + 8).unwrap() as usize;
+            &font[offset..offset + u32_at(font, record + 12).unwrap() as usize]
+        };
+        let (loca, glyf) = (table(b"loca"), table(b"glyf"));
+        let start = u16_at(loca, id * 2).unwrap() as usize * 2;
+        let end = u16_at(loca, id * 2 + 2).unwrap() as usize * 2;
+        &glyf[start..end]
+    }
+
+    #[test]
+    fn composites_keep_their_components() {
+        let data = font(&[&[], &SIMPLE, &OTHER, &COMPOSITE], 5);
+        let subset = glyphs(&data, &[3]).unwrap();
+        assert_eq!(outline(&subset, 1), SIMPLE);
+        assert_eq!(outline(&subset, 2), []);
+        assert_eq!(outline(&subset, 3), COMPOSITE);
+    }
+
+    #[test]
+    fn truncated_loca_is_malformed() {
+        let data = font(&[&[], &SIMPLE, &OTHER, &COMPOSITE], 3);
+        assert_eq!(glyphs(&data, &[3]), Err(SubsetError::Malformed));
+    }
+}
@@ -1,7 +1,42 @@
+/// The number of horizontal positions within each pixel at which glyphs may be drawn.
+pub const SUBPIXEL_PHASES: u8 = 4;
+
 pub trait FontStyle: Clone {
     fn font_name(&self) -> &str;
     fn em_px(&self) -> u16;
 
     fn line_height(&self) -> u16;
     fn baseline(&self) -> u16;
+
+    /// The height of lowercase letters above the baseline, if known.
+    fn x_height(&self) -> Option<u16> {
+        None
+    }
+
+    /// The height of capital letters above the baseline, if known.
+    fn cap_height(&self) -> Option<u16> {
+        None
+    }
+
+    /// The distance from the baseline to the top of the font's tallest glyphs, if known.
+    fn ascent(&self) -> Option<u16> {
+        None
+    }
+
+    /// The distance from the baseline to the bottom of the font's lowest glyphs, if known.
+    fn descent(&self) -> Option<u16> {
+        None
+    }
+
+    /// The adjustment in pixels to the distance between `left` and `right` when `right`
+    /// immediately follows `left`. Negative adjustments move the characters closer together.
+    fn kerning(&self, _left: char, _right: char) -> i32 {
+        0
+    }
+
+    /// The distance in pixels between tab stops. Defaults to the width of four spaces, which are a
+    /// third of an em wide.
+    fn tab_stop(&self) -> u16 {
+        (self.em_px() as u32 * 4 / 3) as u16
+    }
 }
@@ -2,7 +2,16 @@ mod builder;
 #[cfg(feature = "epub")]
 pub mod epub;
 mod fonts;
+mod hyphenator;
 pub mod markdown;
+mod reflow;
+#[cfg(test)]
+mod testing;
 
-pub use builder::{Align, Builder, Hyphenator, ParagraphBuilder, ParagraphOptions};
+pub use builder::{
+    Align, Builder, Direction, HeaderScope, Hyphenator, ListKind, OverflowPolicy, PageBreaking,
+    ParagraphBuilder, ParagraphOptions, StructureKind, StructureNode, TextIndent, VerbatimOverflow,
+    WhitespaceMetrics,
+};
 pub use fonts::*;
+pub use hyphenator::{CompositeHyphenator, Script};
@@ -4,8 +4,10 @@ use crate::{
 };
 
 use alloc::string::String;
-use alloc::{vec, vec::*};
+use alloc::{borrow::Cow, vec, vec::*};
+use core::ops::Range;
 use embedded_graphics::{geometry::Point, primitives::Rectangle};
+use hashbrown::HashMap;
 use text_layout::*;
 use unicode_segmentation::UnicodeSegmentation;
 
@@ -19,26 +21,115 @@ impl Hyphenator for () {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 enum Box<'a> {
     Indent,
     SetStyle {
         id: u16,
         line_height: u16,
         baseline: u16,
+        em_px: u16,
+        whitespace: Glue,
     },
     Word {
-        text: &'a str,
+        text: Cow<'a, str>,
     },
     Char {
         text: char,
     },
+    Raw {
+        command: Command<String>,
+    },
+    Marker {
+        text: String,
+        width: f32,
+        hang: f32,
+    },
+}
+
+/// Width, stretch, and shrink of the glue between words.
+#[derive(Clone, Copy, Debug)]
+struct Glue {
+    width: f32,
+    stretch: f32,
+    shrink: f32,
+}
+
+/// How the space between words is sized.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WhitespaceMetrics {
+    /// Width of a space as a fraction of the em size. If `None`, the width of the font's space
+    /// character is used.
+    pub width_em: Option<f32>,
+    /// How far a space may stretch, as a fraction of its width.
+    pub stretch: f32,
+    /// How far a space may shrink, as a fraction of its width.
+    pub shrink: f32,
+}
+
+impl Default for WhitespaceMetrics {
+    /// A third of an em that stretches by half and shrinks by a third. These are the metrics
+    /// readers assume when applying adjustment ratios.
+    fn default() -> Self {
+        WhitespaceMetrics {
+            width_em: Some(1.0 / 3.0),
+            stretch: 1.0 / 2.0,
+            shrink: 1.0 / 3.0,
+        }
+    }
+}
+
+impl WhitespaceMetrics {
+    fn glue<S: FontStyle>(&self, style: &S) -> Glue {
+        let em_px = style.em_px() as f32;
+        let width = match self.width_em {
+            Some(width_em) => width_em * em_px,
+            None => match style.measure_string(" ").bounding_box.size.width {
+                // Fall back to the default for fonts that do not report a space advance.
+                0 => em_px / 3.0,
+                width => width as f32,
+            },
+        };
+        self.glue_for_width(width)
+    }
+
+    fn glue_for_width(&self, width: f32) -> Glue {
+        Glue {
+            width,
+            stretch: width * self.stretch,
+            shrink: width * self.shrink,
+        }
+    }
+}
+
+/// Converts an adjustment ratio for glue with the given metrics into the ratio that gives spaces
+/// the same width when applied to the default metrics for a font of size `em_px`.
+fn reader_adjustment_ratio(r: f32, em_px: u16, glue: Glue) -> f32 {
+    let width = if r < 0.0 {
+        glue.width + glue.shrink * r
+    } else if r > 0.0 {
+        glue.width + glue.stretch * r
+    } else {
+        glue.width
+    };
+
+    // Widths that differ only by rounding are equal, so that unadjusted spaces stay unadjusted.
+    let default = WhitespaceMetrics::default().glue_for_width(em_px as f32 / 3.0);
+    let difference = width - default.width;
+    if difference < -1.0e-3 {
+        difference / default.shrink
+    } else if difference > 1.0e-3 {
+        difference / default.stretch
+    } else {
+        0.0
+    }
 }
 
 #[derive(Debug)]
 enum Penalty {
     SoftHyphen,
     HardHyphen,
+    BreakAfter,
     HardBreak,
 }
 
@@ -49,15 +140,286 @@ pub enum Align {
     Justify,
 }
 
+/// The base direction of a paragraph's text.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// Words are laid out from left to right.
+    Ltr,
+    /// Words are laid out from right to left. Lines start at the right edge of the paragraph, so
+    /// `Align::Left` and `Align::Right` align lines to the right and left edges, respectively.
+    ///
+    /// Only the order of whole words is reversed; characters are reversed within words that
+    /// contain right-to-left characters.
+    Rtl,
+}
+
+/// How block content that is larger than the page is laid out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Draw the content at its natural size at the top of a fresh page. Anything that extends
+    /// past the page is cut off by the display.
+    Clip,
+    /// Scale the content down proportionally to fit the page.
+    Scale,
+    /// Draw the content at its natural size at the current position.
+    Overflow,
+}
+
+/// How content is divided into pages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PageBreaking {
+    /// Each page is filled before moving on to the next.
+    Greedy,
+    /// The pages between forced page breaks are filled as evenly as possible. Content is laid out
+    /// on a single tall galley and divided into pages once a forced break is reached, choosing the
+    /// breaks that minimize the sum of the squared unused space of each page.
+    Balanced,
+}
+
+/// How verbatim lines that are too long for their paragraph are laid out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VerbatimOverflow {
+    /// Lines are only broken at line feeds. Anything that extends past the paragraph is cut off
+    /// by the display.
+    Clip,
+    /// Lines are broken at spaces to fit the paragraph, and after `column` characters if given.
+    /// Words that are longer than `column` characters are split.
+    Wrap { column: Option<usize> },
+}
+
+/// The kind of a list.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ListKind {
+    /// Items are marked with their number.
+    Ordered,
+    /// Items are marked with a bullet.
+    Unordered,
+}
+
+struct List {
+    kind: ListKind,
+    next: u32,
+    /// Width by which the list indents its items, and in which their markers hang.
+    indent: f32,
+}
+
+struct BlockQuote {
+    /// Margin added to each side of the quoted block.
+    margin: f32,
+    /// Top-left corner of the part of the quote's bar that has yet to be drawn, if the quote has
+    /// a bar.
+    bar: Option<Point>,
+}
+
+/// Size of synthesized small capitals relative to the size of full capitals.
+const SMALL_CAPS_SCALE: f32 = 0.8;
+
+/// Width of the bar drawn down the left edge of a block quote.
+const QUOTE_BAR_WIDTH: u16 = 2;
+
+/// An enlarged first letter that spans several lines of a paragraph.
+struct DropCap {
+    /// Number of lines spanned by the cap.
+    lines: u8,
+    /// The cap's text, style ID, and width, once the paragraph's first letter has been added.
+    cap: Option<(String, u16, f32)>,
+}
+
+/// A block that is kept on the same page as the first line that follows it.
+struct Keep {
+    /// Offset of the block's first command.
+    start: usize,
+    /// Cursor at the start of the block.
+    cursor: Point,
+    /// Style and line metrics at the start of the block.
+    style_id: u16,
+    line_height: u16,
+    baseline: u16,
+    /// True once the block has been laid out.
+    done: bool,
+}
+
+/// A row of blocks laid out side by side.
+struct Row {
+    /// Top of the row.
+    top: i32,
+    /// Bottom of the tallest block laid out so far.
+    bottom: i32,
+    /// The page count when the row's top was recorded.
+    page: usize,
+}
+
+/// Content laid out since the last forced page break, when balancing pages.
+struct Galley {
+    /// Offset of the galley's first command.
+    start: usize,
+    /// Places at which the galley may be broken, in order.
+    breaks: Vec<GalleyBreak>,
+}
+
+/// A place at which a galley may be broken.
+struct GalleyBreak {
+    /// Offset of the line break or cursor move that becomes the page break.
+    command: usize,
+    /// Bottom of the content before the break.
+    end: i32,
+    /// Top of the content after the break.
+    resume: i32,
+    /// Style and line metrics in effect at the break.
+    style_id: u16,
+    line_height: u16,
+    baseline: u16,
+}
+
+/// The number of pages a galley may grow to before its leading pages are broken, which keeps
+/// cursor positions within range.
+const GALLEY_PAGES: i32 = 16;
+
+/// The kind of a node in a document's logical structure.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StructureKind {
+    Heading {
+        level: u8,
+    },
+    Paragraph,
+    List {
+        kind: ListKind,
+    },
+    ListItem,
+    BlockQuote,
+    CodeBlock,
+    Table,
+    TableCaption,
+    TableRow,
+    /// A header cell. The cells of a row share a single line of text, so the command ranges of
+    /// cells are empty; a cell's column is its index within its row.
+    TableHeaderCell {
+        scope: HeaderScope,
+    },
+    /// A data cell. Its headers are the header cells that share its row or column and whose scope
+    /// covers it.
+    TableDataCell,
+}
+
+/// The cells covered by a table header cell, following the HTML `scope` attribute.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HeaderScope {
+    /// The header applies to the rest of its row.
+    Row,
+    /// The header applies to the rest of its column.
+    Column,
+    /// The header applies to the rest of its row group.
+    RowGroup,
+    /// The header applies to the rest of its column group.
+    ColumnGroup,
+    /// The cells covered by the header are determined from context.
+    Auto,
+}
+
+/// A node in a document's logical structure.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StructureNode {
+    pub kind: StructureKind,
+    /// The range of output commands covered by the node, including those of its children.
+    pub commands: Range<usize>,
+    pub children: Vec<StructureNode>,
+}
+
+/// Indentation of the lines of a paragraph, following CSS `text-indent`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TextIndent {
+    /// Width of the indent. May be negative.
+    pub px: f32,
+    /// Also indent each line that follows a hard line break.
+    pub each_line: bool,
+    /// Invert which lines are indented.
+    pub hanging: bool,
+}
+
 pub struct ParagraphOptions {
     pub align: Align,
+    /// Whether the last line of a justified paragraph is justified too. Last lines that would be
+    /// stretched too far are left ragged.
+    pub justify_last_line: bool,
     pub margin_bottom_px: f32,
     pub margin_left_px: f32,
     pub margin_right_px: f32,
     pub margin_top_px: f32,
 }
 
-// TODO: non-breaking spaces
+/// Returns the height and baseline of a line whose styles give it `height` and `baseline`, but
+/// whose paragraph may override its height. The difference in height is split evenly above and
+/// below the text.
+fn line_metrics(line_height: Option<u16>, height: u16, baseline: u16) -> (u16, u16) {
+    match line_height {
+        None => (height, baseline),
+        Some(line_height) => {
+            let leading = (line_height as i32 - height as i32) / 2;
+            let baseline = (baseline as i32 + leading).clamp(0, line_height as i32);
+            (line_height, baseline as u16)
+        }
+    }
+}
+
+/// Returns a range of a word, borrowing from the word's source text if the word does.
+fn substring<'a>(word: &Cow<'a, str>, range: Range<usize>) -> Cow<'a, str> {
+    match word {
+        Cow::Borrowed(word) => Cow::Borrowed(&word[range]),
+        Cow::Owned(word) => Cow::Owned(String::from(&word[range])),
+    }
+}
+
+/// The largest adjustment ratio at which the last line of a paragraph is justified.
+const MAX_LAST_LINE_RATIO: f32 = 2.0;
+
+/// The cost of a soft hyphen that would exceed the consecutive hyphen limit.
+const EXCESS_HYPHEN_COST: f32 = 3000.0;
+
+/// Returns the index of the first soft hyphen in `breaks` that ends more than `limit` hyphenated
+/// lines in a row, if any.
+fn excess_hyphen(
+    items: &[Item<Box<'_>, (), Penalty>],
+    breaks: impl Iterator<Item = usize>,
+    limit: usize,
+) -> Option<usize> {
+    let mut run = 0;
+    for at in breaks {
+        match &items[at] {
+            Item::Penalty {
+                data: Penalty::SoftHyphen,
+                ..
+            } => {
+                run += 1;
+                if run > limit {
+                    return Some(at);
+                }
+            }
+            Item::Penalty {
+                data: Penalty::HardHyphen,
+                ..
+            } => run += 1,
+            _ => run = 0,
+        }
+    }
+    None
+}
+
+/// Returns true if `c` is a space that must not be broken or adjusted.
+fn is_non_breaking_space(c: char) -> bool {
+    matches!(c, '\u{a0}' | '\u{202f}')
+}
+
+/// Returns the distance the cursor must be moved after `text` if it is a run of non-breaking
+/// spaces `width` wide. Readers draw each non-breaking space a third of an em wide regardless of
+/// the builder's whitespace metrics.
+fn non_breaking_space_correction(text: &str, width: f32, em_px: u16) -> i16 {
+    if !text.chars().all(is_non_breaking_space) {
+        return 0;
+    }
+    let reader_width = text.chars().count() as f32 * em_px as f32 / 3.0;
+    let dx = width - reader_width;
+    (if dx < 0.0 { dx - 0.5 } else { dx + 0.5 }) as i16
+}
 
 pub struct Builder<S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> {
     // Static info.
@@ -69,6 +431,26 @@ pub struct Builder<S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> {
     default_style: S,
     /// Hyphenator
     hyphenator: H,
+    /// Characters after which a line may be broken.
+    break_after: Vec<char>,
+    /// Policy for block content larger than the page.
+    overflow_policy: OverflowPolicy,
+    /// The content that has yet to be divided into pages, if pages are balanced.
+    galley: Option<Galley>,
+    /// Distance between tab stops in verbatim text, in spaces.
+    tab_width: usize,
+    /// Layout of verbatim lines that are too long for their paragraph.
+    verbatim_overflow: VerbatimOverflow,
+    /// Base direction of new paragraphs.
+    direction: Direction,
+    /// Sizing of the space between words.
+    whitespace_metrics: WhitespaceMetrics,
+    /// Minimum number of characters before a hyphenation point.
+    min_hyphen_prefix: usize,
+    /// Minimum number of characters after a hyphenation point.
+    min_hyphen_suffix: usize,
+    /// Number of hyphenated lines in a row after which further hyphens are discouraged, if any.
+    consecutive_hyphen_limit: Option<usize>,
 
     // Current style.
     style: S,
@@ -88,61 +470,168 @@ pub struct Builder<S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> {
     /// Current cursor.
     cursor: Point,
 
+    /// Open lists, innermost last.
+    lists: Vec<List>,
+    /// Open block quotes, innermost last.
+    quotes: Vec<BlockQuote>,
+    /// The block being kept with the line that follows it, if any.
+    keep: Option<Keep>,
+    /// The open row, if any.
+    row: Option<Row>,
+
     // Styles
     styles: Vec<Style>,
+    style_ids: HashMap<Style, u16>,
 
     // Output
     commands: Vec<Command<String>>,
     pages: usize,
+
+    // Logical structure
+    /// Completed top-level nodes, if structure is being recorded.
+    structure: Option<Vec<StructureNode>>,
+    /// Start offsets and completed children of open nodes, innermost last.
+    open_structure: Vec<(usize, Vec<StructureNode>)>,
 }
 
 impl<S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> Builder<S, F, H> {
     /// Create a new document builder.
-    pub fn new(bounding_box: Rectangle, fonts: F, default_style: S, hyphenator: H) -> Self {
+    pub fn new(
+        bounding_box: Rectangle,
+        fonts: F,
+        default_style: S,
+        hyphenator: H,
+        whitespace_metrics: WhitespaceMetrics,
+    ) -> Self {
         let styles = vec![Style {
             font_name: String::from(default_style.font_name()),
             em_px: default_style.em_px(),
         }];
+        let style_ids = HashMap::from([(styles[0].clone(), 0)]);
 
         let cursor = Point::new(0, default_style.em_px() as i32);
 
         let line_height = default_style.line_height();
         let baseline = default_style.baseline();
-        let whitespace_width = default_style.em_px() as f32 / 3.0;
-        let whitespace_stretch = whitespace_width / 2.0;
-        let whitespace_shrink = whitespace_width / 3.0;
+        let whitespace = whitespace_metrics.glue(&default_style);
 
         Builder {
             bounding_box,
             fonts,
             default_style: default_style.clone(),
             hyphenator,
+            break_after: vec!['/', '—', '·'],
+            overflow_policy: OverflowPolicy::Scale,
+            galley: None,
+            tab_width: 4,
+            verbatim_overflow: VerbatimOverflow::Wrap { column: None },
+            direction: Direction::Ltr,
+            whitespace_metrics,
+            min_hyphen_prefix: 2,
+            min_hyphen_suffix: 3,
+            consecutive_hyphen_limit: None,
             style: default_style,
             style_id: 0,
             line_height,
             baseline,
-            whitespace_width,
-            whitespace_stretch,
-            whitespace_shrink,
+            whitespace_width: whitespace.width,
+            whitespace_stretch: whitespace.stretch,
+            whitespace_shrink: whitespace.shrink,
             cursor,
+            lists: Vec::new(),
+            quotes: Vec::new(),
+            keep: None,
+            row: None,
             styles,
-            commands: Vec::new(),
+            style_ids,
+            // Readers may start the first page with any line metrics, so set them explicitly.
+            commands: vec![Command::SetLineMetrics {
+                height: line_height,
+                baseline,
+            }],
             pages: 0,
+            structure: None,
+            open_structure: Vec::new(),
         }
     }
 
+    /// Look up the font style for the given style without registering it.
+    pub fn font_style(&self, style: &Style) -> Option<S> {
+        self.fonts.get_style(style)
+    }
+
+    /// Set the characters after which a line may be broken. Defaults to slashes, em dashes, and
+    /// middle dots.
+    pub fn set_break_after(&mut self, chars: &[char]) {
+        self.break_after.clear();
+        self.break_after.extend_from_slice(chars);
+    }
+
+    /// Set the policy for block content larger than the page. Defaults to `OverflowPolicy::Scale`.
+    pub fn set_overflow_policy(&mut self, policy: OverflowPolicy) {
+        self.overflow_policy = policy;
+    }
+
+    /// Set how content is divided into pages. Defaults to `PageBreaking::Greedy`.
+    ///
+    /// Balanced pages are only known once a forced page break is reached or the document is
+    /// finished, so `page_count` lags behind the content while balancing. Block quote bars and
+    /// kept blocks are not supported across balanced page breaks.
+    pub fn set_page_breaking(&mut self, mode: PageBreaking) {
+        self.break_galley();
+        self.galley = match mode {
+            PageBreaking::Greedy => None,
+            PageBreaking::Balanced => Some(Galley {
+                start: self.commands.len(),
+                breaks: Vec::new(),
+            }),
+        };
+    }
+
+    /// Set the distance between tab stops in verbatim text, in spaces. Defaults to 4.
+    pub fn set_tab_width(&mut self, width: usize) {
+        self.tab_width = width.max(1);
+    }
+
+    /// Set how verbatim lines that are too long for their paragraph are laid out. Defaults to
+    /// wrapping at spaces.
+    pub fn set_verbatim_overflow(&mut self, overflow: VerbatimOverflow) {
+        self.verbatim_overflow = overflow;
+    }
+
+    /// Set the minimum number of characters before a hyphenation point. Defaults to 2.
+    pub fn set_min_hyphen_prefix(&mut self, chars: usize) {
+        self.min_hyphen_prefix = chars;
+    }
+
+    /// Set the minimum number of characters after a hyphenation point. Defaults to 3.
+    pub fn set_min_hyphen_suffix(&mut self, chars: usize) {
+        self.min_hyphen_suffix = chars;
+    }
+
+    /// Set the number of hyphenated lines in a row after which further hyphenation is
+    /// discouraged. Defaults to `None`, which places no limit on consecutive hyphens.
+    pub fn set_consecutive_hyphen_limit(&mut self, limit: Option<usize>) {
+        self.consecutive_hyphen_limit = limit;
+    }
+
+    /// Set the base direction of new paragraphs. Defaults to `Direction::Ltr`.
+    pub fn set_direction(&mut self, direction: Direction) {
+        self.direction = direction;
+    }
+
     fn get_style(&mut self, style: &Style) -> (S, u16) {
         let font_style = match self.fonts.get_style(style) {
             None => return (self.default_style.clone(), 0),
             Some(s) => s,
         };
-        for i in 0..self.styles.len() {
-            if self.styles[i] == *style {
-                return (font_style, i as u16);
-            }
+        if let Some(id) = self.style_ids.get(style) {
+            return (font_style, *id);
         }
+        let id = self.styles.len() as u16;
         self.styles.push(style.clone());
-        (font_style, (self.styles.len() - 1) as u16)
+        self.style_ids.insert(style.clone(), id);
+        (font_style, id)
     }
 
     pub fn set_style(&mut self, style: &Style) {
@@ -151,9 +640,10 @@ impl<S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> Builder<S, F, H> {
             self.line_height = style.line_height();
             self.baseline = style.baseline();
 
-            self.whitespace_width = style.em_px() as f32 / 3.0;
-            self.whitespace_stretch = self.whitespace_width / 2.0;
-            self.whitespace_shrink = self.whitespace_width / 3.0;
+            let whitespace = self.whitespace_metrics.glue(&style);
+            self.whitespace_width = whitespace.width;
+            self.whitespace_stretch = whitespace.stretch;
+            self.whitespace_shrink = whitespace.shrink;
 
             self.style = style;
             self.style_id = id;
@@ -166,12 +656,62 @@ impl<S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> Builder<S, F, H> {
         }
     }
 
-    pub fn finish(self) -> (Vec<Style>, Vec<Command<String>>) {
+    /// Record the document's logical structure alongside its commands. Structure is delimited by
+    /// calls to `begin_structure` and `end_structure`, which are ignored unless recording is
+    /// enabled.
+    pub fn record_structure(&mut self) {
+        if self.structure.is_none() {
+            self.structure = Some(Vec::new());
+        }
+    }
+
+    /// Begins a structure node at the current position.
+    pub fn begin_structure(&mut self) {
+        if self.structure.is_some() {
+            self.open_structure.push((self.commands.len(), Vec::new()));
+        }
+    }
+
+    /// Ends the innermost open structure node at the current position. The node's kind is given
+    /// here rather than in `begin_structure`, as some kinds are not known until their content
+    /// has been seen.
+    pub fn end_structure(&mut self, kind: StructureKind) {
+        let structure = match &mut self.structure {
+            None => return,
+            Some(structure) => structure,
+        };
+        let (start, children) = match self.open_structure.pop() {
+            None => return,
+            Some(open) => open,
+        };
+
+        let node = StructureNode {
+            kind,
+            commands: start..self.commands.len(),
+            children,
+        };
+        match self.open_structure.last_mut() {
+            Some((_, siblings)) => siblings.push(node),
+            None => structure.push(node),
+        }
+    }
+
+    /// Takes the recorded structure, if any. Nodes that are still open are discarded. Any
+    /// content that is waiting to be balanced is divided into pages first.
+    pub fn take_structure(&mut self) -> Option<Vec<StructureNode>> {
+        self.break_galley();
+        self.open_structure.clear();
+        self.structure.take()
+    }
+
+    pub fn finish(mut self) -> (Vec<Style>, Vec<Command<String>>) {
+        self.break_galley();
         (self.styles, self.commands)
     }
 
+    /// Returns true if nothing has been added to the document.
     pub fn is_empty(&self) -> bool {
-        self.commands.is_empty()
+        matches!(self.commands[..], [Command::SetLineMetrics { .. }])
     }
 
     pub fn page_count(&self) -> usize {
@@ -181,17 +721,23 @@ impl<S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> Builder<S, F, H> {
     pub fn paragraph<'a>(self, options: Option<ParagraphOptions>) -> ParagraphBuilder<'a, S, F, H> {
         let style = self.style.clone();
         let style_id = self.style_id;
+        let direction = self.direction;
         let whitespace_width = self.whitespace_width;
         let whitespace_stretch = self.whitespace_stretch;
         let whitespace_shrink = self.whitespace_shrink;
 
-        let options = options.unwrap_or(ParagraphOptions {
+        let mut options = options.unwrap_or(ParagraphOptions {
             align: Align::Justify,
+            justify_last_line: false,
             margin_bottom_px: 0.0,
             margin_left_px: 0.0,
             margin_right_px: 0.0,
             margin_top_px: 0.0,
         });
+        // Paragraphs inside lists and block quotes are indented by their nesting depth.
+        let (margin_left, margin_right) = self.margins();
+        options.margin_left_px += margin_left;
+        options.margin_right_px += margin_right;
 
         ParagraphBuilder {
             builder: self,
@@ -203,43 +749,697 @@ impl<S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> Builder<S, F, H> {
             whitespace_shrink,
             breaks: Vec::new(),
             items: Vec::new(),
+            text_indent: TextIndent::default(),
+            direction,
+            drop_cap: None,
+            line_height: None,
+            small_caps: false,
+            verbatim_column: 0,
+            verbatim_breaks: 0,
+            verbatim_spaces: 0,
+        }
+    }
+
+    /// Begins a list nested inside any currently open lists.
+    pub fn begin_list(&mut self, kind: ListKind) {
+        self.begin_list_with_indent(kind, self.indent_width());
+    }
+
+    /// Begins a list nested inside any currently open lists whose items are indented by `indent`
+    /// pixels.
+    pub fn begin_list_with_indent(&mut self, kind: ListKind, indent: f32) {
+        self.lists.push(List {
+            kind,
+            next: 1,
+            indent,
+        });
+    }
+
+    /// Ends the innermost open list.
+    pub fn end_list(&mut self) {
+        self.lists.pop();
+    }
+
+    /// Begins a paragraph for the next item of the innermost open list. The item's marker hangs
+    /// in the list's indent to the left of its first line.
+    pub fn list_item<'a>(
+        mut self,
+        options: Option<ParagraphOptions>,
+    ) -> ParagraphBuilder<'a, S, F, H> {
+        let (text, hang) = match self.lists.last_mut() {
+            None => return self.paragraph(options),
+            Some(list) => (
+                match list.kind {
+                    ListKind::Unordered => String::from("•"),
+                    ListKind::Ordered => {
+                        let n = list.next;
+                        list.next += 1;
+                        alloc::format!("{n}.")
+                    }
+                },
+                list.indent,
+            ),
+        };
+
+        let mut paragraph = self.paragraph(options);
+        let metrics = paragraph.style.measure_string(&text);
+        let width = metrics.bounding_box.size.width as f32;
+        paragraph.items.push(Item::Box {
+            width: 0.0,
+            data: Box::Marker { text, width, hang },
+        });
+        paragraph
+    }
+
+    /// Begins a block quote nested inside any currently open lists or block quotes. Paragraphs
+    /// in the quote are indented on both sides. If `bar` is true, a vertical bar is drawn down
+    /// the left edge of the quote.
+    pub fn begin_blockquote(&mut self, bar: bool) {
+        self.begin_blockquote_with_margin(bar, self.indent_width());
+    }
+
+    /// Begins a block quote nested inside any currently open lists or block quotes whose
+    /// paragraphs are indented by `margin` pixels on both sides.
+    pub fn begin_blockquote_with_margin(&mut self, bar: bool, margin: f32) {
+        let (margin_left, _) = self.margins();
+        let bar = if bar {
+            Some(Point::new(margin_left as i32, self.cursor.y))
+        } else {
+            None
+        };
+        self.quotes.push(BlockQuote { margin, bar });
+    }
+
+    /// Ends the innermost open block quote.
+    pub fn end_blockquote(&mut self) {
+        if let Some(BlockQuote { bar: Some(top), .. }) = self.quotes.pop() {
+            draw_quote_bar(&mut self.commands, self.cursor, top);
         }
     }
 
+    /// The left indent added for each level of list or block quote nesting.
+    fn indent_width(&self) -> f32 {
+        4.0 * self.whitespace_width
+    }
+
+    /// The left and right margins added by open lists and block quotes.
+    fn margins(&self) -> (f32, f32) {
+        let quotes: f32 = self.quotes.iter().map(|q| q.margin).sum();
+        let lists: f32 = self.lists.iter().map(|l| l.indent).sum();
+        (lists + quotes, quotes)
+    }
+
+    /// The width available to paragraphs inside the currently open lists and block quotes.
+    pub fn content_width(&self) -> f32 {
+        let (margin_left, margin_right) = self.margins();
+        self.bounding_box.size.width as f32 - margin_left - margin_right
+    }
+
+    /// Begins a row of blocks laid out side by side, such as the cells of a table row. Each block
+    /// is begun with `row_cell`.
+    pub fn begin_row(&mut self) {
+        self.row = Some(Row {
+            top: self.cursor.y,
+            bottom: self.cursor.y,
+            page: self.pages,
+        });
+    }
+
+    /// Begins a left-aligned paragraph at the top of the open row that spans `width` pixels
+    /// starting `left` pixels from the left edge of the content. If an earlier block in the row
+    /// continued onto a new page, the paragraph begins at the top of that page instead.
+    pub fn row_cell<'a>(mut self, left: f32, width: f32) -> ParagraphBuilder<'a, S, F, H> {
+        if let Some(row) = &mut self.row {
+            if row.page != self.pages {
+                row.top = 0;
+                row.bottom = 0;
+                row.page = self.pages;
+            }
+            row.bottom = row.bottom.max(self.cursor.y);
+            if self.cursor.y != row.top {
+                self.cursor.y = row.top;
+                self.commands.push(Command::SetCursor {
+                    x: self.cursor.x as u16,
+                    y: self.cursor.y as u16,
+                });
+            }
+        }
+
+        let right = self.content_width() - left - width;
+        self.paragraph(Some(ParagraphOptions {
+            align: Align::Left,
+            justify_last_line: false,
+            margin_bottom_px: 0.0,
+            margin_left_px: left,
+            margin_right_px: right.max(0.0),
+            margin_top_px: 0.0,
+        }))
+    }
+
+    /// Ends the open row, continuing below its tallest block.
+    pub fn end_row(&mut self) {
+        let row = match self.row.take() {
+            None => return,
+            Some(row) => row,
+        };
+        let bottom = if row.page == self.pages {
+            row.bottom.max(self.cursor.y)
+        } else {
+            self.cursor.y
+        };
+        if bottom != self.cursor.y {
+            self.cursor.y = bottom;
+            self.commands.push(Command::SetCursor {
+                x: self.cursor.x as u16,
+                y: self.cursor.y as u16,
+            });
+        }
+    }
+
+    /// Keeps the next paragraph on the same page as the first line laid out after it. If a page
+    /// break would separate them, the paragraph is moved to the start of the next page instead.
+    pub fn keep_with_next(&mut self) {
+        self.keep = Some(Keep {
+            start: self.commands.len(),
+            cursor: self.cursor,
+            style_id: self.style_id,
+            line_height: self.line_height,
+            baseline: self.baseline,
+            done: false,
+        });
+    }
+
+    /// Releases the kept block, if any, once the line that follows it has been laid out.
+    fn end_keep(&mut self) {
+        if matches!(self.keep, Some(Keep { done: true, .. })) {
+            self.keep = None;
+        }
+    }
+
+    /// Breaks the page before the kept block, if any, moving the block to the start of the next
+    /// page. Returns false if there is no kept block or if the block and the `px` pixels below it
+    /// would not fit on the new page.
+    fn break_before_kept(&mut self, px: i32) -> bool {
+        let keep = match self.keep.take() {
+            None => return false,
+            Some(keep) => keep,
+        };
+        let height = self.cursor.y - keep.cursor.y;
+        if keep.cursor.y == 0 || height + px > self.bounding_box.size.height as i32 {
+            return false;
+        }
+
+        let block = self.commands.split_off(keep.start);
+        let cursor = self.cursor;
+        self.cursor = keep.cursor;
+        self.page_break();
+        if self.style_id != keep.style_id {
+            self.commands.push(Command::SetStyle { s: keep.style_id });
+        }
+        if self.line_height != keep.line_height || self.baseline != keep.baseline {
+            self.commands.push(Command::SetLineMetrics {
+                height: keep.line_height,
+                baseline: keep.baseline,
+            });
+        }
+
+        // Commands that were recorded as part of the block's structure follow the page break.
+        self.shift_structure(keep.start, self.commands.len() - keep.start);
+
+        let dy = keep.cursor.y as u16;
+        self.commands
+            .extend(block.into_iter().map(|command| match command {
+                Command::SetCursor { x, y } => Command::SetCursor {
+                    x,
+                    y: y.saturating_sub(dy),
+                },
+                command => command,
+            }));
+        self.cursor = Point::new(cursor.x, height);
+        true
+    }
+
+    /// Shifts the recorded structure's command offsets at or after `from` by `by`.
+    fn shift_structure(&mut self, from: usize, by: usize) {
+        fn shift(nodes: &mut [StructureNode], from: usize, by: usize) {
+            for node in nodes {
+                if node.commands.start >= from {
+                    node.commands.start += by;
+                }
+                if node.commands.end > from {
+                    node.commands.end += by;
+                }
+                shift(&mut node.children, from, by);
+            }
+        }
+
+        if let Some(structure) = &mut self.structure {
+            shift(structure, from, by);
+        }
+        for (start, children) in &mut self.open_structure {
+            if *start >= from {
+                *start += by;
+            }
+            shift(children, from, by);
+        }
+    }
+
+    /// Records the command just pushed as a place at which the galley may be broken, if pages
+    /// are being balanced. `end` is the bottom of the content before the command.
+    fn galley_break(&mut self, end: i32) {
+        // The blocks of a row overlap, so the galley is only broken between rows.
+        if self.row.is_some() {
+            return;
+        }
+        let galley = match &mut self.galley {
+            None => return,
+            Some(galley) => galley,
+        };
+        galley.breaks.push(GalleyBreak {
+            command: self.commands.len() - 1,
+            end,
+            resume: self.cursor.y,
+            style_id: self.style_id,
+            line_height: self.line_height,
+            baseline: self.baseline,
+        });
+        if self.cursor.y > GALLEY_PAGES * self.bounding_box.size.height as i32 {
+            self.break_galley();
+        }
+    }
+
+    /// Divides the galley into balanced pages, if pages are being balanced. The content after the
+    /// last break stays on the galley as the current page.
+    fn break_galley(&mut self) {
+        let galley = match self.galley.take() {
+            None => return,
+            Some(galley) => galley,
+        };
+        let chosen = balance_pages(
+            &galley.breaks,
+            self.cursor.y,
+            self.bounding_box.size.height as i32,
+        );
+        let last = match chosen.last() {
+            None => {
+                self.galley = Some(galley);
+                return;
+            }
+            Some(last) => *last,
+        };
+
+        // Each break replaces a command with a page break followed by the style and line metrics
+        // in effect at the break.
+        for &i in chosen.iter().rev() {
+            self.shift_structure(galley.breaks[i].command + 1, 2);
+        }
+
+        let tail = self.commands.split_off(galley.start);
+        let mut pending = chosen.iter().map(|&i| &galley.breaks[i]).peekable();
+        let mut dy = 0;
+        for (offset, command) in tail.into_iter().enumerate() {
+            match pending.next_if(|b| b.command == galley.start + offset) {
+                Some(b) => {
+                    self.commands.push(Command::PageBreak);
+                    self.commands.push(Command::SetStyle { s: b.style_id });
+                    self.commands.push(Command::SetLineMetrics {
+                        height: b.line_height,
+                        baseline: b.baseline,
+                    });
+                    self.pages += 1;
+                    dy = b.resume;
+                }
+                None => self.commands.push(match command {
+                    Command::SetCursor { x, y } => Command::SetCursor {
+                        x,
+                        y: (y as i32 - dy).max(0) as u16,
+                    },
+                    command => command,
+                }),
+            }
+        }
+        self.cursor.y -= dy;
+        for quote in &mut self.quotes {
+            if let Some(top) = &mut quote.bar {
+                top.y = (top.y - dy).max(0);
+            }
+        }
+        self.keep = None;
+
+        // The breaks after the last page break remain available.
+        let shift = 2 * chosen.len();
+        let start = galley.breaks[last].command + shift + 1;
+        let breaks = galley
+            .breaks
+            .into_iter()
+            .skip(last + 1)
+            .map(|b| GalleyBreak {
+                command: b.command + shift,
+                end: b.end - dy,
+                resume: b.resume - dy,
+                ..b
+            })
+            .collect();
+        self.galley = Some(Galley { start, breaks });
+    }
+
     pub fn advance_vertical(&mut self, px: f32) {
         if px != 0.0 {
             let remaining = self.bounding_box.size.height as i32 - self.cursor.y;
-            if remaining < px as i32 {
+            if self.galley.is_none() && remaining < px as i32 && !self.break_before_kept(px as i32)
+            {
                 self.page_break();
             } else {
+                let end = self.cursor.y;
                 self.cursor += Point::new(0, px as i32);
                 self.commands.push(Command::SetCursor {
                     x: self.cursor.x as u16,
                     y: self.cursor.y as u16,
                 });
+                self.galley_break(end);
             }
         }
     }
 
     pub fn advance_line(&mut self) {
         let remaining = self.bounding_box.size.height as i32 - self.cursor.y;
-        if remaining < self.line_height as i32 {
+        if self.galley.is_none()
+            && remaining < self.line_height as i32
+            && !self.break_before_kept(self.line_height as i32)
+        {
             self.page_break();
         } else {
             self.commands.push(Command::LineBreak);
             self.cursor += Point::new(0, self.line_height as i32);
+            self.galley_break(self.cursor.y);
+        }
+    }
+
+    /// Draws a horizontal rule across the width of the content inside any open lists and block
+    /// quotes, centered vertically within a line. The rule is 2px high unless otherwise specified.
+    pub fn horizontal_rule(&mut self, height: Option<u16>) {
+        let height = height.unwrap_or(2);
+        let offset = self.line_height.saturating_sub(height) as i16 / 2;
+        let (margin_left, _) = self.margins();
+        let dx = margin_left as i16;
+
+        self.end_keep();
+
+        self.commands.push(Command::MoveBy { dx, dy: offset });
+        self.commands.push(Command::FillRect {
+            w: self.content_width() as u16,
+            h: height,
+        });
+        self.commands.push(Command::MoveBy {
+            dx: -dx,
+            dy: -offset,
+        });
+        self.advance_line();
+    }
+
+    /// Draws the image at `data_offset` in the document's image table as a block at the start of
+    /// the current line, moving to a new page first if the image does not fit on this one.
+    /// Images larger than the page are handled according to the builder's overflow policy.
+    pub fn image(&mut self, data_offset: u32, width: u16, height: u16) {
+        self.place_image(data_offset, width, height, None);
+    }
+
+    /// Draws the image at `data_offset` like [`image`](Self::image), but centered between the
+    /// edges of the content box inset by `margin_left_px` and `margin_right_px`.
+    pub fn centered_image(
+        &mut self,
+        data_offset: u32,
+        width: u16,
+        height: u16,
+        margin_left_px: f32,
+        margin_right_px: f32,
+    ) {
+        self.place_image(
+            data_offset,
+            width,
+            height,
+            Some((margin_left_px, margin_right_px)),
+        );
+    }
+
+    /// Draws an image as a block, centered between the given insets if any. An image that
+    /// follows `keep_with_next` is the kept block, and travels with the line after it.
+    fn place_image(
+        &mut self,
+        data_offset: u32,
+        width: u16,
+        height: u16,
+        center: Option<(f32, f32)>,
+    ) {
+        let page_width = self.bounding_box.size.width as f32;
+        let page_height = self.bounding_box.size.height as i32;
+
+        let (width, height) = match self.overflow_policy {
+            OverflowPolicy::Scale if width as f32 > page_width || height as i32 > page_height => {
+                let scale = f32::min(
+                    page_width / width as f32,
+                    page_height as f32 / height as f32,
+                );
+                (
+                    (width as f32 * scale) as u16,
+                    (height as f32 * scale) as u16,
+                )
+            }
+            _ => (width, height),
+        };
+
+        self.end_keep();
+        if self.galley.is_none()
+            && self.cursor.y > 0
+            && self.cursor.y + height as i32 > page_height
+            && self.overflow_policy != OverflowPolicy::Overflow
+        {
+            self.page_break();
+        }
+
+        if let Some((inset_left, inset_right)) = center {
+            let (margin_left, margin_right) = self.margins();
+            let available = page_width - margin_left - margin_right - inset_left - inset_right;
+            let dx = margin_left + inset_left + (available - width as f32) / 2.0;
+            if dx >= 1.0 {
+                self.commands.push(Command::Advance { dx: dx as u16 });
+            }
+        }
+        self.commands.push(Command::DrawImage {
+            width,
+            height,
+            data_offset,
+        });
+
+        // Continue below the image. A kept image stays on this page until the line after it
+        // shows whether both fit.
+        self.cursor.y += height as i32;
+        if let Some(keep) = &mut self.keep {
+            keep.done = true;
+        }
+        if self.galley.is_none() && self.cursor.y >= page_height && self.keep.is_none() {
+            self.page_break();
+        } else {
+            self.commands.push(Command::SetCursor {
+                x: self.cursor.x as u16,
+                y: self.cursor.y as u16,
+            });
+            self.galley_break(self.cursor.y);
+        }
+    }
+
+    /// Marks the current position as the location of the anchor with the given ID.
+    pub fn anchor(&mut self, id: u16) {
+        self.commands.push(Command::Anchor { id });
+    }
+
+    /// Appends a command to the output at the current position.
+    ///
+    /// The builder does not interpret the command, so commands that move the cursor or change the
+    /// current style or line metrics will leave the builder's bookkeeping out of sync with the
+    /// output.
+    pub fn push_raw(&mut self, command: Command<String>) {
+        self.commands.push(command);
+    }
+
+    pub fn page_break(&mut self) {
+        self.break_galley();
+
+        // A page break within a kept block or between it and the line that follows it ends the
+        // keep.
+        self.keep = None;
+
+        // Finish the bars of any open block quotes on this page; they resume at the top of the
+        // next.
+        for quote in &mut self.quotes {
+            if let Some(top) = quote.bar {
+                draw_quote_bar(&mut self.commands, self.cursor, top);
+                quote.bar = Some(Point::new(top.x, 0));
+            }
+        }
+
+        self.commands.push(Command::PageBreak);
+        self.pages += 1;
+        self.commands.push(Command::SetStyle { s: self.style_id });
+        self.commands.push(Command::SetLineMetrics {
+            height: self.line_height,
+            baseline: self.baseline,
+        });
+        self.cursor = Point::new(0, 0);
+
+        if let Some(galley) = &mut self.galley {
+            galley.start = self.commands.len();
+            galley.breaks.clear();
+        }
+    }
+}
+
+/// Returns true if `c` is written right-to-left.
+fn is_rtl(c: char) -> bool {
+    matches!(
+        c,
+        '\u{0590}'..='\u{08ff}' | '\u{fb1d}'..='\u{fdff}' | '\u{fe70}'..='\u{feff}'
+    )
+}
+
+/// Returns the characters of `word` in visual order. Words that contain right-to-left characters
+/// are reversed, except for runs of ASCII letters and digits, which keep their order.
+fn visual_word(word: &str) -> String {
+    if !word.chars().any(is_rtl) {
+        return String::from(word);
+    }
+
+    let mut chars: Vec<char> = word.chars().rev().collect();
+    let mut start = 0;
+    while start < chars.len() {
+        let len = chars[start..]
+            .iter()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .count();
+        chars[start..start + len].reverse();
+        start += len.max(1);
+    }
+    chars.into_iter().collect()
+}
+
+/// Reorders the commands of a right-to-left line into visual order by reversing the order of its
+/// words. `style` is the style in effect at the start of the line; the style in effect at the end
+/// of the line is restored afterwards.
+///
+/// Indents are dropped, as they are accounted for when the line is aligned. Other commands,
+/// including list markers, are kept at the start of the line and are not mirrored.
+fn visual_order(commands: Vec<Command<String>>, style: u16) -> Vec<Command<String>> {
+    let mut words: Vec<(u16, String)> = Vec::new();
+    let mut out = Vec::new();
+    let mut current = style;
+    for command in commands {
+        match command {
+            Command::SetStyle { s } => current = s,
+            Command::Show { str } => {
+                for (i, word) in str.split(' ').enumerate() {
+                    if i > 0 {
+                        words.push((current, String::from(" ")));
+                    }
+                    if !word.is_empty() {
+                        words.push((current, visual_word(word)));
+                    }
+                }
+            }
+            Command::Advance { .. } => {}
+            command => out.push(command),
+        }
+    }
+
+    let end = current;
+    let mut current = style;
+    let mut text = String::new();
+    for (s, word) in words.into_iter().rev() {
+        if s != current {
+            if !text.is_empty() {
+                out.push(Command::Show {
+                    str: core::mem::take(&mut text),
+                });
+            }
+            out.push(Command::SetStyle { s });
+            current = s;
+        }
+        text.push_str(&word);
+    }
+    if !text.is_empty() {
+        out.push(Command::Show { str: text });
+    }
+    if current != end {
+        out.push(Command::SetStyle { s: end });
+    }
+    out
+}
+
+/// Chooses the breaks that divide a galley ending at `end` into pages of the given height,
+/// returning the indices of the chosen breaks in order. Each page, including the last, costs the
+/// square of its unused height; a page that is too tall costs more than any page that fits.
+fn balance_pages(breaks: &[GalleyBreak], end: i32, height: i32) -> Vec<usize> {
+    let overfull = 4 * height as i64 * height as i64;
+
+    // best[i] is the cost of the best layout whose last page ends at the i'th break, along with
+    // the break that precedes that page. The final entry is for the end of the galley.
+    let mut best: Vec<(i64, Option<usize>)> = Vec::with_capacity(breaks.len() + 1);
+    for i in 0..=breaks.len() {
+        let page_end = match breaks.get(i) {
+            None => end,
+            Some(b) => b.end,
+        };
+
+        // Consider each earlier break as the start of the page, latest first. Earlier starts
+        // only make the page taller.
+        let mut choice: Option<(i64, Option<usize>)> = None;
+        for k in (0..=i).rev() {
+            let (page_start, cost, prev) = match k {
+                0 => (0, 0, None),
+                k => (breaks[k - 1].resume, best[k - 1].0, Some(k - 1)),
+            };
+            let used = page_end - page_start;
+            if used > height {
+                if choice.is_none() {
+                    choice = Some((cost + overfull, prev));
+                }
+                break;
+            }
+            let slack = (height - used) as i64;
+            let cost = cost + slack * slack;
+            match choice {
+                Some((c, _)) if c <= cost => {}
+                _ => choice = Some((cost, prev)),
+            }
         }
+        best.push(choice.unwrap_or((0, None)));
     }
 
-    pub fn page_break(&mut self) {
-        self.commands.push(Command::PageBreak);
-        self.pages += 1;
-        self.commands.push(Command::SetStyle { s: self.style_id });
-        self.commands.push(Command::SetLineMetrics {
-            height: self.line_height,
-            baseline: self.baseline,
+    let mut chosen = Vec::new();
+    let mut next = best[breaks.len()].1;
+    while let Some(i) = next {
+        chosen.push(i);
+        next = best[i].1;
+    }
+    chosen.reverse();
+    chosen
+}
+
+/// Draws a block quote bar from `top` down to the line at `cursor`, then returns to `cursor`.
+fn draw_quote_bar(commands: &mut Vec<Command<String>>, cursor: Point, top: Point) {
+    let height = cursor.y - top.y;
+    if height > 0 {
+        commands.push(Command::SetCursor {
+            x: top.x as u16,
+            y: top.y as u16,
+        });
+        commands.push(Command::FillRect {
+            w: QUOTE_BAR_WIDTH,
+            h: height as u16,
+        });
+        commands.push(Command::SetCursor {
+            x: cursor.x as u16,
+            y: cursor.y as u16,
         });
-        self.cursor = Point::new(0, 0);
     }
 }
 
@@ -265,15 +1465,45 @@ pub struct ParagraphBuilder<'a, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator
 
     // Items
     items: Vec<Item<Box<'a>, (), Penalty>>,
+
+    /// Line indentation.
+    text_indent: TextIndent,
+    /// Base direction.
+    direction: Direction,
+    /// Drop cap, if any.
+    drop_cap: Option<DropCap>,
+    /// Height of the paragraph's lines in place of the height given by their styles, if any.
+    line_height: Option<u16>,
+    /// Synthesize small capitals from lowercase letters.
+    small_caps: bool,
+
+    // Verbatim text state
+    /// Column of the next character on the current line.
+    verbatim_column: usize,
+    /// Line breaks that have yet to be pushed.
+    verbatim_breaks: usize,
+    /// Spaces that have yet to be pushed.
+    verbatim_spaces: usize,
 }
 
 impl<'a, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> ParagraphBuilder<'a, S, F, H> {
+    /// Look up the font style for the given style without registering it.
+    pub fn font_style(&self, style: &Style) -> Option<S> {
+        self.builder.font_style(style)
+    }
+
+    /// The number of pages started before the paragraph.
+    pub fn page_count(&self) -> usize {
+        self.builder.page_count()
+    }
+
     pub fn set_style(&mut self, style: &Style) {
         let (style, id) = self.builder.get_style(style);
         if id != self.style_id {
-            self.whitespace_width = style.em_px() as f32 / 3.0;
-            self.whitespace_stretch = self.whitespace_width / 2.0;
-            self.whitespace_shrink = self.whitespace_width / 3.0;
+            let whitespace = self.builder.whitespace_metrics.glue(&style);
+            self.whitespace_width = whitespace.width;
+            self.whitespace_stretch = whitespace.stretch;
+            self.whitespace_shrink = whitespace.shrink;
 
             self.items.push(Item::Box {
                 width: 0.0,
@@ -281,6 +1511,8 @@ impl<'a, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> ParagraphBuilder<'a,
                     id,
                     line_height: style.line_height(),
                     baseline: style.baseline(),
+                    em_px: style.em_px(),
+                    whitespace,
                 },
             });
 
@@ -293,6 +1525,17 @@ impl<'a, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> ParagraphBuilder<'a,
         self.items.is_empty()
     }
 
+    /// Appends a command to the paragraph at the current position. The command occupies no space
+    /// in the line.
+    ///
+    /// See `Builder::push_raw` for caveats.
+    pub fn push_raw(&mut self, command: Command<String>) {
+        self.items.push(Item::Box {
+            width: 0.0,
+            data: Box::Raw { command },
+        });
+    }
+
     pub fn indent(&mut self, size: f32) {
         self.items.push(Item::Box {
             width: size * self.whitespace_width,
@@ -307,6 +1550,89 @@ impl<'a, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> ParagraphBuilder<'a,
         });
     }
 
+    /// Sets the base direction of the paragraph's text.
+    pub fn set_direction(&mut self, direction: Direction) {
+        self.direction = direction;
+    }
+
+    /// Sets whether lowercase letters are drawn as small capitals: capitals scaled down from the
+    /// current style. Letters that are already uppercase are drawn at full size.
+    pub fn set_small_caps(&mut self, small_caps: bool) {
+        self.small_caps = small_caps;
+    }
+
+    /// Sets the height of each of the paragraph's lines, overriding the line heights of its
+    /// styles. The extra space, if any, is split evenly above and below the text. `None` restores
+    /// the styles' line heights.
+    pub fn set_line_height(&mut self, line_height: Option<u16>) {
+        self.line_height = line_height;
+    }
+
+    /// Enlarges the first letter of the paragraph so that it spans `lines` lines. The letter is
+    /// drawn to the left of the first `lines` lines, which are narrowed to make room for it. Must
+    /// be called before any content is added.
+    pub fn drop_cap(&mut self, lines: u8) {
+        if lines > 1 {
+            self.drop_cap = Some(DropCap { lines, cap: None });
+        }
+    }
+
+    /// Sets the cap of a pending drop cap to the first letter of `word`, returning the rest of
+    /// the word.
+    fn take_drop_cap<'w>(&mut self, word: &'w str) -> &'w str {
+        let lines = match &self.drop_cap {
+            Some(DropCap { lines, cap: None }) => *lines,
+            _ => return word,
+        };
+        let first = match word.chars().next() {
+            None => return word,
+            Some(c) => c,
+        };
+
+        // Size the cap so that it reaches from the top of the first line to the baseline of the
+        // last.
+        let line_height = self.style.line_height() as f32;
+        let ascent = line_height - self.style.baseline() as f32;
+        let height = (lines - 1) as f32 * line_height + ascent;
+        let em_px = height * self.style.em_px() as f32 / ascent;
+        let (style, id) = self.builder.get_style(&Style {
+            font_name: String::from(self.style.font_name()),
+            em_px: em_px as u16,
+        });
+
+        let (text, rest) = word.split_at(first.len_utf8());
+        let width = style.measure_string(text).bounding_box.size.width as f32;
+        self.drop_cap = Some(DropCap {
+            lines,
+            cap: Some((String::from(text), id, width + self.whitespace_width)),
+        });
+        rest
+    }
+
+    /// Sets the indentation of the paragraph's lines. Must be called before any content is added.
+    ///
+    /// The first line, and each line that follows a hard break if `each_line` is set, is indented
+    /// by `px`. If `hanging` is set, every other line is indented instead.
+    pub fn set_text_indent(&mut self, indent: TextIndent) {
+        self.text_indent = indent;
+        if indent.hanging {
+            // Indent every line via the margin, then pull the first line back out.
+            self.options.margin_left_px += indent.px;
+        }
+        self.push_text_indent();
+    }
+
+    /// Pushes the indent for a line that starts the paragraph or follows a hard break.
+    fn push_text_indent(&mut self) {
+        let TextIndent { px, hanging, .. } = self.text_indent;
+        if px != 0.0 {
+            self.items.push(Item::Box {
+                width: if hanging { -px } else { px },
+                data: Box::Indent,
+            });
+        }
+    }
+
     pub fn hard_line_break(&mut self) {
         // Append glue for a ragged-right terminator.
         self.items.push(Item::Glue {
@@ -321,6 +1647,9 @@ impl<'a, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> ParagraphBuilder<'a,
             flagged: true,
             data: Penalty::HardBreak,
         });
+        if self.text_indent.each_line {
+            self.push_text_indent();
+        }
     }
 
     pub fn soft_line_break(&mut self) {
@@ -336,63 +1665,198 @@ impl<'a, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> ParagraphBuilder<'a,
         });
     }
 
-    pub fn text(&mut self, s: &'a str) {
-        for word in s.split_word_bounds() {
-            self.word(word);
+    pub fn text<T: Into<Cow<'a, str>>>(&mut self, s: T) {
+        match s.into() {
+            Cow::Borrowed(s) => {
+                for word in s.split_word_bounds() {
+                    self.word(word);
+                }
+            }
+            Cow::Owned(s) => {
+                for word in s.split_word_bounds() {
+                    self.word(String::from(word));
+                }
+            }
         }
     }
 
-    pub fn word(&mut self, word: &'a str) {
-        let is_whitespace = word.chars().all(|c: char| c.is_whitespace());
+    pub fn word<W: Into<Cow<'a, str>>>(&mut self, word: W) {
+        let word = word.into();
+        let is_whitespace = word
+            .chars()
+            .all(|c: char| c.is_whitespace() && !is_non_breaking_space(c));
         if is_whitespace {
             self.whitespace();
+        } else if self.small_caps && word.chars().any(char::is_lowercase) {
+            self.small_caps_word(&word);
+        } else if word.chars().all(is_non_breaking_space) {
+            // Non-breaking spaces are boxes, so a line can never be broken at them.
+            self.items.push(Item::Box {
+                width: word.chars().count() as f32 * self.whitespace_width,
+                data: Box::Word { text: word },
+            });
         } else {
-            self.builder.hyphenator.hyphenate(word, &mut self.breaks);
+            let start = word.len() - self.take_drop_cap(&word).len();
+            let word = substring(&word, start..word.len());
+            if word.is_empty() {
+                return;
+            }
+
+            self.builder.hyphenator.hyphenate(&word, &mut self.breaks);
+
+            // Drop breaks that would leave too few characters on either side of the hyphen.
+            let (min_prefix, min_suffix) = (
+                self.builder.min_hyphen_prefix,
+                self.builder.min_hyphen_suffix,
+            );
+            let len = word.chars().count();
+            self.breaks.retain(|offset| {
+                let prefix = word[..*offset].chars().count();
+                prefix >= min_prefix && len - prefix >= min_suffix
+            });
+
             let word = if self.breaks.is_empty() {
                 word
             } else {
+                // A line broken at a soft hyphen ends with a hyphen, so the break has its width.
+                let hyphen_width = self.style.measure_string("-").bounding_box.size.width;
                 let mut last = 0;
                 for offset in &self.breaks {
-                    let sub = &word[last..*offset];
-                    let metrics = self.style.measure_string(sub);
+                    let sub = substring(&word, last..*offset);
+                    let metrics = self.style.measure_string(&sub);
                     let width = metrics.bounding_box.size.width;
                     self.items.push(Item::Box {
                         width: width as f32,
                         data: Box::Word { text: sub },
                     });
                     self.items.push(Item::Penalty {
-                        width: 0.0,
+                        width: hyphen_width as f32,
                         cost: 50.0,
                         flagged: true,
                         data: Penalty::SoftHyphen,
                     });
                     last = *offset;
                 }
-                &word[last..]
+                substring(&word, last..word.len())
             };
 
-            let metrics = self.style.measure_string(word);
+            let metrics = self.style.measure_string(&word);
             let width = metrics.bounding_box.size.width;
+            let ends_with_break = word.ends_with(self.builder.break_after.as_slice());
+            let is_hyphen = word == "-" || word == "–";
             self.items.push(Item::Box {
                 width: width as f32,
                 data: Box::Word { text: word },
             });
-            if word == "-" || word == "–" {
+            if is_hyphen {
                 self.items.push(Item::Penalty {
                     width: 0.0,
                     cost: 50.0,
                     flagged: true,
                     data: Penalty::HardHyphen,
                 });
+            } else if ends_with_break {
+                self.break_after();
+            }
+        }
+    }
+
+    /// Appends a word that contains lowercase letters as small capitals. The word is split into
+    /// runs of lowercase and other characters; lowercase runs are uppercased and shown in a
+    /// scaled-down copy of the current style. Words in small capitals are not hyphenated.
+    fn small_caps_word(&mut self, word: &str) {
+        let start = word.len() - self.take_drop_cap(word).len();
+        let word = &word[start..];
+
+        let (small, small_id) = self.builder.get_style(&Style {
+            font_name: String::from(self.style.font_name()),
+            em_px: (self.style.em_px() as f32 * SMALL_CAPS_SCALE) as u16,
+        });
+        let set_small = Box::SetStyle {
+            id: small_id,
+            line_height: small.line_height(),
+            baseline: small.baseline(),
+            em_px: small.em_px(),
+            whitespace: self.builder.whitespace_metrics.glue(&small),
+        };
+        let set_regular = Box::SetStyle {
+            id: self.style_id,
+            line_height: self.style.line_height(),
+            baseline: self.style.baseline(),
+            em_px: self.style.em_px(),
+            whitespace: Glue {
+                width: self.whitespace_width,
+                stretch: self.whitespace_stretch,
+                shrink: self.whitespace_shrink,
+            },
+        };
+
+        let mut rest = word;
+        while let Some(first) = rest.chars().next() {
+            let lowercase = first.is_lowercase();
+            let end = rest
+                .char_indices()
+                .find(|(_, c)| c.is_lowercase() != lowercase)
+                .map_or(rest.len(), |(i, _)| i);
+            let (run, tail) = rest.split_at(end);
+            rest = tail;
+
+            if lowercase {
+                let text = run.to_uppercase();
+                let width = small.measure_string(&text).bounding_box.size.width;
+                self.items.push(Item::Box {
+                    width: 0.0,
+                    data: set_small.clone(),
+                });
+                self.items.push(Item::Box {
+                    width: width as f32,
+                    data: Box::Word { text: text.into() },
+                });
+                self.items.push(Item::Box {
+                    width: 0.0,
+                    data: set_regular.clone(),
+                });
+            } else {
+                let width = self.style.measure_string(run).bounding_box.size.width;
+                self.items.push(Item::Box {
+                    width: width as f32,
+                    data: Box::Word {
+                        text: String::from(run).into(),
+                    },
+                });
             }
         }
+
+        if word.ends_with(self.builder.break_after.as_slice()) {
+            self.break_after();
+        }
+    }
+
+    /// Appends text as a single box. The text is not hyphenated, and the line is never broken
+    /// within it.
+    pub fn unbreakable(&mut self, text: &'a str) {
+        let metrics = self.style.measure_string(text);
+        let width = metrics.bounding_box.size.width;
+        self.items.push(Item::Box {
+            width: width as f32,
+            data: Box::Word { text: text.into() },
+        });
     }
 
     pub fn char(&mut self, c: char) {
-        if c.is_whitespace() {
+        if is_non_breaking_space(c) {
+            self.items.push(Item::Box {
+                width: self.whitespace_width,
+                data: Box::Char { text: c },
+            });
+        } else if c.is_whitespace() {
             self.whitespace();
         } else {
             let mut b = [0; 4];
+            if self.take_drop_cap(c.encode_utf8(&mut b)).is_empty() {
+                return;
+            }
+
             let metrics = self.style.measure_string(c.encode_utf8(&mut b));
             let width = metrics.bounding_box.size.width;
             self.items.push(Item::Box {
@@ -406,8 +1870,128 @@ impl<'a, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> ParagraphBuilder<'a,
                     flagged: true,
                     data: Penalty::HardHyphen,
                 });
+            } else if self.builder.break_after.contains(&c) {
+                self.break_after();
+            }
+        }
+    }
+
+    /// Appends preformatted text to the paragraph. Unlike `text`, runs of spaces are preserved,
+    /// words are not hyphenated, tabs are expanded to the builder's tab width, and each line feed
+    /// ends a line. Lines that are too long for the paragraph are laid out according to the
+    /// builder's verbatim overflow policy.
+    ///
+    /// Line feeds and spaces are only pushed once they are followed by more verbatim text, so
+    /// trailing blank lines do not take up any space.
+    pub fn verbatim(&mut self, text: &'a str) {
+        let mut word_start = None;
+        for (i, c) in text.char_indices() {
+            if !matches!(c, ' ' | '\t' | '\n' | '\r') {
+                word_start.get_or_insert(i);
+                continue;
+            }
+
+            if let Some(start) = word_start.take() {
+                self.verbatim_word(&text[start..i]);
+            }
+            match c {
+                ' ' => {
+                    self.verbatim_spaces += 1;
+                    self.verbatim_column += 1;
+                }
+                '\t' => {
+                    let tab_width = self.builder.tab_width;
+                    let n = tab_width - self.verbatim_column % tab_width;
+                    self.verbatim_spaces += n;
+                    self.verbatim_column += n;
+                }
+                '\n' => {
+                    self.verbatim_breaks += 1;
+                    self.verbatim_spaces = 0;
+                    self.verbatim_column = 0;
+                }
+                _ => {}
+            }
+        }
+        if let Some(start) = word_start {
+            self.verbatim_word(&text[start..]);
+        }
+    }
+
+    fn verbatim_word(&mut self, word: &'a str) {
+        for _ in 0..self.verbatim_breaks {
+            self.hard_line_break();
+        }
+        self.verbatim_breaks = 0;
+
+        let (clip, wrap_column) = match self.builder.verbatim_overflow {
+            VerbatimOverflow::Clip => (true, None),
+            VerbatimOverflow::Wrap { column } => (false, column),
+        };
+
+        // A word that would pass the wrap column begins a new line, unless it is the first word
+        // on its line.
+        if let Some(column) = wrap_column {
+            let len = word.chars().count();
+            if self.verbatim_column + len > column && self.verbatim_column > self.verbatim_spaces {
+                self.hard_line_break();
+                self.verbatim_spaces = 0;
+                self.verbatim_column = 0;
+            }
+        }
+
+        for _ in 0..self.verbatim_spaces {
+            // Spaces are fixed-width so that columns line up. Clipped lines are never broken at
+            // them.
+            if clip {
+                self.items.push(Item::Box {
+                    width: self.whitespace_width,
+                    data: Box::Char { text: ' ' },
+                });
+            } else {
+                self.items.push(Item::Glue {
+                    width: self.whitespace_width,
+                    stretch: 0.0,
+                    shrink: 0.0,
+                    data: (),
+                });
+            }
+        }
+        self.verbatim_spaces = 0;
+
+        // A word that is longer than the rest of the line is split at the wrap column.
+        let mut word = word;
+        if let Some(column) = wrap_column {
+            let mut room = column.saturating_sub(self.verbatim_column).max(1);
+            while let Some((split, _)) = word.char_indices().nth(room) {
+                self.verbatim_box(&word[..split]);
+                self.hard_line_break();
+                self.verbatim_column = 0;
+                word = &word[split..];
+                room = column.max(1);
             }
         }
+        self.verbatim_box(word);
+    }
+
+    /// Appends a run of verbatim text that contains no whitespace.
+    fn verbatim_box(&mut self, text: &'a str) {
+        let metrics = self.style.measure_string(text);
+        let width = metrics.bounding_box.size.width;
+        self.items.push(Item::Box {
+            width: width as f32,
+            data: Box::Word { text: text.into() },
+        });
+        self.verbatim_column += text.chars().count();
+    }
+
+    fn break_after(&mut self) {
+        self.items.push(Item::Penalty {
+            width: 0.0,
+            cost: 25.0,
+            flagged: false,
+            data: Penalty::BreakAfter,
+        });
     }
 
     fn paragraph_break(&mut self) {
@@ -425,10 +2009,17 @@ impl<'a, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> ParagraphBuilder<'a,
             _ => {}
         }
 
-        // Append terminating glue.
+        // Append terminating glue. The glue leaves the last line ragged unless it is justified.
+        let mut justify_last_line =
+            self.options.justify_last_line && matches!(self.options.align, Align::Justify);
+        let terminator = self.items.len();
         self.items.push(Item::Glue {
             width: 0.0,
-            stretch: f32::INFINITY,
+            stretch: if justify_last_line {
+                0.0
+            } else {
+                f32::INFINITY
+            },
             shrink: 0.0,
             data: (),
         });
@@ -443,43 +2034,145 @@ impl<'a, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> ParagraphBuilder<'a,
             - self.options.margin_left_px
             - self.options.margin_right_px;
 
-        // Calculate line breaks.
-        let breaks = KnuthPlass::new()
-            .with_threshold(f32::INFINITY)
-            .layout_paragraph(&self.items, paragraph_width);
+        // Lines beside the drop cap, if any, are narrowed by the cap's width.
+        let (cap_lines, cap_width) = match &self.drop_cap {
+            Some(DropCap {
+                lines,
+                cap: Some((_, _, width)),
+            }) => (*lines as usize, *width),
+            _ => (0, 0.0),
+        };
 
-        let breaks = if breaks.is_empty() {
-            FirstFit::new()
+        // Calculate line breaks.
+        let layout = |items: &[Item<Box<'a>, (), Penalty>], width: f32| {
+            let breaks = KnuthPlass::new()
                 .with_threshold(f32::INFINITY)
-                .allow_overflow(true)
-                .layout_paragraph(&self.items, paragraph_width)
-        } else {
+                .layout_paragraph(items, width);
+
+            let breaks = if breaks.is_empty() {
+                FirstFit::new()
+                    .with_threshold(f32::INFINITY)
+                    .allow_overflow(true)
+                    .layout_paragraph(items, width)
+            } else {
+                breaks
+            };
+
+            if breaks.is_empty() {
+                // Set the paragraph as a single over-full line rather than failing.
+                return vec![Break {
+                    break_at: items.len().saturating_sub(1),
+                    adjustment_ratio: 0.0,
+                }];
+            }
             breaks
         };
 
-        if breaks.is_empty() {
-            for i in &self.items {
-                println!("{:?}", i);
+        let breaks = loop {
+            let mut breaks = layout(&self.items, paragraph_width - cap_width);
+            if cap_lines > 0 && breaks.len() > cap_lines {
+                // Lay out the lines below the drop cap at the full width.
+                breaks.truncate(cap_lines);
+                let start = breaks[cap_lines - 1].break_at + 1;
+                breaks.extend(
+                    layout(&self.items[start..], paragraph_width)
+                        .into_iter()
+                        .map(|mut b| {
+                            b.break_at += start;
+                            b
+                        }),
+                );
             }
-            panic!("layout failed");
-        }
+
+            // Leave the last line ragged if justifying it would stretch it too far.
+            let last_ratio = breaks.last().map_or(0.0, |b| b.adjustment_ratio);
+            if justify_last_line && last_ratio > MAX_LAST_LINE_RATIO {
+                justify_last_line = false;
+                if let Item::Glue { stretch, .. } = &mut self.items[terminator] {
+                    *stretch = f32::INFINITY;
+                }
+                continue;
+            }
+
+            // Discourage the first soft hyphen that exceeds the consecutive hyphen limit and try
+            // again. Each penalty is raised at most once, so this terminates.
+            let limit = match self.builder.consecutive_hyphen_limit {
+                None => break breaks,
+                Some(limit) => limit,
+            };
+            let excess = excess_hyphen(&self.items, breaks.iter().map(|b| b.break_at), limit);
+            match excess.map(|at| &mut self.items[at]) {
+                Some(Item::Penalty { cost, .. }) if *cost < EXCESS_HYPHEN_COST => {
+                    *cost = EXCESS_HYPHEN_COST;
+                }
+                _ => break breaks,
+            }
+        };
 
         // Line metrics
         let mut current_line_height = self.builder.line_height;
         let mut current_baseline = self.builder.baseline;
 
+        // Whitespace metrics of the current style. The builder's style is the style in effect at
+        // the start of the paragraph.
+        let mut current_style_id = self.builder.style_id;
+        let mut current_em_px = self.builder.style.em_px();
+        let mut current_whitespace = Glue {
+            width: self.builder.whitespace_width,
+            stretch: self.builder.whitespace_stretch,
+            shrink: self.builder.whitespace_shrink,
+        };
+
         self.builder.advance_vertical(self.options.margin_top_px);
 
+        if let Some(DropCap {
+            lines,
+            cap: Some((text, id, _)),
+        }) = self.drop_cap.take()
+        {
+            // Start a new page if the cap does not fit on this one.
+            let line_height = self.builder.line_height as i32;
+            let remaining = self.builder.bounding_box.size.height as i32 - self.builder.cursor.y;
+            if self.builder.galley.is_none() && remaining < lines as i32 * line_height {
+                self.builder.page_break();
+            }
+
+            // Draw the cap on the last line it spans, then return to the start of the first.
+            let cursor = self.builder.cursor;
+            let commands = &mut self.builder.commands;
+            commands.push(Command::SetStyle { s: id });
+            commands.push(Command::SetCursor {
+                x: self.options.margin_left_px as u16,
+                y: (cursor.y + (lines as i32 - 1) * line_height) as u16,
+            });
+            commands.push(Command::Show { str: text });
+            commands.push(Command::SetCursor {
+                x: cursor.x as u16,
+                y: cursor.y as u16,
+            });
+            commands.push(Command::SetStyle {
+                s: self.builder.style_id,
+            });
+        }
+
         // Paginate.
         let mut item = 0;
-        for b in breaks {
+        for (line, b) in breaks.into_iter().enumerate() {
             let items = &self.items[item..=b.break_at];
 
+            let cap_offset = if line < cap_lines { cap_width } else { 0.0 };
+            let paragraph_width = paragraph_width - cap_offset;
+
             let adjustment_ratio = match &self.options.align {
                 Align::Left | Align::Center | Align::Right if b.adjustment_ratio > 0.0 => 0.0,
                 _ => b.adjustment_ratio,
             };
 
+            // Readers apply the adjustment ratio using the style in effect at the start of the line.
+            let line_em_px = current_em_px;
+            let line_whitespace = current_whitespace;
+            let line_style_id = current_style_id;
+
             let mut line_width = 0.0;
             let mut commands = Vec::new();
 
@@ -496,6 +2189,8 @@ impl<'a, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> ParagraphBuilder<'a,
                                     id,
                                     line_height,
                                     baseline,
+                                    em_px,
+                                    whitespace,
                                 },
                             ..
                         } => {
@@ -505,6 +2200,9 @@ impl<'a, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> ParagraphBuilder<'a,
                                 any_text = true;
                             }
                             commands.push(Command::SetStyle { s: *id });
+                            current_style_id = *id;
+                            current_em_px = *em_px;
+                            current_whitespace = *whitespace;
 
                             if !any_text && *line_height != current_line_height
                                 || *line_height > current_line_height
@@ -520,7 +2218,14 @@ impl<'a, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> ParagraphBuilder<'a,
                         } => {
                             assert!(text.is_empty());
                             line_width += *width;
-                            commands.push(Command::Advance { dx: *width as u16 });
+                            if *width < 0.0 {
+                                commands.push(Command::MoveBy {
+                                    dx: *width as i16,
+                                    dy: 0,
+                                });
+                            } else {
+                                commands.push(Command::Advance { dx: *width as u16 });
+                            }
                         }
                         Item::Box {
                             width,
@@ -528,6 +2233,14 @@ impl<'a, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> ParagraphBuilder<'a,
                         } => {
                             line_width += *width;
                             text.push_str(word);
+
+                            let dx = non_breaking_space_correction(word, *width, current_em_px);
+                            if dx != 0 {
+                                commands.push(Command::Show { str: text });
+                                commands.push(Command::MoveBy { dx, dy: 0 });
+                                text = String::new();
+                                any_text = true;
+                            }
                         }
                         Item::Box {
                             width,
@@ -535,6 +2248,55 @@ impl<'a, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> ParagraphBuilder<'a,
                         } => {
                             line_width += *width;
                             text.push(*char);
+
+                            let mut b = [0; 4];
+                            let char = char.encode_utf8(&mut b);
+                            let dx = non_breaking_space_correction(char, *width, current_em_px);
+                            if dx != 0 {
+                                commands.push(Command::Show { str: text });
+                                commands.push(Command::MoveBy { dx, dy: 0 });
+                                text = String::new();
+                                any_text = true;
+                            }
+                        }
+                        Item::Box {
+                            data: Box::Raw { command },
+                            ..
+                        } => {
+                            if !text.is_empty() {
+                                commands.push(Command::Show { str: text });
+                                text = String::new();
+                                any_text = true;
+                            }
+                            commands.push(command.clone());
+                        }
+                        Item::Box {
+                            data:
+                                Box::Marker {
+                                    text: marker,
+                                    width,
+                                    hang,
+                                },
+                            ..
+                        } => {
+                            if !text.is_empty() {
+                                commands.push(Command::Show { str: text });
+                                text = String::new();
+                                any_text = true;
+                            }
+                            // Draw the marker in the hanging indent, then return to the start of
+                            // the line.
+                            commands.push(Command::MoveBy {
+                                dx: -*hang as i16,
+                                dy: 0,
+                            });
+                            commands.push(Command::Show {
+                                str: marker.clone(),
+                            });
+                            commands.push(Command::MoveBy {
+                                dx: (*hang - *width) as i16,
+                                dy: 0,
+                            });
                         }
                         Item::Glue {
                             width,
@@ -556,33 +2318,50 @@ impl<'a, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> ParagraphBuilder<'a,
                     }
                 }
                 if let Item::Penalty {
+                    width,
                     data: Penalty::SoftHyphen,
                     ..
                 } = &items[items.len() - 1]
                 {
+                    line_width += *width;
                     text.push('-');
                 }
                 if !text.is_empty() {
                     commands.push(Command::Show { str: text });
                 }
 
-                if push_line_metrics {
+                let (line_height, baseline) =
+                    line_metrics(self.line_height, current_line_height, current_baseline);
+                if push_line_metrics
+                    || line_height != self.builder.line_height
+                    || baseline != self.builder.baseline
+                {
                     self.builder.commands.push(Command::SetLineMetrics {
-                        height: current_line_height,
-                        baseline: current_baseline,
+                        height: line_height,
+                        baseline,
                     });
                 }
 
                 self.builder.commands.push(Command::SetAdjustmentRatio {
-                    r: adjustment_ratio,
+                    r: reader_adjustment_ratio(adjustment_ratio, line_em_px, line_whitespace),
                 });
 
+                // Right-to-left lines start at the right edge.
+                let start = match self.direction {
+                    Direction::Ltr => 0.0,
+                    Direction::Rtl => {
+                        commands = visual_order(commands, line_style_id);
+                        paragraph_width - line_width
+                    }
+                };
+
                 // TODO: account for leading indent?
                 let indent = self.options.margin_left_px
+                    + cap_offset
                     + match &self.options.align {
                         Align::Center => (paragraph_width - line_width) / 2.0,
-                        Align::Right => paragraph_width as f32 - line_width,
-                        _ => 0.0,
+                        Align::Right => paragraph_width - line_width - start,
+                        _ => start,
                     };
                 if indent != 0.0 {
                     self.builder
@@ -591,9 +2370,10 @@ impl<'a, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> ParagraphBuilder<'a,
                 }
 
                 self.builder.commands.append(&mut commands);
+                self.builder.end_keep();
 
-                self.builder.line_height = current_line_height;
-                self.builder.baseline = current_baseline;
+                self.builder.line_height = line_height;
+                self.builder.baseline = baseline;
             }
 
             self.builder.advance_line();
@@ -601,7 +2381,22 @@ impl<'a, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> ParagraphBuilder<'a,
             item = b.break_at + 1;
         }
 
+        // Later content uses its styles' line metrics.
+        if self.builder.line_height != current_line_height
+            || self.builder.baseline != current_baseline
+        {
+            self.builder.line_height = current_line_height;
+            self.builder.baseline = current_baseline;
+            self.builder.commands.push(Command::SetLineMetrics {
+                height: current_line_height,
+                baseline: current_baseline,
+            });
+        }
+
         self.builder.advance_vertical(self.options.margin_bottom_px);
+        if let Some(keep) = &mut self.builder.keep {
+            keep.done = true;
+        }
 
         self.items.clear();
     }
@@ -618,3 +2413,386 @@ impl<'a, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> ParagraphBuilder<'a,
         self.builder
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::testing::{TestFonts, TestStyle};
+    use embedded_graphics::geometry::Size;
+
+    /// Returns a builder for a `width` by `height` page whose text is 20px.
+    fn builder(width: u32, height: u32) -> Builder<TestStyle, TestFonts, ()> {
+        Builder::new(
+            Rectangle::new(Point::zero(), Size::new(width, height)),
+            TestFonts,
+            TestStyle::new("Test", 20),
+            (),
+            WhitespaceMetrics::default(),
+        )
+    }
+
+    /// Returns the index of the first `Show` of `text`, ignoring the space that ends a paragraph.
+    fn show_index(commands: &[Command<String>], text: &str) -> usize {
+        commands
+            .iter()
+            .position(|c| matches!(c, Command::Show { str } if str.trim_end() == text))
+            .unwrap()
+    }
+
+    /// Returns options for a left-aligned paragraph without margins.
+    fn left_aligned() -> ParagraphOptions {
+        ParagraphOptions {
+            align: Align::Left,
+            justify_last_line: false,
+            margin_bottom_px: 0.0,
+            margin_left_px: 0.0,
+            margin_right_px: 0.0,
+            margin_top_px: 0.0,
+        }
+    }
+
+    #[test]
+    fn raw_commands_keep_their_position() {
+        let mut p = builder(400, 600).paragraph(None);
+        p.text("one");
+        let mut b = p.finish();
+        b.push_raw(Command::Nop);
+        let mut p = b.paragraph(None);
+        p.text("two");
+        p.push_raw(Command::Nop);
+        p.text("three");
+        let (_, commands) = p.finish().finish();
+
+        let nops = commands
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| matches!(c, Command::Nop))
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        assert_eq!(nops.len(), 2);
+
+        // The builder's Nop follows the first paragraph and precedes the second.
+        let one = show_index(&commands, "one");
+        let two = show_index(&commands, "two");
+        assert!(one < nops[0] && nops[0] < two);
+        assert!(matches!(commands[nops[0] - 1], Command::LineBreak));
+
+        // The paragraph's Nop splits the line's text between the two words.
+        assert_eq!(nops[1], two + 1);
+        assert_eq!(show_index(&commands, "three"), nops[1] + 1);
+    }
+
+    #[test]
+    fn first_page_sets_line_metrics() {
+        let b = builder(400, 600);
+        assert!(b.is_empty());
+        let mut p = b.paragraph(None);
+        p.text("one");
+        let (_, commands) = p.finish().finish();
+        assert!(matches!(
+            commands[0],
+            Command::SetLineMetrics {
+                height: 25,
+                baseline: 5
+            }
+        ));
+    }
+
+    #[test]
+    fn whitespace_metrics_reach_readers() {
+        let metrics = WhitespaceMetrics {
+            width_em: Some(0.5),
+            stretch: 0.5,
+            shrink: 0.0,
+        };
+        let b = Builder::new(
+            Rectangle::new(Point::zero(), Size::new(400, 600)),
+            TestFonts,
+            TestStyle::new("Test", 30),
+            (),
+            metrics,
+        );
+        let mut p = b.paragraph(Some(left_aligned()));
+        p.text("a b\u{a0}c");
+        let (_, commands) = p.finish().finish();
+
+        // Readers apply adjustment ratios to spaces a third of an em wide, so the unadjusted line
+        // carries the ratio that widens them to half an em.
+        let r = commands
+            .iter()
+            .find_map(|c| match c {
+                Command::SetAdjustmentRatio { r } => Some(*r),
+                _ => None,
+            })
+            .unwrap();
+        let space = 10.0 + 5.0 * r;
+        assert!((space - 15.0).abs() < 0.01, "space is {space}px");
+
+        // Non-breaking spaces are not adjusted, so the builder moves past the difference.
+        let nbsp = commands
+            .iter()
+            .position(|c| matches!(c, Command::Show { str } if str == "a b\u{a0}"))
+            .unwrap();
+        assert!(matches!(
+            commands[nbsp + 1],
+            Command::MoveBy { dx: 5, dy: 0 }
+        ));
+        assert_eq!(show_index(&commands, "c"), nbsp + 2);
+    }
+
+    #[test]
+    fn styles_are_interned_in_order() {
+        let styles: Vec<Style> = (0..1000)
+            .map(|i| Style {
+                font_name: alloc::format!("Font {i}"),
+                em_px: 20,
+            })
+            .collect();
+
+        // Set each style twice, in opposite orders.
+        let mut b = builder(400, 600);
+        for style in styles.iter().chain(styles.iter().rev()) {
+            b.set_style(style);
+        }
+        let (interned, commands) = b.finish();
+
+        // Styles are numbered in the order they were first seen, after the default style.
+        assert_eq!(interned.len(), 1001);
+        assert_eq!(interned[0].font_name, "Test");
+        assert_eq!(&interned[1..], &styles[..]);
+
+        let ids: Vec<u16> = commands
+            .iter()
+            .filter_map(|c| match c {
+                Command::SetStyle { s } => Some(*s),
+                _ => None,
+            })
+            .collect();
+        let expected: Vec<u16> = (1..=1000).chain((1..=999).rev()).collect();
+        assert_eq!(ids, expected);
+    }
+
+    /// Returns the number of lines of text on each page.
+    fn lines_per_page(commands: &[Command<String>]) -> Vec<usize> {
+        commands
+            .split(|c| matches!(c, Command::PageBreak))
+            .map(|page| {
+                page.iter()
+                    .filter(|c| matches!(c, Command::Show { .. }))
+                    .count()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn balanced_pages_are_more_even() {
+        let layout = |mode| {
+            // Five lines, of which four fit on a page.
+            let mut b = builder(400, 110);
+            b.set_page_breaking(mode);
+            for _ in 0..5 {
+                let mut p = b.paragraph(None);
+                p.text("line");
+                b = p.finish();
+            }
+            lines_per_page(&b.finish().1)
+        };
+
+        let greedy = layout(PageBreaking::Greedy);
+        let balanced = layout(PageBreaking::Balanced);
+        assert_eq!(greedy, vec![4, 1]);
+        assert_eq!(balanced.len(), 2);
+        assert_eq!(balanced.iter().sum::<usize>(), 5);
+        assert!(balanced.iter().all(|lines| (2..=3).contains(lines)));
+    }
+
+    #[test]
+    fn verbatim_lines_are_not_stretched() {
+        let mut p = builder(200, 600).paragraph(None);
+        p.text("aaa bb aaa bb aaa bb aaa bb aaa bb");
+        let mut p = p.finish().paragraph(Some(left_aligned()));
+        p.verbatim("a  b\nc d");
+        let (_, commands) = p.finish().finish();
+
+        let ratios: Vec<f32> = commands
+            .iter()
+            .filter_map(|c| match c {
+                Command::SetAdjustmentRatio { r } => Some(*r),
+                _ => None,
+            })
+            .collect();
+        assert!(ratios[0] > 0.0, "{ratios:?}");
+        assert_eq!(&ratios[ratios.len() - 2..], &[0.0, 0.0]);
+
+        // Each verbatim line sets its own ratio before showing its text.
+        let first = show_index(&commands, "a  b");
+        assert!(matches!(
+            commands[first - 1],
+            Command::SetAdjustmentRatio { r } if r == 0.0
+        ));
+    }
+
+    /// Returns the horizontal offset of the text of each line from the start of the line.
+    fn line_indents(commands: &[Command<String>]) -> Vec<i32> {
+        let mut indents = Vec::new();
+        let (mut x, mut shown) = (0, false);
+        for command in commands {
+            match command {
+                Command::Advance { dx } if !shown => x += *dx as i32,
+                Command::MoveBy { dx, .. } if !shown => x += *dx as i32,
+                Command::Show { .. } if !shown => {
+                    indents.push(x);
+                    shown = true;
+                }
+                Command::LineBreak | Command::PageBreak => (x, shown) = (0, false),
+                _ => {}
+            }
+        }
+        indents
+    }
+
+    #[test]
+    fn hanging_indents_skip_the_first_line() {
+        let mut p = builder(200, 600).paragraph(Some(left_aligned()));
+        p.set_text_indent(TextIndent {
+            px: 20.0,
+            each_line: false,
+            hanging: true,
+        });
+        p.text("aaa bbb ccc ddd eee fff ggg hhh iii jjj kkk lll");
+        let (_, commands) = p.finish().finish();
+
+        let indents = line_indents(&commands);
+        assert!(indents.len() >= 3, "{indents:?}");
+        assert_eq!(indents[0], 0);
+        assert!(indents[1..].iter().all(|x| *x == 20), "{indents:?}");
+    }
+
+    #[test]
+    fn slashes_are_break_opportunities() {
+        let mut p = builder(400, 600).paragraph(None);
+        p.text("path/to/some/resource");
+        let breaks: Vec<&str> = p
+            .items
+            .windows(2)
+            .filter_map(|pair| match pair {
+                [Item::Box {
+                    data: Box::Word { text },
+                    ..
+                }, Item::Penalty {
+                    data: Penalty::BreakAfter,
+                    ..
+                }] => Some(&text[..]),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(breaks, ["/", "/", "/"]);
+
+        // A line too narrow for the whole path is broken after its slashes.
+        let mut p = builder(100, 600).paragraph(Some(left_aligned()));
+        p.text("path/to/some/resource");
+        let (_, commands) = p.finish().finish();
+        let lines: Vec<&str> = commands
+            .iter()
+            .filter_map(|c| match c {
+                Command::Show { str } => Some(str.trim_end()),
+                _ => None,
+            })
+            .collect();
+        assert!(lines.len() > 1, "{lines:?}");
+        assert!(lines[..lines.len() - 1]
+            .iter()
+            .all(|line| line.ends_with('/')));
+        assert_eq!(lines.concat(), "path/to/some/resource");
+    }
+
+    #[test]
+    fn tall_images_are_scaled_or_clipped() {
+        let layout = |policy: Option<OverflowPolicy>| {
+            let mut b = builder(400, 600);
+            if let Some(policy) = policy {
+                b.set_overflow_policy(policy);
+            }
+            let mut p = b.paragraph(None);
+            p.text("before");
+            let mut b = p.finish();
+            b.image(0, 100, 1200);
+            let (_, commands) = b.finish();
+            let image = commands
+                .iter()
+                .position(|c| matches!(c, Command::DrawImage { .. }))
+                .unwrap();
+
+            // The image does not fit below the paragraph, so it starts a new page.
+            let pages = commands[..image]
+                .iter()
+                .filter(|c| matches!(c, Command::PageBreak))
+                .count();
+            assert_eq!(pages, 1);
+            match commands[image] {
+                Command::DrawImage { width, height, .. } => (width, height),
+                _ => unreachable!(),
+            }
+        };
+
+        // By default, the image is scaled down to the page's height.
+        assert_eq!(layout(None), (50, 600));
+        assert_eq!(layout(Some(OverflowPolicy::Scale)), (50, 600));
+
+        // Clipped images keep their size, and the display cuts them off at the page's edge.
+        assert_eq!(layout(Some(OverflowPolicy::Clip)), (100, 1200));
+    }
+
+    #[test]
+    fn right_to_left_words_are_right_aligned_and_reversed() {
+        let mut b = builder(100, 600);
+        b.set_direction(Direction::Rtl);
+        let mut p = b.paragraph(Some(left_aligned()));
+        p.text("שלום עולם טוב");
+        let (_, commands) = p.finish().finish();
+
+        // Each line ends at the right edge, and its first word is the rightmost.
+        let shown: Vec<&str> = commands
+            .iter()
+            .filter_map(|c| match c {
+                Command::Show { str } => Some(str.trim()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(shown, ["םלוע םולש", "בוט"]);
+        // Letters are 10px wide and spaces are a third of an em.
+        assert_eq!(line_indents(&commands), [13, 70]);
+    }
+
+    #[test]
+    fn non_breaking_spaces_never_break() {
+        // The text is 27px wide, so the line is over-full.
+        let mut p = builder(20, 600).paragraph(Some(left_aligned()));
+        p.text("a\u{a0}b");
+        let nbsp = p.items.iter().find_map(|item| match item {
+            Item::Box {
+                width,
+                data: Box::Word { text },
+            } if text == "\u{a0}" => Some(*width),
+            _ => None,
+        });
+        assert_eq!(nbsp, Some(p.whitespace_width));
+        let (_, commands) = p.finish().finish();
+
+        // Both words are shown together, so no line break falls between them.
+        let shown: Vec<&str> = commands
+            .iter()
+            .filter_map(|c| match c {
+                Command::Show { str } => Some(str.trim_end_matches(' ')),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(shown, ["a\u{a0}b"]);
+
+        // The space is a third of an em, as readers draw it, so the cursor needs no correction.
+        assert!(
+            !commands.iter().any(|c| matches!(c, Command::MoveBy { .. })),
+            "{commands:?}"
+        );
+    }
+}
@@ -1,7 +1,11 @@
 #[allow(dead_code)]
 use crate::{
-    layout::{Align, Builder, FontStyle, Fonts, Hyphenator, ParagraphBuilder, ParagraphOptions},
-    Command, Header, Style,
+    layout::{
+        Align, Builder, FontStyle, Fonts, HeaderScope, Hyphenator, ListKind, ParagraphBuilder,
+        ParagraphOptions, StructureKind, StructureNode, TextIndent, VerbatimOverflow,
+        WhitespaceMetrics,
+    },
+    Command, Header, ImageFormat, ImageTable, Metadata, Style, VERSION,
 };
 
 use ego_tree::NodeRef;
@@ -11,11 +15,13 @@ use scraper::{html::Html, node::Text, Node};
 use selectors::matching;
 use servo_arc::Arc;
 use servo_url::ServoUrl;
+use std::collections::HashMap;
 use std::error::Error;
 use std::io::{Read, Seek};
 use style::{
     context::QuirksMode,
     media_queries::MediaList,
+    selector_parser::PseudoElement,
     shared_lock::{Locked, SharedRwLock},
     stylesheet_set::DocumentStylesheetSet,
     stylesheets::{
@@ -28,7 +34,12 @@ use url::Url;
 mod element;
 use element::Element;
 mod element_style;
-use element_style::{ComputeContext, ComputedStyle, FontAngle, TextAlign};
+mod toc;
+use element_style::{
+    CaptionSide, ComputeContext, ComputedStyle, ContentItem, Display, FontAngle, FontVariant,
+    GenericFontFamily, ObjectFit, TextAlign,
+};
+pub use toc::TocEntry;
 
 pub struct Options {
     pixels_per_inch: f32,
@@ -36,7 +47,12 @@ pub struct Options {
     emphasis: Option<Style>,
     strong: Option<Style>,
     heading: Option<Vec<Style>>,
+    code: Option<Style>,
     title: Option<String>,
+    language: Option<String>,
+    toc: bool,
+    definition_indent: Option<f32>,
+    pre_overflow: VerbatimOverflow,
 }
 
 impl Options {
@@ -47,7 +63,12 @@ impl Options {
             emphasis: None,
             strong: None,
             heading: None,
+            code: None,
             title: None,
+            language: None,
+            toc: false,
+            definition_indent: None,
+            pre_overflow: VerbatimOverflow::Wrap { column: None },
         }
     }
 
@@ -66,10 +87,41 @@ impl Options {
         self
     }
 
+    pub fn with_code(mut self, code: Option<Style>) -> Self {
+        self.code = code;
+        self
+    }
+
     pub fn with_title<S: AsRef<str>>(mut self, title: Option<S>) -> Self {
         self.title = title.map(|s| s.as_ref().into());
         self
     }
+
+    pub fn with_language<S: AsRef<str>>(mut self, language: Option<S>) -> Self {
+        self.language = language.map(|s| s.as_ref().into());
+        self
+    }
+
+    /// Begin the document with a table of contents, taken from the book's navigation document
+    /// or NCX.
+    pub fn with_toc(mut self, toc: bool) -> Self {
+        self.toc = toc;
+        self
+    }
+
+    /// Indent definitions in definition lists by `indent` pixels relative to their terms, unless
+    /// the stylesheet gives them a margin. Defaults to the indent of list items.
+    pub fn with_definition_indent(mut self, indent: Option<f32>) -> Self {
+        self.definition_indent = indent;
+        self
+    }
+
+    /// Lay out lines of preformatted text that are too long for the page according to
+    /// `overflow`. Defaults to wrapping them at spaces.
+    pub fn with_pre_overflow(mut self, overflow: VerbatimOverflow) -> Self {
+        self.pre_overflow = overflow;
+        self
+    }
 }
 
 enum BuilderState<'a, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> {
@@ -104,11 +156,27 @@ impl<'a, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> BuilderState<'a, S, F
         }
     }
 
+    fn font_style(&self, style: &Style) -> Option<S> {
+        match self {
+            BuilderState::Doc(ref d) => d.font_style(style),
+            BuilderState::Paragraph(ref p) => p.font_style(style),
+            _ => None,
+        }
+    }
+
     fn if_doc<Fn: FnOnce(&mut Builder<S, F, H>)>(&mut self, func: Fn) {
         if let BuilderState::Doc(ref mut d) = self {
             func(d);
         }
     }
+
+    fn page_count(&self) -> usize {
+        match self {
+            BuilderState::Doc(ref d) => d.page_count(),
+            BuilderState::Paragraph(ref p) => p.page_count(),
+            _ => 0,
+        }
+    }
 }
 
 /// Context used to lay out epub documents.
@@ -117,38 +185,73 @@ struct LayoutContext<'a, R: Read + Seek, S: FontStyle, F: Fonts<Style = S>, H: H
     doc: &'a mut EpubDoc<R>,
     options: &'a Options,
     base_url: &'a Url,
+    // URL of the document being laid out.
+    url: &'a Url,
     builder: BuilderState<'a, S, F, H>,
+    images: &'a mut ImageTable,
+    // Index of the page that holds each document and each element with an ID, by URL.
+    locations: &'a mut HashMap<Url, usize>,
     content_width: u32,
+    page_height: u32,
     lock: SharedRwLock,
     stylesheets: DocumentStylesheetSet<DocumentStyleSheet>,
     computed_style: Vec<ComputedStyle>,
+    // True if the next paragraph begins a list item.
+    in_list_item: bool,
+    // Instances of CSS counters by name, innermost last, with the depth of the element that
+    // instantiated each.
+    counters: HashMap<String, Vec<(usize, i32)>>,
+    // Generated content of the most recently styled element's `::before` pseudo-element, waiting
+    // for the element's first paragraph or text.
+    before: Option<String>,
+    // The options' style for the heading being laid out, if any. It replaces the regular style
+    // within the heading.
+    heading: Option<Style>,
+    // The left and right margins of the figure being laid out, if any. Images inside a figure are
+    // centered between them.
+    figure: Option<(f32, f32)>,
 }
 
 impl<'a, R: Read + Seek, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator>
     LayoutContext<'a, R, S, F, H>
 {
     /// Create a new layout context.
+    #[allow(clippy::too_many_arguments)]
     fn new(
         doc: &'a mut EpubDoc<R>,
         options: &'a Options,
         base_url: &'a Url,
+        url: &'a Url,
         builder: Builder<S, F, H>,
+        images: &'a mut ImageTable,
+        locations: &'a mut HashMap<Url, usize>,
         content_width: u32,
+        page_height: u32,
     ) -> Self {
         let computed_style = ComputedStyle::new(options.regular.em_px as f32);
         LayoutContext {
             doc,
             options,
             base_url,
+            url,
             builder: BuilderState::Doc(builder),
+            images,
+            locations,
             content_width,
+            page_height,
             lock: SharedRwLock::new(),
             stylesheets: DocumentStylesheetSet::new(),
             computed_style: vec![computed_style],
+            in_list_item: false,
+            counters: HashMap::new(),
+            before: None,
+            heading: None,
+            figure: None,
         }
     }
 
-    fn push_style(&mut self, element: Element) -> Style {
+    /// Returns the most specific style rule that matches the element, if any.
+    fn matching_rule(&self, element: Element) -> Option<Arc<Locked<StyleRule>>> {
         let mut nth_index_cache = Default::default();
         let mut context = matching::MatchingContext::new(
             matching::MatchingMode::Normal,
@@ -183,11 +286,19 @@ impl<'a, R: Read + Seek, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator>
                 _ => Some((this_style.0.clone(), this_style.1.specificity())),
             };
         }
+        style.map(|(style, _)| style)
+    }
 
+    /// Computes the style of an element with the given matching rule from the current style.
+    fn compute_style(&self, rule: Option<&Arc<Locked<StyleRule>>>) -> ComputedStyle {
         let top = self.computed_style[self.computed_style.len() - 1];
-        let style = match style {
-            None => top.clone(),
-            Some((ref style, _)) => {
+        match rule {
+            None => ComputedStyle {
+                display: Display::Normal,
+                object_fit: ObjectFit::Fill,
+                ..top
+            },
+            Some(style) => {
                 let guard = self.lock.read();
                 let block = style.read_with(&guard).block.read_with(&guard);
                 top.compute(
@@ -198,59 +309,281 @@ impl<'a, R: Read + Seek, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator>
                     },
                 )
             }
-        };
+        }
+    }
+
+    /// Returns false if the element's computed `display` is `none`, in which case neither it nor
+    /// its descendants are laid out.
+    fn is_displayed(&self, element: Element) -> bool {
+        let rule = self.matching_rule(element);
+        self.compute_style(rule.as_ref()).display != Display::None
+    }
 
-        eprintln!("push({:?})", style);
+    fn push_style(&mut self, element: Element) -> Style {
+        let style = self.matching_rule(element);
+        let computed = self.compute_style(style.as_ref());
+
+        self.computed_style.push(computed);
+
+        // Counters are reset and then incremented before the element's generated content is
+        // evaluated.
+        let guard = self.lock.read();
+        let (resets, increments) = match style {
+            None => (Vec::new(), Vec::new()),
+            Some(ref style) => {
+                let block = style.read_with(&guard).block.read_with(&guard);
+                (
+                    element_style::counter_resets(block),
+                    element_style::counter_increments(block),
+                )
+            }
+        };
+        drop(guard);
+        for (name, value) in resets {
+            self.reset_counter(name, value);
+        }
+        for (name, value) in increments {
+            self.increment_counter(name, value);
+        }
+        self.before = self.before_content(element);
 
-        self.computed_style.push(style);
         self.as_style(&self.computed_style[self.computed_style.len() - 1])
     }
 
     fn pop_style(&mut self) -> Style {
         self.computed_style.pop();
+
+        // A counter is in scope for the element that instantiated it, that element's following
+        // siblings, and their descendants.
+        let depth = self.computed_style.len() + 1;
+        self.counters.retain(|_, instances| {
+            instances.retain(|(d, _)| *d <= depth);
+            !instances.is_empty()
+        });
+
         self.as_style(&self.computed_style[self.computed_style.len() - 1])
     }
 
+    /// Instantiates a counter on the current element.
+    fn reset_counter(&mut self, name: String, value: i32) {
+        let depth = self.computed_style.len();
+        let instances = self.counters.entry(name).or_default();
+        match instances.last_mut() {
+            Some((d, v)) if *d == depth => *v = value,
+            _ => instances.push((depth, value)),
+        }
+    }
+
+    /// Increments the innermost instance of a counter, instantiating it on the current element if
+    /// there is none.
+    fn increment_counter(&mut self, name: String, value: i32) {
+        if !self.counters.contains_key(&name) {
+            self.reset_counter(name.clone(), 0);
+        }
+        if let Some((_, v)) = self.counters.get_mut(&name).and_then(|i| i.last_mut()) {
+            *v = v.saturating_add(value);
+        }
+    }
+
+    /// Returns the text of the element's `::before` pseudo-element, if any. Only strings and
+    /// decimal counters are supported.
+    fn before_content(&self, element: Element) -> Option<String> {
+        let mut nth_index_cache = Default::default();
+        let mut context = matching::MatchingContext::new(
+            matching::MatchingMode::ForStatelessPseudoElement,
+            None,
+            &mut nth_index_cache,
+            matching::QuirksMode::NoQuirks,
+            matching::NeedsSelectorFlags::No,
+        );
+
+        let guard = self.lock.read();
+        let mut before: Option<(Vec<ContentItem>, u32)> = None;
+        let rules = self.stylesheets.iter().flat_map(|sheet| {
+            sheet.0.rules(&guard).iter().filter_map(|rule| match rule {
+                CssRule::Style(style) => Some(style),
+                _ => None,
+            })
+        });
+        for rule in rules {
+            let rule = rule.read_with(&guard);
+            let specificity = rule.selectors.0.iter().find_map(|sel| {
+                (sel.pseudo_element() == Some(&PseudoElement::Before)
+                    && matching::matches_selector(sel, 0, None, &element, &mut context))
+                .then_some(sel.specificity())
+            });
+            let specificity = match specificity {
+                None => continue,
+                Some(specificity) => specificity,
+            };
+            if matches!(before, Some((_, s)) if s > specificity) {
+                continue;
+            }
+            if let Some(content) = element_style::content(rule.block.read_with(&guard)) {
+                before = Some((content, specificity));
+            }
+        }
+
+        let (content, _) = before?;
+        let mut text = String::new();
+        for item in content {
+            match item {
+                ContentItem::String(s) => text.push_str(&s),
+                ContentItem::Counter(name) => {
+                    let value = self
+                        .counters
+                        .get(&name)
+                        .and_then(|i| i.last())
+                        .map_or(0, |(_, v)| *v);
+                    text.push_str(&value.to_string());
+                }
+                ContentItem::Counters(name, separator) => match self.counters.get(&name) {
+                    None => text.push('0'),
+                    Some(instances) => {
+                        let values: Vec<String> =
+                            instances.iter().map(|(_, v)| v.to_string()).collect();
+                        text.push_str(&values.join(&separator));
+                    }
+                },
+            }
+        }
+        Some(text)
+    }
+
     fn as_style(&self, style: &ComputedStyle) -> Style {
-        let font_name = match style.font_style {
-            FontAngle::Normal => match &self.options.strong {
-                Some(strong) if style.font_weight.0 >= 600.0 => strong.font_name.clone(),
-                _ => self.options.regular.font_name.clone(),
-            },
-            FontAngle::Italic => match &self.options.emphasis {
-                None => self.options.regular.font_name.clone(),
-                Some(emphasis) => emphasis.font_name.clone(),
-            },
+        if let Some(heading) = &self.heading {
+            if style.font_family != GenericFontFamily::Monospace
+                && style.font_style == FontAngle::Normal
+                && style.font_weight.0 < 600.0
+            {
+                return heading.clone();
+            }
         }
-        .clone();
+
+        let font_name = match (&style.font_family, &self.options.code) {
+            (GenericFontFamily::Monospace, Some(code)) => code.font_name.clone(),
+            _ => match style.font_style {
+                FontAngle::Normal => match &self.options.strong {
+                    Some(strong) if style.font_weight.0 >= 600.0 => strong.font_name.clone(),
+                    _ => self.options.regular.font_name.clone(),
+                },
+                FontAngle::Italic => match &self.options.emphasis {
+                    None => self.options.regular.font_name.clone(),
+                    Some(emphasis) => emphasis.font_name.clone(),
+                },
+            },
+        };
 
         let em_px: u16 = style.font_size.0 as u16;
 
+        let em_px = match style.font_size_adjust.0 {
+            None => em_px,
+            Some(aspect) => {
+                // Scale the font so that its x-height matches the requested aspect value.
+                let unadjusted = Style {
+                    font_name: font_name.clone(),
+                    em_px,
+                };
+                match self
+                    .builder
+                    .font_style(&unadjusted)
+                    .and_then(|s| s.x_height())
+                {
+                    Some(x_height) if x_height != 0 => {
+                        let ratio = x_height as f32 / em_px as f32;
+                        (style.font_size.0 * aspect / ratio) as u16
+                    }
+                    _ => em_px,
+                }
+            }
+        };
+
         Style { font_name, em_px }
     }
 }
 
 /// Turn events and bytes into an edf document.
-pub fn build<R: Read + Seek, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator>(
+pub fn build<R: Read + Seek, S: FontStyle, F: Fonts<Style = S> + Clone, H: Hyphenator + Clone>(
     doc: &mut EpubDoc<R>,
     bounding_box: Rectangle,
     fonts: F,
     hyphenator: H,
     options: Options,
 ) -> Result<(Header, Vec<Command<String>>), Box<dyn Error>> {
+    build_document(doc, bounding_box, fonts, hyphenator, options, false)
+        .map(|(header, commands, _)| (header, commands))
+}
+
+/// Turn events and bytes into an edf document, recording the document's logical structure
+/// (tables, their captions, rows, and cells) alongside its commands.
+pub fn build_with_structure<
+    R: Read + Seek,
+    S: FontStyle,
+    F: Fonts<Style = S> + Clone,
+    H: Hyphenator + Clone,
+>(
+    doc: &mut EpubDoc<R>,
+    bounding_box: Rectangle,
+    fonts: F,
+    hyphenator: H,
+    options: Options,
+) -> Result<(Header, Vec<Command<String>>, Vec<StructureNode>), Box<dyn Error>> {
+    build_document(doc, bounding_box, fonts, hyphenator, options, true)
+        .map(|(header, commands, structure)| (header, commands, structure.unwrap_or_default()))
+}
+
+fn build_document<
+    R: Read + Seek,
+    S: FontStyle,
+    F: Fonts<Style = S> + Clone,
+    H: Hyphenator + Clone,
+>(
+    doc: &mut EpubDoc<R>,
+    bounding_box: Rectangle,
+    fonts: F,
+    hyphenator: H,
+    options: Options,
+    record_structure: bool,
+) -> Result<(Header, Vec<Command<String>>, Option<Vec<StructureNode>>), Box<dyn Error>> {
     let default_style = match fonts.get_style(&options.regular) {
         None => return Err("missing font for regular style".into()),
         Some(s) => s,
     };
 
     let root_url = Url::parse("epub://").unwrap();
-    let mut builder = Builder::new(bounding_box, fonts, default_style, hyphenator);
+    let mut builder = Builder::new(
+        bounding_box,
+        fonts.clone(),
+        default_style.clone(),
+        hyphenator.clone(),
+        WhitespaceMetrics::default(),
+    );
+    builder.set_verbatim_overflow(options.pre_overflow);
+    if record_structure {
+        builder.record_structure();
+    }
 
     let title = match doc.metadata.get("title") {
         Some(values) if !values.is_empty() => values[0].clone(),
         _ => "Untitled".into(),
     };
+    let language = match doc.metadata.get("language") {
+        Some(values) if !values.is_empty() => Some(values[0].clone()),
+        _ => None,
+    };
+    let metadata = Metadata {
+        author: doc.metadata.get("creator").and_then(|v| v.first().cloned()),
+        date: doc.metadata.get("date").and_then(|v| v.first().cloned()),
+        other: Vec::new(),
+    };
 
+    let mut toc_entries = if options.toc {
+        toc::ncx(doc)
+    } else {
+        Vec::new()
+    };
+    let mut locations = HashMap::new();
+    let mut images = ImageTable::new();
     while doc.go_next() {
         let path = match doc.get_current_path() {
             None => continue,
@@ -271,10 +604,26 @@ pub fn build<R: Read + Seek, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator>(
             .and_then(|p| p.to_str())
             .unwrap_or("");
         let base_url = Url::parse(&format!("epub:///{}/", base_path)).unwrap_or(root_url.clone());
+        let url = path.to_str().and_then(toc::url).unwrap_or(base_url.clone());
+        locations.insert(url.clone(), builder.page_count());
 
-        let mut context =
-            LayoutContext::new(doc, &options, &base_url, builder, bounding_box.size.width);
-        let doc = Html::parse_document(&content);
+        let html = Html::parse_document(&content);
+        if options.toc && toc_entries.is_empty() {
+            toc_entries = toc::nav(&html, &url);
+        }
+
+        let mut context = LayoutContext::new(
+            doc,
+            &options,
+            &base_url,
+            &url,
+            builder,
+            &mut images,
+            &mut locations,
+            bounding_box.size.width,
+            bounding_box.size.height,
+        );
+        let doc = &html;
         let root = doc
             .tree
             .root()
@@ -285,10 +634,80 @@ pub fn build<R: Read + Seek, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator>(
         builder = context.builder.take();
     }
 
-    let (styles, commands) = builder.finish();
+    let mut structure = builder.take_structure();
+    let (mut styles, mut commands) = builder.finish();
+    if !toc_entries.is_empty() {
+        let heading = options.heading.as_ref().and_then(|h| h.first());
+        let (toc_styles, toc_commands) = toc::layout(
+            &toc_entries,
+            &locations,
+            bounding_box,
+            fonts,
+            default_style,
+            hyphenator,
+            &options.regular,
+            heading,
+        );
+        let len = prepend(&mut styles, &mut commands, toc_styles, toc_commands);
+        if let Some(structure) = &mut structure {
+            for node in structure {
+                shift_structure(node, len);
+            }
+        }
+    }
+
     let title = options.title.unwrap_or(title);
-    let header = Header { styles, title };
-    Ok((header, commands))
+    let language = options.language.or(language);
+    let header = Header {
+        version: VERSION,
+        styles,
+        title,
+        images,
+        language,
+        revision: None,
+        fonts: Vec::new(),
+        metadata,
+        page: None,
+    };
+    Ok((header, commands, structure))
+}
+
+/// Prepends a command stream laid out against its own style table to a document's commands,
+/// merging the style tables. Returns the number of commands prepended.
+fn prepend(
+    styles: &mut Vec<Style>,
+    commands: &mut Vec<Command<String>>,
+    prefix_styles: Vec<Style>,
+    prefix: Vec<Command<String>>,
+) -> usize {
+    let ids: Vec<u16> = prefix_styles
+        .into_iter()
+        .map(|style| match styles.iter().position(|s| *s == style) {
+            Some(id) => id as u16,
+            None => {
+                styles.push(style);
+                (styles.len() - 1) as u16
+            }
+        })
+        .collect();
+
+    let len = prefix.len();
+    let rest = core::mem::replace(commands, prefix);
+    for command in commands.iter_mut() {
+        if let Command::SetStyle { s } = command {
+            *s = ids[*s as usize];
+        }
+    }
+    commands.extend(rest);
+    len
+}
+
+/// Moves a structure node and its children `len` commands later.
+fn shift_structure(node: &mut StructureNode, len: usize) {
+    node.commands = node.commands.start + len..node.commands.end + len;
+    for child in &mut node.children {
+        shift_structure(child, len);
+    }
 }
 
 struct Handlers<R, S, F, H> {
@@ -297,23 +716,44 @@ struct Handlers<R, S, F, H> {
 
 impl<'a, R: Read + Seek, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> Handlers<R, S, F, H> {
     fn push_style(element: Element, context: &mut LayoutContext<'a, R, S, F, H>) {
+        if let Some(id) = element.attr("id") {
+            let mut url = context.url.clone();
+            url.set_fragment(Some(id));
+            let page = context.builder.page_count();
+            context.locations.entry(url).or_insert(page);
+        }
+
         let style = context.push_style(element);
+        let small_caps = Self::small_caps(context);
         match context.builder {
             BuilderState::Doc(ref mut doc) => doc.set_style(&style),
-            BuilderState::Paragraph(ref mut p) => p.set_style(&style),
+            BuilderState::Paragraph(ref mut p) => {
+                p.set_style(&style);
+                p.set_small_caps(small_caps);
+            }
             _ => unreachable!(),
         };
     }
 
     fn pop_style(context: &mut LayoutContext<'a, R, S, F, H>) {
         let style = context.pop_style();
+        let small_caps = Self::small_caps(context);
         match context.builder {
             BuilderState::Doc(ref mut doc) => doc.set_style(&style),
-            BuilderState::Paragraph(ref mut p) => p.set_style(&style),
+            BuilderState::Paragraph(ref mut p) => {
+                p.set_style(&style);
+                p.set_small_caps(small_caps);
+            }
             _ => unreachable!(),
         };
     }
 
+    /// Returns true if the current element's text is set in small capitals.
+    fn small_caps(context: &LayoutContext<'a, R, S, F, H>) -> bool {
+        let style = &context.computed_style[context.computed_style.len() - 1];
+        style.font_variant == FontVariant::SmallCaps
+    }
+
     fn metadata_content(node: NodeRef<'a, Node>, context: &mut LayoutContext<'a, R, S, F, H>) {
         node.value().is_element().then(|| {
             let elem = Element::new(node);
@@ -337,6 +777,9 @@ impl<'a, R: Read + Seek, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> Handl
             Node::Text(text) => Self::text(text, context),
             Node::Element(_) => {
                 let elem = Element::new(node);
+                if !context.is_displayed(elem) {
+                    return;
+                }
                 Self::push_style(elem, context);
                 eprintln!("flow: {}", elem.value().name());
                 match elem.value().name() {
@@ -367,6 +810,7 @@ impl<'a, R: Read + Seek, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> Handl
                     "em" => Self::em(elem, context),
                     "embed" => Self::embed(elem, context),
                     "fieldset" => Self::fieldset(elem, context),
+                    "figcaption" => Self::figcaption(elem, context),
                     "figure" => Self::figure(elem, context),
                     "footer" => Self::footer(elem, context),
                     "form" => Self::form(elem, context),
@@ -438,6 +882,9 @@ impl<'a, R: Read + Seek, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> Handl
             Node::Text(text) => Self::text(text, context),
             Node::Element(_) => {
                 let elem = Element::new(node);
+                if !context.is_displayed(elem) {
+                    return;
+                }
                 Self::push_style(elem, context);
                 eprintln!("phrasing: {}", elem.value().name());
                 match elem.value().name() {
@@ -616,17 +1063,68 @@ impl<'a, R: Read + Seek, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> Handl
 
     fn aside(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {}
 
-    fn h1(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {}
+    fn h1(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {
+        Self::heading(1, elem, context);
+    }
+
+    fn h2(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {
+        Self::heading(2, elem, context);
+    }
+
+    fn h3(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {
+        Self::heading(3, elem, context);
+    }
 
-    fn h2(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {}
+    fn h4(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {
+        Self::heading(4, elem, context);
+    }
 
-    fn h3(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {}
+    fn h5(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {
+        Self::heading(5, elem, context);
+    }
 
-    fn h4(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {}
+    fn h6(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {
+        Self::heading(6, elem, context);
+    }
 
-    fn h5(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {}
+    fn heading(level: u8, elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {
+        let style = &context.computed_style[context.computed_style.len() - 1];
+        let em_px = style.font_size.0;
+        let mut options = Self::paragraph_options(style);
+
+        // Headings are set off from the text around them even if the stylesheet gives them no
+        // margins.
+        options.margin_top_px = options.margin_top_px.max(em_px);
+        options.margin_bottom_px = options.margin_bottom_px.max(em_px / 2.0);
+
+        context.heading = context
+            .options
+            .heading
+            .as_ref()
+            .and_then(|heading| heading.get(level as usize - 1))
+            .cloned();
+
+        Self::end_paragraph(context);
+        context.builder.if_doc(|doc| {
+            doc.begin_structure();
+            // A heading travels with at least the first line of its body.
+            doc.keep_with_next();
+            if let Some(ref heading) = context.heading {
+                doc.set_style(heading);
+            }
+        });
+        Self::begin_paragraph(options, context);
+
+        for c in elem.children() {
+            Self::phrasing_content(c, context);
+        }
 
-    fn h6(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {}
+        Self::end_paragraph(context);
+        context.heading = None;
+        context
+            .builder
+            .if_doc(|doc| doc.end_structure(StructureKind::Heading { level }));
+    }
 
     fn hgroup(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {}
 
@@ -638,8 +1136,7 @@ impl<'a, R: Read + Seek, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> Handl
 
     // Grouping content
 
-    fn p(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {
-        let style = &context.computed_style[context.computed_style.len() - 1];
+    fn paragraph_options(style: &ComputedStyle) -> ParagraphOptions {
         let align = match style.text_align {
             TextAlign::Left => Align::Left,
             TextAlign::Right => Align::Right,
@@ -647,62 +1144,337 @@ impl<'a, R: Read + Seek, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> Handl
             TextAlign::Justify => Align::Justify,
         };
 
-        let options = ParagraphOptions {
+        ParagraphOptions {
             align,
-            margin_bottom_px: style.margin_bottom.0,
-            margin_left_px: style.margin_left.0,
-            margin_right_px: style.margin_right.0,
-            margin_top_px: style.margin_top.0,
-        };
+            justify_last_line: false,
+            margin_bottom_px: style.margin_bottom.0 + style.padding_bottom.0,
+            margin_left_px: style.margin_left.0 + style.padding_left.0,
+            margin_right_px: style.margin_right.0 + style.padding_right.0,
+            margin_top_px: style.margin_top.0 + style.padding_top.0,
+        }
+    }
 
-        let indent_px = style.text_indent.0;
+    fn begin_paragraph(options: ParagraphOptions, context: &mut LayoutContext<'a, R, S, F, H>) {
+        let list_item = core::mem::replace(&mut context.in_list_item, false);
+        context.builder.map(|b| {
+            let doc = match b {
+                BuilderState::Doc(doc) => doc,
+                BuilderState::Paragraph(p) => p.finish(),
+                _ => unreachable!(),
+            };
+            if list_item {
+                BuilderState::Paragraph(doc.list_item(Some(options)))
+            } else {
+                BuilderState::Paragraph(doc.paragraph(Some(options)))
+            }
+        });
+        let small_caps = Self::small_caps(context);
+        context.builder.paragraph().set_small_caps(small_caps);
+        Self::generated_content(context);
+    }
 
-        context.builder.map(|b| match b {
-            BuilderState::Doc(doc) => BuilderState::Paragraph(doc.paragraph(Some(options))),
-            BuilderState::Paragraph(p) => {
-                BuilderState::Paragraph(p.finish().paragraph(Some(options)))
+    /// Itemizes the pending `::before` content, if any, into the current paragraph.
+    fn generated_content(context: &mut LayoutContext<'a, R, S, F, H>) {
+        if let BuilderState::Paragraph(ref mut p) = context.builder {
+            if let Some(before) = context.before.take() {
+                for c in before.chars() {
+                    p.char(c);
+                }
             }
-            _ => unreachable!(),
+        }
+    }
+
+    fn end_paragraph(context: &mut LayoutContext<'a, R, S, F, H>) {
+        context.builder.map(|b| match b {
+            BuilderState::Paragraph(p) => BuilderState::Doc(p.finish()),
+            doc => doc,
         });
+    }
 
-        // TODO: remove this and take margins into account.
-        if indent_px > 0.0 {
-            context.builder.paragraph().indent_px(indent_px);
+    fn p(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {
+        let style = &context.computed_style[context.computed_style.len() - 1];
+        let options = Self::paragraph_options(style);
+        let text_indent = TextIndent {
+            px: style.text_indent.0,
+            ..Default::default()
+        };
+        let line_height = style.line_height.px(style.font_size.0);
+
+        Self::begin_paragraph(options, context);
+
+        let paragraph = context.builder.paragraph();
+        paragraph.set_text_indent(text_indent);
+        paragraph.set_line_height(line_height.map(|px| px as u16));
+
+        for c in elem.children() {
+            Self::flow_content(c, context);
         }
 
+        Self::end_paragraph(context);
+    }
+
+    fn hr(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {
+        Self::end_paragraph(context);
+        context.builder.if_doc(|doc| doc.horizontal_rule(None));
+    }
+
+    fn pre(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {
+        let style = &context.computed_style[context.computed_style.len() - 1];
+        let options = ParagraphOptions {
+            align: Align::Left,
+            ..Self::paragraph_options(style)
+        };
+
+        Self::begin_paragraph(options, context);
+        Self::set_code_style(context);
+
+        for c in elem.children() {
+            Self::preformatted_content(c, context);
+        }
+
+        Self::end_paragraph(context);
+    }
+
+    fn preformatted_content(node: NodeRef<'a, Node>, context: &mut LayoutContext<'a, R, S, F, H>) {
+        match node.value() {
+            Node::Text(text) => context.builder.if_paragraph(|p| p.verbatim(text)),
+            Node::Element(_) => {
+                let elem = Element::new(node);
+                Self::push_style(elem, context);
+                for c in elem.children() {
+                    Self::preformatted_content(c, context);
+                }
+                Self::pop_style(context);
+            }
+            _ => {}
+        }
+    }
+
+    /// Switches to the code style, if any. The element's own style is restored when it is popped.
+    fn set_code_style(context: &mut LayoutContext<'a, R, S, F, H>) {
+        if let Some(ref style) = context.options.code {
+            match context.builder {
+                BuilderState::Doc(ref mut doc) => doc.set_style(style),
+                BuilderState::Paragraph(ref mut p) => p.set_style(style),
+                _ => unreachable!(),
+            };
+        }
+    }
+
+    fn blockquote(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {
+        // Books set quotes off by indentation alone, so no bar is drawn. The quote's own margin
+        // and padding, if any, indent it in place of the default indent.
+        let style = &context.computed_style[context.computed_style.len() - 1];
+        let margin = style.margin_left.0 + style.padding_left.0;
+
+        Self::end_paragraph(context);
+        context.builder.if_doc(|doc| {
+            if margin > 0.0 {
+                doc.begin_blockquote_with_margin(false, margin);
+            } else {
+                doc.begin_blockquote(false);
+            }
+        });
+
         for c in elem.children() {
             Self::flow_content(c, context);
         }
 
-        context.builder.map(|b| match b {
-            BuilderState::Paragraph(p) => BuilderState::Doc(p.finish()),
-            doc => doc,
+        Self::end_paragraph(context);
+        context.builder.if_doc(|doc| doc.end_blockquote());
+    }
+
+    fn list(kind: ListKind, elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {
+        // The list's own margin and padding, if any, indent its items in place of the default
+        // indent.
+        let style = &context.computed_style[context.computed_style.len() - 1];
+        let indent = style.margin_left.0 + style.padding_left.0;
+
+        Self::end_paragraph(context);
+        context.builder.if_doc(|doc| {
+            if indent > 0.0 {
+                doc.begin_list_with_indent(kind, indent);
+            } else {
+                doc.begin_list(kind);
+            }
         });
+
+        for c in elem.children() {
+            if let Node::Element(e) = c.value() {
+                if e.name() == "li" {
+                    Self::push_style(Element::new(c), context);
+                    Self::li(Element::new(c), context);
+                    Self::pop_style(context);
+                }
+            }
+        }
+
+        context.builder.if_doc(|doc| doc.end_list());
     }
 
-    fn hr(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {}
+    fn ol(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {
+        Self::list(ListKind::Ordered, elem, context);
+    }
 
-    fn pre(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {}
+    fn ul(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {
+        Self::list(ListKind::Unordered, elem, context);
+    }
 
-    fn blockquote(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {}
+    fn menu(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {
+        Self::list(ListKind::Unordered, elem, context);
+    }
 
-    fn ol(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {}
+    fn li(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {
+        // If the item's content begins with a paragraph, the marker goes on that paragraph.
+        // Otherwise the item's content forms a paragraph of its own.
+        context.in_list_item = true;
+        let first = elem.children().find(|c| match c.value() {
+            Node::Text(text) => text.trim() != "",
+            _ => true,
+        });
+        let starts_with_p =
+            matches!(first.map(|c| c.value()), Some(Node::Element(e)) if e.name() == "p");
+        if !starts_with_p {
+            let style = &context.computed_style[context.computed_style.len() - 1];
+            let options = Self::paragraph_options(style);
+            Self::begin_paragraph(options, context);
+        }
 
-    fn ul(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {}
+        for c in elem.children() {
+            Self::flow_content(c, context);
+        }
 
-    fn menu(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {}
+        Self::end_paragraph(context);
+        context.in_list_item = false;
+    }
 
-    fn li(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {}
+    fn dl(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {
+        Self::end_paragraph(context);
 
-    fn dl(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {}
+        // Terms and definitions may be wrapped in a `div` to group them.
+        for c in elem.children() {
+            if let Node::Element(e) = c.value() {
+                match e.name() {
+                    "dt" | "dd" | "div" => {
+                        Self::push_style(Element::new(c), context);
+                        match e.name() {
+                            "dt" => Self::dt(Element::new(c), context),
+                            "dd" => Self::dd(Element::new(c), context),
+                            _ => Self::dl(Element::new(c), context),
+                        }
+                        Self::pop_style(context);
+                    }
+                    _ => {}
+                }
+            }
+        }
 
-    fn dt(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {}
+        Self::end_paragraph(context);
+    }
 
-    fn dd(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {}
+    fn dt(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {
+        let style = &context.computed_style[context.computed_style.len() - 1];
+        let options = Self::paragraph_options(style);
+        Self::begin_paragraph(options, context);
+
+        // Terms are set in the strong style.
+        if let Some(ref style) = context.options.strong {
+            context.builder.paragraph().set_style(style);
+        }
+        for c in elem.children() {
+            Self::phrasing_content(c, context);
+        }
+
+        Self::end_paragraph(context);
+    }
+
+    fn dd(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {
+        // The definition's own margin and padding, if any, indent it in place of the configured
+        // indent. An open list indents the definition's paragraphs without marking them.
+        let style = &context.computed_style[context.computed_style.len() - 1];
+        let margin = style.margin_left.0 + style.padding_left.0;
+        let indent = if margin > 0.0 {
+            Some(margin)
+        } else {
+            context.options.definition_indent
+        };
+        let options = ParagraphOptions {
+            margin_left_px: 0.0,
+            ..Self::paragraph_options(style)
+        };
+
+        Self::end_paragraph(context);
+        context.builder.if_doc(|doc| match indent {
+            None => doc.begin_list(ListKind::Unordered),
+            Some(indent) => doc.begin_list_with_indent(ListKind::Unordered, indent),
+        });
+
+        // If the definition begins with a paragraph, its content is laid out as is. Otherwise the
+        // definition's content forms a paragraph of its own.
+        let first = elem.children().find(|c| match c.value() {
+            Node::Text(text) => text.trim() != "",
+            _ => true,
+        });
+        let starts_with_p =
+            matches!(first.map(|c| c.value()), Some(Node::Element(e)) if e.name() == "p");
+        if !starts_with_p {
+            Self::begin_paragraph(options, context);
+        }
+        for c in elem.children() {
+            Self::flow_content(c, context);
+        }
+
+        Self::end_paragraph(context);
+        context.builder.if_doc(|doc| doc.end_list());
+    }
+
+    fn figure(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {
+        let style = &context.computed_style[context.computed_style.len() - 1];
+        let options = Self::paragraph_options(style);
+
+        Self::end_paragraph(context);
+        context
+            .builder
+            .if_doc(|doc| doc.advance_vertical(options.margin_top_px));
+        let outer = context
+            .figure
+            .replace((options.margin_left_px, options.margin_right_px));
+
+        for c in elem.children() {
+            Self::flow_content(c, context);
+        }
+
+        Self::end_paragraph(context);
+        context.figure = outer;
+        context
+            .builder
+            .if_doc(|doc| doc.advance_vertical(options.margin_bottom_px));
+    }
 
-    fn figure(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {}
+    fn figcaption(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {
+        // Captions are centered within the figure's margins and set in the emphasis style.
+        let style = &context.computed_style[context.computed_style.len() - 1];
+        let (figure_left, figure_right) = context.figure.unwrap_or_default();
+        let options = Self::paragraph_options(style);
+        let options = ParagraphOptions {
+            align: Align::Center,
+            margin_left_px: options.margin_left_px + figure_left,
+            margin_right_px: options.margin_right_px + figure_right,
+            ..options
+        };
 
-    fn figcaption(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {}
+        Self::end_paragraph(context);
+        Self::begin_paragraph(options, context);
+        if let Some(ref style) = context.options.emphasis {
+            context.builder.paragraph().set_style(style);
+        }
+
+        for c in elem.children() {
+            Self::phrasing_content(c, context);
+        }
+
+        Self::end_paragraph(context);
+    }
 
     fn main(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {}
 
@@ -752,7 +1524,12 @@ impl<'a, R: Read + Seek, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> Handl
 
     fn time(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {}
 
-    fn code(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {}
+    fn code(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {
+        Self::set_code_style(context);
+        for c in elem.children() {
+            Self::phrasing_content(c, context);
+        }
+    }
 
     fn var(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {}
 
@@ -806,7 +1583,140 @@ impl<'a, R: Read + Seek, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> Handl
 
     fn source(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {}
 
-    fn img(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {}
+    fn img(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {
+        let image = elem
+            .attr("src")
+            .and_then(|src| Self::image_resource(src, context));
+        let (data, image_width, image_height) = match image {
+            Some(image) => image,
+            None => {
+                Self::alt_text(elem, context);
+                return;
+            }
+        };
+
+        // The image's box is given by its width and height attributes. If only one is given, the
+        // other follows the image's aspect ratio.
+        let attr = |name| elem.attr(name).and_then(|v| v.trim().parse::<u32>().ok());
+        let (width, height) = match (attr("width"), attr("height")) {
+            (Some(w), Some(h)) => (w, h),
+            (Some(w), None) => (w, w * image_height / image_width),
+            (None, Some(h)) => (h * image_width / image_height, h),
+            (None, None) => (image_width, image_height),
+        };
+        let fit = context.computed_style[context.computed_style.len() - 1].object_fit;
+
+        // Images are laid out on their own line. If the image interrupts a paragraph, the
+        // paragraph's remaining content continues below it.
+        let in_paragraph = matches!(context.builder, BuilderState::Paragraph(_));
+        Self::end_paragraph(context);
+
+        let page_height = context.page_height as f32;
+        let figure = context.figure;
+        let images = &mut context.images;
+        context.builder.if_doc(|doc| {
+            let (inset_left, inset_right) = figure.unwrap_or_default();
+            let content_width = doc.content_width() - inset_left - inset_right;
+            let scale = f32::min(
+                1.0,
+                f32::min(content_width / width as f32, page_height / height as f32),
+            );
+            let (width, height) = (
+                (width as f32 * scale) as u16,
+                (height as f32 * scale) as u16,
+            );
+            if width == 0 || height == 0 {
+                return;
+            }
+
+            // Readers stretch images to their box, so images that are fitted any other way are
+            // rendered into their box here.
+            let box_fitted = match fit {
+                ObjectFit::Fill => None,
+                _ if (width as u32, height as u32) == (image_width, image_height) => None,
+                _ => decode_gray(&data).map(|(pixels, w, h)| {
+                    fit_image(&pixels, w, h, width as u32, height as u32, fit)
+                }),
+            };
+            let data_offset = match box_fitted {
+                Some(pixels) => images.push(ImageFormat::Gray8, &pixels),
+                None => images.push(ImageFormat::Png, &data),
+            };
+            match figure {
+                // A figure's image travels with the first line of its caption.
+                Some((inset_left, inset_right)) => {
+                    doc.keep_with_next();
+                    doc.centered_image(data_offset, width, height, inset_left, inset_right);
+                }
+                None => doc.image(data_offset, width, height),
+            }
+        });
+
+        if in_paragraph {
+            let style = &context.computed_style[context.computed_style.len() - 1];
+            let options = ParagraphOptions {
+                margin_top_px: 0.0,
+                ..Self::paragraph_options(style)
+            };
+            Self::begin_paragraph(options, context);
+        }
+    }
+
+    /// Fetches the PNG image at `src`, relative to the current document, returning its data and
+    /// dimensions.
+    fn image_resource(
+        src: &str,
+        context: &mut LayoutContext<'a, R, S, F, H>,
+    ) -> Option<(Vec<u8>, u32, u32)> {
+        let src = match Url::options().base_url(Some(context.base_url)).parse(src) {
+            Ok(src) => src,
+            Err(err) => {
+                eprintln!("failed to parse image src: {}", err);
+                return None;
+            }
+        };
+        if src.scheme() != "epub" {
+            return None;
+        }
+        let path = &src.path()[1..];
+
+        let data = match context.doc.get_resource_by_path(path) {
+            None => {
+                eprintln!("image {} not found", src);
+                return None;
+            }
+            Some(data) => data,
+        };
+
+        // Only PNG images can be embedded; anything else falls back to its alternate text.
+        let (width, height) = match png::Decoder::new(data.as_slice()).read_info() {
+            Ok(reader) => (reader.info().width, reader.info().height),
+            Err(err) => {
+                eprintln!("failed to decode image {}: {}", src, err);
+                return None;
+            }
+        };
+        Some((data, width, height))
+    }
+
+    /// Lays out an image's alternate text in place of the image.
+    fn alt_text(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {
+        let alt = match elem.attr("alt") {
+            Some(alt) if alt.trim() != "" => alt,
+            _ => return,
+        };
+
+        if let BuilderState::Paragraph(ref mut p) = context.builder {
+            p.text(alt);
+            return;
+        }
+
+        let style = &context.computed_style[context.computed_style.len() - 1];
+        let options = Self::paragraph_options(style);
+        Self::begin_paragraph(options, context);
+        context.builder.paragraph().text(alt);
+        Self::end_paragraph(context);
+    }
 
     fn iframe(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {}
 
@@ -828,25 +1738,184 @@ impl<'a, R: Read + Seek, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> Handl
 
     // Tabular data
 
-    fn table(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {}
+    fn table(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {
+        let caption = elem
+            .children()
+            .find(|c| matches!(c.value(), Node::Element(e) if e.name() == "caption"))
+            .map(Element::new);
+
+        // The caption's placement is determined by its computed caption-side.
+        let caption_side = match caption {
+            None => CaptionSide::Top,
+            Some(caption) => {
+                Self::push_style(caption, context);
+                let side = context.computed_style[context.computed_style.len() - 1].caption_side;
+                Self::pop_style(context);
+                side
+            }
+        };
+
+        Self::end_paragraph(context);
+        context.builder.if_doc(|doc| doc.begin_structure());
+
+        // TODO: keep the caption on the same page as the table.
+        if let (Some(caption), CaptionSide::Top) = (caption, caption_side) {
+            Self::push_style(caption, context);
+            Self::caption(caption, context);
+            Self::pop_style(context);
+        }
+
+        for c in elem.children() {
+            if let Node::Element(e) = c.value() {
+                if !matches!(e.name(), "thead" | "tbody" | "tfoot" | "tr") {
+                    continue;
+                }
 
-    fn caption(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {}
+                let child = Element::new(c);
+                Self::push_style(child, context);
+                match e.name() {
+                    "thead" => Self::thead(child, context),
+                    "tbody" => Self::tbody(child, context),
+                    "tfoot" => Self::tfoot(child, context),
+                    _ => Self::tr(child, context),
+                }
+                Self::pop_style(context);
+            }
+        }
+
+        if let (Some(caption), CaptionSide::Bottom) = (caption, caption_side) {
+            Self::push_style(caption, context);
+            Self::caption(caption, context);
+            Self::pop_style(context);
+        }
+
+        context
+            .builder
+            .if_doc(|doc| doc.end_structure(StructureKind::Table));
+    }
+
+    fn caption(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {
+        let style = &context.computed_style[context.computed_style.len() - 1];
+        let options = ParagraphOptions {
+            align: Align::Center,
+            ..Self::paragraph_options(style)
+        };
+
+        context.builder.if_doc(|doc| doc.begin_structure());
+        Self::begin_paragraph(options, context);
+        for c in elem.children() {
+            Self::phrasing_content(c, context);
+        }
+        Self::end_paragraph(context);
+        context
+            .builder
+            .if_doc(|doc| doc.end_structure(StructureKind::TableCaption));
+    }
 
     fn colgroup(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {}
 
     fn col(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {}
 
-    fn tbody(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {}
+    fn table_section(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {
+        for c in elem.children() {
+            if matches!(c.value(), Node::Element(e) if e.name() == "tr") {
+                let row = Element::new(c);
+                Self::push_style(row, context);
+                Self::tr(row, context);
+                Self::pop_style(context);
+            }
+        }
+    }
 
-    fn thead(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {}
+    fn tbody(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {
+        Self::table_section(elem, context);
+    }
 
-    fn tfoot(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {}
+    fn thead(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {
+        Self::table_section(elem, context);
+    }
 
-    fn tr(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {}
+    fn tfoot(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {
+        Self::table_section(elem, context);
+    }
 
-    fn td(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {}
+    fn tr(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {
+        // Lay out each row as a single left-aligned paragraph with its cells separated by an
+        // em-sized gap.
+        let style = &context.computed_style[context.computed_style.len() - 1];
+        let options = ParagraphOptions {
+            align: Align::Left,
+            ..Self::paragraph_options(style)
+        };
+
+        context.builder.if_doc(|doc| doc.begin_structure());
+        Self::begin_paragraph(options, context);
+        let mut cells = Vec::new();
+        let mut first = true;
+        for c in elem.children() {
+            if let Node::Element(e) = c.value() {
+                if !matches!(e.name(), "td" | "th") {
+                    continue;
+                }
+
+                if !first {
+                    let p = context.builder.paragraph();
+                    for _ in 0..3 {
+                        p.whitespace();
+                    }
+                }
+                first = false;
+
+                let cell = Element::new(c);
+                Self::push_style(cell, context);
+                match e.name() {
+                    "td" => {
+                        cells.push(StructureKind::TableDataCell);
+                        Self::td(cell, context);
+                    }
+                    _ => {
+                        let scope = match cell.attr("scope") {
+                            Some("row") => HeaderScope::Row,
+                            Some("col") => HeaderScope::Column,
+                            Some("rowgroup") => HeaderScope::RowGroup,
+                            Some("colgroup") => HeaderScope::ColumnGroup,
+                            _ => HeaderScope::Auto,
+                        };
+                        cells.push(StructureKind::TableHeaderCell { scope });
+                        Self::th(cell, context);
+                    }
+                }
+                Self::pop_style(context);
+            }
+        }
+        Self::end_paragraph(context);
+
+        // The cells' commands are only emitted once the row's line is complete, so they are
+        // recorded afterwards.
+        context.builder.if_doc(|doc| {
+            for kind in cells {
+                doc.begin_structure();
+                doc.end_structure(kind);
+            }
+            doc.end_structure(StructureKind::TableRow);
+        });
+    }
 
-    fn th(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {}
+    fn td(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {
+        for c in elem.children() {
+            Self::phrasing_content(c, context);
+        }
+    }
+
+    fn th(elem: Element<'a>, context: &mut LayoutContext<'a, R, S, F, H>) {
+        // Header cells are set in the strong style.
+        if let Some(ref style) = context.options.strong {
+            context.builder.paragraph().set_style(style);
+        }
+        for c in elem.children() {
+            Self::phrasing_content(c, context);
+        }
+    }
 
     // Forms
 
@@ -901,11 +1970,484 @@ impl<'a, R: Read + Seek, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> Handl
     // Text
 
     fn text(text: &'a Text, context: &mut LayoutContext<'a, R, S, F, H>) {
+        if text.trim() != "" {
+            Self::generated_content(context);
+        }
+        let style = &context.computed_style[context.computed_style.len() - 1];
+        let transform = style.text_transform;
         context.builder.if_paragraph(|p| {
             eprintln!("text: {:?}", text);
             if text.trim() != "" {
-                p.text(text);
+                p.text(transform.apply(text));
             }
         });
     }
 }
+
+/// Decodes a PNG image into 8-bit grayscale pixels, returning the pixels and the image's width
+/// and height.
+fn decode_gray(data: &[u8]) -> Option<(Vec<u8>, u32, u32)> {
+    let mut decoder = png::Decoder::new(data);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info().ok()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).ok()?;
+    let buf = &buf[..info.buffer_size()];
+
+    let channels = info.color_type.samples();
+    let pixels = buf
+        .chunks_exact(channels)
+        .map(|p| match p.len() {
+            1 | 2 => p[0],
+            _ => ((p[0] as u16 * 77 + p[1] as u16 * 150 + p[2] as u16 * 29) >> 8) as u8,
+        })
+        .collect();
+    Some((pixels, info.width, info.height))
+}
+
+/// Renders grayscale pixels into a `width` by `height` box as directed by `fit`, centering the
+/// image in the box. Parts of the box that the image does not reach are white, and parts of the
+/// image that fall outside the box are cropped.
+fn fit_image(
+    pixels: &[u8],
+    image_width: u32,
+    image_height: u32,
+    width: u32,
+    height: u32,
+    fit: ObjectFit,
+) -> Vec<u8> {
+    let scale_x = width as f32 / image_width as f32;
+    let scale_y = height as f32 / image_height as f32;
+    let (scale_x, scale_y) = match fit {
+        ObjectFit::Fill => (scale_x, scale_y),
+        ObjectFit::Contain => (scale_x.min(scale_y), scale_x.min(scale_y)),
+        ObjectFit::Cover => (scale_x.max(scale_y), scale_x.max(scale_y)),
+        ObjectFit::None => (1.0, 1.0),
+        ObjectFit::ScaleDown => {
+            let scale = scale_x.min(scale_y).min(1.0);
+            (scale, scale)
+        }
+    };
+    let left = (width as f32 - image_width as f32 * scale_x) / 2.0;
+    let top = (height as f32 - image_height as f32 * scale_y) / 2.0;
+
+    // Nearest-neighbor scaling.
+    let mut boxed = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        let sy = (y as f32 + 0.5 - top) / scale_y;
+        for x in 0..width {
+            let sx = (x as f32 + 0.5 - left) / scale_x;
+            let inside =
+                sx >= 0.0 && sy >= 0.0 && sx < image_width as f32 && sy < image_height as f32;
+            boxed.push(if inside {
+                pixels[sy as usize * image_width as usize + sx as usize]
+            } else {
+                0xff
+            });
+        }
+    }
+    boxed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::testing::TestFonts;
+    use embedded_graphics::geometry::{Point, Size};
+    use std::io::Cursor;
+
+    fn crc32(data: &[u8]) -> u32 {
+        let mut crc = !0u32;
+        for byte in data {
+            crc ^= *byte as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 != 0 {
+                    (crc >> 1) ^ 0xedb8_8320
+                } else {
+                    crc >> 1
+                };
+            }
+        }
+        !crc
+    }
+
+    /// Returns a zip archive that stores the given files without compression.
+    fn zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+        // Files are dated 1980-01-01, the earliest date a zip archive can represent.
+        const DATE: u16 = 0x21;
+
+        let mut archive = Vec::new();
+        let mut directory = Vec::new();
+        for (name, data) in files {
+            let offset = archive.len() as u32;
+            let crc = crc32(data);
+
+            archive.extend(0x0403_4b50u32.to_le_bytes());
+            for field in [20, 0, 0, 0, DATE] {
+                archive.extend(u16::to_le_bytes(field));
+            }
+            for field in [crc, data.len() as u32, data.len() as u32] {
+                archive.extend(field.to_le_bytes());
+            }
+            archive.extend((name.len() as u16).to_le_bytes());
+            archive.extend(0u16.to_le_bytes());
+            archive.extend(name.as_bytes());
+            archive.extend(*data);
+
+            directory.extend(0x0201_4b50u32.to_le_bytes());
+            for field in [20, 20, 0, 0, 0, DATE] {
+                directory.extend(u16::to_le_bytes(field));
+            }
+            for field in [crc, data.len() as u32, data.len() as u32] {
+                directory.extend(field.to_le_bytes());
+            }
+            for field in [name.len() as u16, 0, 0, 0, 0] {
+                directory.extend(field.to_le_bytes());
+            }
+            directory.extend(0u32.to_le_bytes());
+            directory.extend(offset.to_le_bytes());
+            directory.extend(name.as_bytes());
+        }
+
+        let directory_offset = archive.len() as u32;
+        let count = files.len() as u16;
+        archive.extend(&directory);
+        archive.extend(0x0605_4b50u32.to_le_bytes());
+        for field in [0, 0, count, count] {
+            archive.extend(u16::to_le_bytes(field));
+        }
+        archive.extend((directory.len() as u32).to_le_bytes());
+        archive.extend(directory_offset.to_le_bytes());
+        archive.extend(0u16.to_le_bytes());
+        archive
+    }
+
+    /// Returns a book whose chapters have the given bodies and share the given stylesheet. Like
+    /// most books, it begins with a cover, which is not laid out.
+    fn book(css: &str, chapters: &[&str]) -> EpubDoc<Cursor<Vec<u8>>> {
+        book_with_images(css, chapters, &[])
+    }
+
+    /// Returns a book like [`book`] that also contains the given PNG images.
+    fn book_with_images(
+        css: &str,
+        chapters: &[&str],
+        images: &[(&str, &[u8])],
+    ) -> EpubDoc<Cursor<Vec<u8>>> {
+        let mut names = vec![String::from("cover")];
+        names.extend((1..=chapters.len()).map(|i| format!("chapter{i}")));
+        let mut bodies = vec!["<p>Cover</p>"];
+        bodies.extend(chapters);
+
+        let mut manifest =
+            String::from(r#"<item id="style" href="style.css" media-type="text/css"/>"#);
+        let mut spine = String::new();
+        for name in &names {
+            manifest.push_str(&format!(
+                r#"<item id="{name}" href="{name}.xhtml" media-type="application/xhtml+xml"/>"#
+            ));
+            spine.push_str(&format!(r#"<itemref idref="{name}"/>"#));
+        }
+        for (name, _) in images {
+            manifest.push_str(&format!(
+                r#"<item id="{name}" href="{name}" media-type="image/png"/>"#
+            ));
+        }
+
+        let container = r#"<?xml version="1.0"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>"#;
+        let package = format!(
+            r#"<?xml version="1.0"?>
+<package version="3.0" unique-identifier="id" xmlns="http://www.idpf.org/2007/opf">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="id">test</dc:identifier>
+    <dc:title>Test</dc:title>
+    <dc:language>en</dc:language>
+  </metadata>
+  <manifest>{manifest}</manifest>
+  <spine>{spine}</spine>
+</package>"#
+        );
+        let documents: Vec<(String, String)> = names
+            .iter()
+            .zip(bodies)
+            .map(|(name, body)| {
+                let document = format!(
+                    r#"<?xml version="1.0"?>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+  <head><title>{name}</title><link rel="stylesheet" href="style.css"/></head>
+  <body>{body}</body>
+</html>"#
+                );
+                (format!("OEBPS/{name}.xhtml"), document)
+            })
+            .collect();
+
+        let mut files: Vec<(&str, &[u8])> = vec![
+            ("mimetype", b"application/epub+zip"),
+            ("META-INF/container.xml", container.as_bytes()),
+            ("OEBPS/content.opf", package.as_bytes()),
+            ("OEBPS/style.css", css.as_bytes()),
+        ];
+        files.extend(documents.iter().map(|(n, d)| (n.as_str(), d.as_bytes())));
+        let images: Vec<(String, &[u8])> = images
+            .iter()
+            .map(|(name, data)| (format!("OEBPS/{name}"), *data))
+            .collect();
+        files.extend(images.iter().map(|(n, d)| (n.as_str(), *d)));
+        EpubDoc::from_reader(Cursor::new(zip(&files))).unwrap()
+    }
+
+    /// Returns the options used by the tests, whose regular text is 20px.
+    fn options() -> Options {
+        let regular = Style {
+            font_name: "Regular".into(),
+            em_px: 20,
+        };
+        Options::new(96.0, regular)
+    }
+
+    /// Lays out a book on a 400x600 page.
+    fn layout(
+        doc: &mut EpubDoc<Cursor<Vec<u8>>>,
+        options: Options,
+    ) -> (Header, Vec<Command<String>>) {
+        let bounds = Rectangle::new(Point::zero(), Size::new(400, 600));
+        build(doc, bounds, TestFonts, (), options).unwrap()
+    }
+
+    /// Returns the indent and text of each non-empty line.
+    fn indented_lines(commands: &[Command<String>]) -> Vec<(i32, String)> {
+        let mut lines = vec![(0, String::new())];
+        for command in commands {
+            let (x, text) = lines.last_mut().unwrap();
+            match command {
+                Command::Show { str } => text.push_str(str),
+                Command::Advance { dx } if text.is_empty() => *x += *dx as i32,
+                Command::MoveBy { dx, .. } if text.is_empty() => *x += *dx as i32,
+                Command::LineBreak | Command::PageBreak => lines.push((0, String::new())),
+                _ => {}
+            }
+        }
+        lines
+            .into_iter()
+            .map(|(x, text)| (x, text.trim().to_string()))
+            .filter(|(_, text)| !text.is_empty())
+            .collect()
+    }
+
+    /// Returns the text of each non-empty line.
+    fn lines(commands: &[Command<String>]) -> Vec<String> {
+        let lines = indented_lines(commands);
+        lines.into_iter().map(|(_, text)| text).collect()
+    }
+
+    #[test]
+    fn font_size_adjust_matches_x_height() {
+        // The test font's x-height is 0.4em, so an aspect of 0.5 scales 20px text to 25px.
+        let css = ".adjusted { font-size-adjust: 0.5 }";
+        let mut doc = book(
+            css,
+            &[r#"<p>plain <span class="adjusted">adjusted</span></p>"#],
+        );
+        let (header, _) = layout(&mut doc, options());
+        let sizes: Vec<u16> = header.styles.iter().map(|s| s.em_px).collect();
+        assert!(sizes.contains(&20), "{sizes:?}");
+        assert!(sizes.contains(&25), "{sizes:?}");
+    }
+
+    #[test]
+    fn counters_number_headings() {
+        let css = r#"
+            h2 { counter-increment: chapter }
+            h2::before { content: counter(chapter) ". " }
+        "#;
+        let mut doc = book(
+            css,
+            &["<h2>One</h2><p>a</p><h2>Two</h2><p>b</p><h2>Three</h2>"],
+        );
+        let (_, commands) = layout(&mut doc, options());
+        let headings: Vec<String> = lines(&commands)
+            .into_iter()
+            .filter(|line| !matches!(line.as_str(), "a" | "b"))
+            .collect();
+        assert_eq!(headings, ["1. One", "2. Two", "3. Three"]);
+    }
+
+    #[test]
+    fn caption_side_places_caption() {
+        let table = "<table><caption>Caption</caption><tr><td>Row</td></tr></table>";
+        for (css, expected) in [
+            ("", ["Caption", "Row"]),
+            ("caption { caption-side: bottom }", ["Row", "Caption"]),
+        ] {
+            let mut doc = book(css, &[table]);
+            let (_, commands) = layout(&mut doc, options());
+            assert_eq!(lines(&commands), expected, "{css}");
+        }
+    }
+
+    #[test]
+    fn padding_indents_block_quotes() {
+        let quote = "<blockquote><p>Quoted</p></blockquote>";
+        let mut indents = Vec::new();
+        for css in [
+            "blockquote { margin: 0 15px }",
+            "blockquote { margin: 0 15px; padding: 10px }",
+        ] {
+            let mut doc = book(css, &[quote]);
+            let (_, commands) = layout(&mut doc, options());
+            let lines = indented_lines(&commands);
+            assert_eq!(lines.len(), 1, "{lines:?}");
+            indents.push(lines[0].0);
+        }
+        assert_eq!(indents, [15, 25]);
+    }
+
+    #[test]
+    fn header_cells_are_recorded_and_bold() {
+        let table = r#"<table><tr><th scope="col">Name</th></tr><tr><td>Ada</td></tr></table>"#;
+        let mut doc = book("", &[table]);
+        let bold = Style {
+            font_name: "Bold".into(),
+            em_px: 20,
+        };
+        let bounds = Rectangle::new(Point::zero(), Size::new(400, 600));
+        let options = options().with_strong(Some(bold));
+        let (header, commands, structure) =
+            build_with_structure(&mut doc, bounds, TestFonts, (), options).unwrap();
+
+        let table = structure
+            .iter()
+            .find(|node| node.kind == StructureKind::Table)
+            .unwrap();
+        let cells: Vec<Vec<StructureKind>> = table
+            .children
+            .iter()
+            .map(|row| row.children.iter().map(|cell| cell.kind).collect())
+            .collect();
+        assert_eq!(
+            cells,
+            [
+                vec![StructureKind::TableHeaderCell {
+                    scope: HeaderScope::Column
+                }],
+                vec![StructureKind::TableDataCell],
+            ]
+        );
+
+        // Each cell's text is shown in its style.
+        let mut style = 0;
+        let mut shown = Vec::new();
+        for command in &commands {
+            match command {
+                Command::SetStyle { s } => style = *s,
+                Command::Show { str } if !str.trim().is_empty() => {
+                    let font_name = header.styles[style as usize].font_name.as_str();
+                    shown.push((str.trim(), font_name));
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(shown, [("Name", "Bold"), ("Ada", "Regular")]);
+    }
+
+    /// Returns a grayscale PNG image whose pixels are given by `shade`.
+    fn png(width: u32, height: u32, shade: impl Fn(u32, u32) -> u8) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut encoder = png::Encoder::new(&mut data, width, height);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        let pixels: Vec<u8> = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| shade(x, y))
+            .collect();
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&pixels).unwrap();
+        writer.finish().unwrap();
+        data
+    }
+
+    #[test]
+    fn object_fit_crops_or_letterboxes() {
+        // Each column of the image is a different shade.
+        let image = png(20, 20, |x, _| x as u8 * 10);
+
+        // Returns the rows of the image as drawn in a 10x20 box.
+        let fitted = |fit: &str| {
+            let css = format!("img {{ object-fit: {fit} }}");
+            let chapter = r#"<p><img src="image.png" width="10" height="20"/></p>"#;
+            let mut doc = book_with_images(&css, &[chapter], &[("image.png", &image)]);
+            let (header, commands) = layout(&mut doc, options());
+            let (size, data_offset) = commands
+                .iter()
+                .find_map(|c| match c {
+                    Command::DrawImage {
+                        width,
+                        height,
+                        data_offset,
+                    } => Some(((*width, *height), *data_offset)),
+                    _ => None,
+                })
+                .unwrap();
+            assert_eq!(size, (10, 20));
+            let image = header.images.get(data_offset).unwrap();
+            assert_eq!(image.format, ImageFormat::Gray8);
+            let rows: Vec<Vec<u8>> = image.data.chunks(10).map(<[u8]>::to_vec).collect();
+            rows
+        };
+
+        // Covering the box scales the image to the box's height and crops its sides.
+        let cover = fitted("cover");
+        let middle: Vec<u8> = (5..15).map(|x| x * 10).collect();
+        assert_eq!(cover.len(), 20);
+        assert!(cover.iter().all(|row| *row == middle), "{cover:?}");
+
+        // Containing it scales the image to the box's width and letterboxes it.
+        let contain = fitted("contain");
+        let white = vec![0xff; 10];
+        let every_other: Vec<u8> = (0..10).map(|x| (2 * x + 1) * 10).collect();
+        assert_eq!(contain.len(), 20);
+        assert!(contain[..5].iter().all(|row| *row == white), "{contain:?}");
+        assert!(
+            contain[5..15].iter().all(|row| *row == every_other),
+            "{contain:?}"
+        );
+        assert!(contain[15..].iter().all(|row| *row == white), "{contain:?}");
+    }
+
+    #[test]
+    fn nav_documents_give_the_contents() {
+        let nav = r#"<nav epub:type="toc"><ol>
+            <li><a href="chapter2.xhtml">Part</a>
+              <ol><li><a href="chapter3.xhtml#section">Section</a></li></ol>
+            </li>
+        </ol></nav>"#;
+        let chapters = [nav, "<p>Two</p>", r#"<p id="section">Three</p>"#];
+        let mut doc = book("", &chapters);
+        let (_, commands) = layout(&mut doc, options().with_toc(true));
+
+        // The contents take one page, so the chapters that follow the navigation document begin
+        // on the third and fourth pages. Nested entries are indented by two ems, and page numbers
+        // are set flush right.
+        let lines = indented_lines(&commands);
+        let contents: Vec<(i32, &str)> = lines[..5]
+            .iter()
+            .map(|(x, text)| (*x, text.as_str()))
+            .collect();
+        assert_eq!(
+            contents,
+            [
+                (0, "Contents"),
+                (0, "Part"),
+                (390, "3"),
+                (40, "Section"),
+                (390, "4"),
+            ]
+        );
+    }
+}
@@ -1,10 +1,13 @@
+use std::borrow::Cow;
 use style::{
     properties::{
-        generated::longhands::font_variant_caps::computed_value::T as FontVariantCaps, LonghandId,
+        generated::longhands::font_variant_caps::computed_value::T as FontVariantCaps,
+        generated::longhands::object_fit::computed_value::T as ObjectFitKeyword, LonghandId,
         PropertyDeclaration, PropertyDeclarationBlock, PropertyDeclarationId,
     },
-    values::{computed, specified},
+    values::{computed, generics, specified},
 };
+use unicode_segmentation::UnicodeSegmentation;
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum GenericFontFamily {
@@ -162,6 +165,29 @@ impl FontSizePx {
     }
 }
 
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FontSizeAdjust(pub Option<f32>);
+
+impl FontSizeAdjust {
+    fn compute(&self, block: &PropertyDeclarationBlock) -> Self {
+        let decl_id = PropertyDeclarationId::Longhand(LonghandId::FontSizeAdjust);
+        if let Some((PropertyDeclaration::FontSizeAdjust(adjust), _)) = block.get(decl_id) {
+            match adjust {
+                specified::font::FontSizeAdjust::Value(value) => match value {
+                    computed::font::GenericFontSizeAdjust::None => Self(None),
+                    computed::font::GenericFontSizeAdjust::ExHeight(n) => Self(Some(n.get())),
+                    // Only x-height matching is supported.
+                    _ => Self(None),
+                },
+                // System fonts are not supported.
+                _ => Self(None),
+            }
+        } else {
+            *self
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum FontAngle {
     Normal,
@@ -275,6 +301,29 @@ margin_px!(MarginLeftPx, MarginLeft);
 margin_px!(MarginRightPx, MarginRight);
 margin_px!(MarginTopPx, MarginTop);
 
+macro_rules! padding_px {
+    ($name:ident, $decl_id:ident) => {
+        #[derive(Copy, Clone, Debug, PartialEq)]
+        pub struct $name(pub f32);
+
+        impl $name {
+            fn compute(&self, block: &PropertyDeclarationBlock, context: &LengthContext) -> Self {
+                let decl_id = PropertyDeclarationId::Longhand(LonghandId::$decl_id);
+                if let Some((PropertyDeclaration::$decl_id(length), _)) = block.get(decl_id) {
+                    Self(context.length(&length.0))
+                } else {
+                    *self
+                }
+            }
+        }
+    };
+}
+
+padding_px!(PaddingBottomPx, PaddingBottom);
+padding_px!(PaddingLeftPx, PaddingLeft);
+padding_px!(PaddingRightPx, PaddingRight);
+padding_px!(PaddingTopPx, PaddingTop);
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum TextAlign {
     Left,
@@ -301,6 +350,52 @@ impl TextAlign {
     }
 }
 
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TextTransform {
+    None,
+    Uppercase,
+    Lowercase,
+    Capitalize,
+}
+
+impl TextTransform {
+    fn compute(&self, block: &PropertyDeclarationBlock) -> Self {
+        let decl_id = PropertyDeclarationId::Longhand(LonghandId::TextTransform);
+        if let Some((PropertyDeclaration::TextTransform(transform), _)) = block.get(decl_id) {
+            if transform.contains(specified::TextTransform::UPPERCASE) {
+                TextTransform::Uppercase
+            } else if transform.contains(specified::TextTransform::LOWERCASE) {
+                TextTransform::Lowercase
+            } else if transform.contains(specified::TextTransform::CAPITALIZE) {
+                TextTransform::Capitalize
+            } else {
+                TextTransform::None
+            }
+        } else {
+            *self
+        }
+    }
+
+    /// Transforms text as it is laid out. Small caps are synthesized from the transformed text, so
+    /// text that is uppercased here is drawn in full-size capitals.
+    pub fn apply<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        match self {
+            TextTransform::None => Cow::Borrowed(text),
+            TextTransform::Uppercase => Cow::Owned(text.to_uppercase()),
+            TextTransform::Lowercase => Cow::Owned(text.to_lowercase()),
+            TextTransform::Capitalize => Cow::Owned(
+                text.split_word_bounds()
+                    .flat_map(|word| {
+                        let mut chars = word.chars();
+                        let first = chars.next().into_iter().flat_map(char::to_uppercase);
+                        first.chain(chars)
+                    })
+                    .collect(),
+            ),
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct TextIndentPx(pub f32);
 
@@ -315,35 +410,162 @@ impl TextIndentPx {
     }
 }
 
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LineHeightPx {
+    /// The height given by the font's metrics.
+    Normal,
+    /// A multiple of the font size. Numbers are inherited as-is, so they scale with the font size
+    /// of each descendant.
+    Number(f32),
+    Px(f32),
+}
+
+impl LineHeightPx {
+    fn compute(&self, block: &PropertyDeclarationBlock, context: &LengthContext) -> Self {
+        let decl_id = PropertyDeclarationId::Longhand(LonghandId::LineHeight);
+        if let Some((PropertyDeclaration::LineHeight(height), _)) = block.get(decl_id) {
+            match height {
+                generics::text::GenericLineHeight::Normal => LineHeightPx::Normal,
+                generics::text::GenericLineHeight::Number(n) => LineHeightPx::Number(n.get()),
+                generics::text::GenericLineHeight::Length(length) => {
+                    LineHeightPx::Px(context.length(&length.0))
+                }
+                // Other line heights, such as -moz-block-height, are not supported.
+                _ => LineHeightPx::Normal,
+            }
+        } else {
+            *self
+        }
+    }
+
+    /// Returns the line height in pixels for text of the given font size, or `None` if the
+    /// height is given by the font's metrics.
+    pub fn px(&self, font_size: f32) -> Option<f32> {
+        match self {
+            LineHeightPx::Normal => None,
+            LineHeightPx::Number(n) => Some(n * font_size),
+            LineHeightPx::Px(px) => Some(*px),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CaptionSide {
+    Top,
+    Bottom,
+}
+
+impl CaptionSide {
+    fn compute(&self, block: &PropertyDeclarationBlock) -> Self {
+        let decl_id = PropertyDeclarationId::Longhand(LonghandId::CaptionSide);
+        if let Some((PropertyDeclaration::CaptionSide(side), _)) = block.get(decl_id) {
+            match side {
+                specified::table::CaptionSide::Bottom => CaptionSide::Bottom,
+                _ => CaptionSide::Top,
+            }
+        } else {
+            *self
+        }
+    }
+}
+
+/// Whether an element is displayed. Unlike the other properties, `display` is not inherited.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Display {
+    Normal,
+    None,
+}
+
+impl Display {
+    pub fn compute(block: &PropertyDeclarationBlock) -> Self {
+        let decl_id = PropertyDeclarationId::Longhand(LonghandId::Display);
+        match block.get(decl_id) {
+            Some((PropertyDeclaration::Display(display), _)) if display.is_none() => Display::None,
+            _ => Display::Normal,
+        }
+    }
+}
+
+/// How an image is fitted to its box. Like `display`, `object-fit` is not inherited.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ObjectFit {
+    /// The image is stretched to fill the box.
+    Fill,
+    /// The image is scaled to fit inside the box, which is letterboxed.
+    Contain,
+    /// The image is scaled to cover the box, and cropped to it.
+    Cover,
+    /// The image keeps its size, centered in the box.
+    None,
+    /// The image is fitted like `Contain`, but never enlarged.
+    ScaleDown,
+}
+
+impl ObjectFit {
+    pub fn compute(block: &PropertyDeclarationBlock) -> Self {
+        let decl_id = PropertyDeclarationId::Longhand(LonghandId::ObjectFit);
+        match block.get(decl_id) {
+            Some((PropertyDeclaration::ObjectFit(fit), _)) => match fit {
+                ObjectFitKeyword::Fill => ObjectFit::Fill,
+                ObjectFitKeyword::Contain => ObjectFit::Contain,
+                ObjectFitKeyword::Cover => ObjectFit::Cover,
+                ObjectFitKeyword::None => ObjectFit::None,
+                ObjectFitKeyword::ScaleDown => ObjectFit::ScaleDown,
+            },
+            _ => ObjectFit::Fill,
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct ComputedStyle {
+    pub caption_side: CaptionSide,
+    pub display: Display,
     pub font_family: GenericFontFamily,
     pub font_size: FontSizePx,
+    pub font_size_adjust: FontSizeAdjust,
     pub font_style: FontAngle,
     pub font_variant: FontVariant,
     pub font_weight: FontWeight,
+    pub line_height: LineHeightPx,
     pub margin_bottom: MarginBottomPx,
     pub margin_left: MarginLeftPx,
     pub margin_right: MarginRightPx,
     pub margin_top: MarginTopPx,
+    pub object_fit: ObjectFit,
+    pub padding_bottom: PaddingBottomPx,
+    pub padding_left: PaddingLeftPx,
+    pub padding_right: PaddingRightPx,
+    pub padding_top: PaddingTopPx,
     pub text_align: TextAlign,
     pub text_indent: TextIndentPx,
+    pub text_transform: TextTransform,
 }
 
 impl ComputedStyle {
     pub fn new(em_px: f32) -> Self {
         Self {
+            caption_side: CaptionSide::Top,
+            display: Display::Normal,
             font_family: GenericFontFamily::Serif,
             font_size: FontSizePx(em_px),
+            font_size_adjust: FontSizeAdjust(None),
             font_style: FontAngle::Normal,
             font_variant: FontVariant::Normal,
+            line_height: LineHeightPx::Normal,
             margin_bottom: MarginBottomPx(0.0),
             margin_left: MarginLeftPx(0.0),
             margin_right: MarginRightPx(0.0),
             margin_top: MarginTopPx(0.0),
+            object_fit: ObjectFit::Fill,
+            padding_bottom: PaddingBottomPx(0.0),
+            padding_left: PaddingLeftPx(0.0),
+            padding_right: PaddingRightPx(0.0),
+            padding_top: PaddingTopPx(0.0),
             font_weight: FontWeight(400.0),
             text_align: TextAlign::Justify,
             text_indent: TextIndentPx(0.0),
+            text_transform: TextTransform::None,
         }
     }
 
@@ -352,17 +574,89 @@ impl ComputedStyle {
         let margin_context = context.length_context_for_margin(font_size.0);
         let text_context = context.length_context_for_font_size(font_size.0);
         Self {
+            caption_side: self.caption_side.compute(block),
+            display: Display::compute(block),
             font_family: self.font_family.compute(block),
             font_size,
+            font_size_adjust: self.font_size_adjust.compute(block),
             font_style: self.font_style.compute(block),
             font_variant: self.font_variant.compute(block),
             font_weight: self.font_weight.compute(block),
+            line_height: self.line_height.compute(block, &text_context),
             margin_bottom: self.margin_bottom.compute(block, &margin_context),
             margin_left: self.margin_left.compute(block, &margin_context),
             margin_right: self.margin_right.compute(block, &margin_context),
             margin_top: self.margin_top.compute(block, &margin_context),
+            object_fit: ObjectFit::compute(block),
+            padding_bottom: self.padding_bottom.compute(block, &margin_context),
+            padding_left: self.padding_left.compute(block, &margin_context),
+            padding_right: self.padding_right.compute(block, &margin_context),
+            padding_top: self.padding_top.compute(block, &margin_context),
             text_align: self.text_align.compute(block),
             text_indent: self.text_indent.compute(block, &text_context),
+            text_transform: self.text_transform.compute(block),
         }
     }
 }
+
+/// Returns the counters reset by a declaration block and the values they are reset to.
+pub fn counter_resets(block: &PropertyDeclarationBlock) -> Vec<(String, i32)> {
+    let decl_id = PropertyDeclarationId::Longhand(LonghandId::CounterReset);
+    match block.get(decl_id) {
+        Some((PropertyDeclaration::CounterReset(reset), _)) => reset
+            .iter()
+            .map(|pair| (pair.name.0.to_string(), pair.value.value()))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Returns the counters incremented by a declaration block and the amounts they are incremented
+/// by.
+pub fn counter_increments(block: &PropertyDeclarationBlock) -> Vec<(String, i32)> {
+    let decl_id = PropertyDeclarationId::Longhand(LonghandId::CounterIncrement);
+    match block.get(decl_id) {
+        Some((PropertyDeclaration::CounterIncrement(increment), _)) => increment
+            .iter()
+            .map(|pair| (pair.name.0.to_string(), pair.value.value()))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// An item of generated content.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ContentItem {
+    String(String),
+    /// The value of the innermost instance of the named counter.
+    Counter(String),
+    /// The values of all instances of the named counter, outermost first, joined by the given
+    /// separator.
+    Counters(String, String),
+}
+
+/// Returns the generated content given by a declaration block, if any. Items other than strings
+/// and counters are ignored.
+pub fn content(block: &PropertyDeclarationBlock) -> Option<Vec<ContentItem>> {
+    let decl_id = PropertyDeclarationId::Longhand(LonghandId::Content);
+    match block.get(decl_id) {
+        Some((PropertyDeclaration::Content(generics::counters::Content::Items(items)), _)) => Some(
+            items
+                .iter()
+                .filter_map(|item| match item {
+                    generics::counters::ContentItem::String(s) => {
+                        Some(ContentItem::String(String::from(&**s)))
+                    }
+                    generics::counters::ContentItem::Counter(name, _) => {
+                        Some(ContentItem::Counter(name.0.to_string()))
+                    }
+                    generics::counters::ContentItem::Counters(name, separator, _) => Some(
+                        ContentItem::Counters(name.0.to_string(), String::from(&**separator)),
+                    ),
+                    _ => None,
+                })
+                .collect(),
+        ),
+        _ => None,
+    }
+}
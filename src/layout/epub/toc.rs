@@ -0,0 +1,209 @@
+use crate::{
+    layout::{Align, Builder, FontStyle, Fonts, Hyphenator, ParagraphOptions, WhitespaceMetrics},
+    Command, Style,
+};
+
+use embedded_graphics::primitives::Rectangle;
+use epub::doc::{EpubDoc, NavPoint};
+use scraper::{html::Html, ElementRef};
+use std::collections::HashMap;
+use std::io::{Read, Seek};
+use url::Url;
+
+/// The most layouts attempted while settling the number of pages taken by a table of contents.
+const MAX_TOC_LAYOUTS: usize = 4;
+
+/// An entry in a book's table of contents.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TocEntry {
+    pub label: String,
+    /// The document the entry refers to and, if the entry refers to a location within it, the
+    /// fragment that identifies that location. Entries that only group other entries have no
+    /// target.
+    pub target: Option<Url>,
+    pub children: Vec<TocEntry>,
+}
+
+/// Returns the URL of a path within the book's container.
+pub fn url(path: &str) -> Option<Url> {
+    Url::parse(&format!("epub:///{}", path.trim_start_matches('/'))).ok()
+}
+
+/// Returns the table of contents given by the book's NCX document, if any.
+pub fn ncx<R: Read + Seek>(doc: &EpubDoc<R>) -> Vec<TocEntry> {
+    fn entry(point: &NavPoint) -> TocEntry {
+        TocEntry {
+            label: point.label.trim().to_string(),
+            target: point.content.to_str().and_then(url),
+            children: point.children.iter().map(entry).collect(),
+        }
+    }
+    doc.toc.iter().map(entry).collect()
+}
+
+/// Returns the table of contents given by the `nav` element of type `toc` in an EPUB 3
+/// navigation document at `url`, if the document has one.
+pub fn nav(html: &Html, url: &Url) -> Vec<TocEntry> {
+    let nav = html.tree.nodes().filter_map(ElementRef::wrap).find(|e| {
+        e.value().name() == "nav"
+            && e.value()
+                .attr("epub:type")
+                .map_or(false, |t| t.split_whitespace().any(|t| t == "toc"))
+    });
+    match nav.and_then(|nav| child_element(nav, "ol")) {
+        None => Vec::new(),
+        Some(list) => nav_list(list, url),
+    }
+}
+
+fn child_element<'a>(element: ElementRef<'a>, name: &str) -> Option<ElementRef<'a>> {
+    element
+        .children()
+        .filter_map(ElementRef::wrap)
+        .find(|e| e.value().name() == name)
+}
+
+fn nav_list(list: ElementRef, url: &Url) -> Vec<TocEntry> {
+    list.children()
+        .filter_map(ElementRef::wrap)
+        .filter(|e| e.value().name() == "li")
+        .filter_map(|item| {
+            // Each item is labeled by a link or, if it only groups other items, a span.
+            let label = item.children().filter_map(ElementRef::wrap).find(|e| {
+                let name = e.value().name();
+                name == "a" || name == "span"
+            })?;
+            let target = label
+                .value()
+                .attr("href")
+                .and_then(|href| Url::options().base_url(Some(url)).parse(href).ok());
+            let children = match child_element(item, "ol") {
+                None => Vec::new(),
+                Some(list) => nav_list(list, url),
+            };
+            Some(TocEntry {
+                label: label.text().collect::<String>().trim().to_string(),
+                target,
+                children,
+            })
+        })
+        .collect()
+}
+
+/// Returns the page index recorded for an entry's target. Targets whose fragment was not found
+/// fall back to the start of their document.
+fn page(locations: &HashMap<Url, usize>, target: &Url) -> Option<usize> {
+    locations.get(target).copied().or_else(|| {
+        let mut document = target.clone();
+        document.set_fragment(None);
+        locations.get(&document).copied()
+    })
+}
+
+/// Lays out the pages of a table of contents. Each entry is indented by its depth and followed
+/// by the number of the page that holds its target, counting the contents' own pages, which
+/// precede the page at index 0 in `locations`.
+#[allow(clippy::too_many_arguments)]
+pub fn layout<S: FontStyle, F: Fonts<Style = S> + Clone, H: Hyphenator + Clone>(
+    entries: &[TocEntry],
+    locations: &HashMap<Url, usize>,
+    bounding_box: Rectangle,
+    fonts: F,
+    default_style: S,
+    hyphenator: H,
+    regular: &Style,
+    heading: Option<&Style>,
+) -> (Vec<Style>, Vec<Command<String>>) {
+    // The page numbers depend on the number of pages taken by the contents, which in turn may
+    // depend on the width of the page numbers.
+    let mut pages = 1;
+    let mut attempts = 0;
+    loop {
+        let builder = Builder::new(
+            bounding_box,
+            fonts.clone(),
+            default_style.clone(),
+            hyphenator.clone(),
+            WhitespaceMetrics::default(),
+        );
+        let builder = layout_pages(builder, entries, locations, pages, regular, heading);
+
+        attempts += 1;
+        if builder.page_count() == pages || attempts == MAX_TOC_LAYOUTS {
+            return builder.finish();
+        }
+        pages = builder.page_count();
+    }
+}
+
+fn layout_pages<S: FontStyle, F: Fonts<Style = S>, H: Hyphenator>(
+    builder: Builder<S, F, H>,
+    entries: &[TocEntry],
+    locations: &HashMap<Url, usize>,
+    pages: usize,
+    regular: &Style,
+    heading: Option<&Style>,
+) -> Builder<S, F, H> {
+    let mut p = builder.paragraph(Some(ParagraphOptions {
+        align: Align::Left,
+        justify_last_line: false,
+        margin_bottom_px: regular.em_px as f32,
+        margin_left_px: 0.0,
+        margin_right_px: 0.0,
+        margin_top_px: 0.0,
+    }));
+    p.set_style(heading.unwrap_or(regular));
+    p.text("Contents");
+    p.set_style(regular);
+    let builder = p.finish();
+
+    let mut builder = layout_entries(builder, entries, locations, pages, regular, 0);
+    builder.set_style(regular);
+    builder.page_break();
+    builder
+}
+
+fn layout_entries<S: FontStyle, F: Fonts<Style = S>, H: Hyphenator>(
+    mut builder: Builder<S, F, H>,
+    entries: &[TocEntry],
+    locations: &HashMap<Url, usize>,
+    pages: usize,
+    regular: &Style,
+    depth: usize,
+) -> Builder<S, F, H> {
+    let em = regular.em_px as f32;
+    for entry in entries {
+        let number = match entry.target.as_ref().and_then(|t| page(locations, t)) {
+            Some(page) => (pages + page + 1).to_string(),
+            None => String::new(),
+        };
+        let number_width = match builder.font_style(regular) {
+            Some(style) => style.measure_string(&number).bounding_box.size.width as f32,
+            None => 0.0,
+        };
+
+        let content_width = builder.content_width();
+        let indent = depth as f32 * 2.0 * em;
+        let label_width = (content_width - indent - number_width - em).max(em);
+
+        builder.begin_row();
+        let mut p = builder.row_cell(indent, label_width);
+        p.text(&entry.label);
+        let mut p = p
+            .finish()
+            .row_cell(content_width - number_width, number_width);
+        p.text(&number);
+        builder = p.finish();
+        builder.end_row();
+
+        builder = layout_entries(
+            builder,
+            &entry.children,
+            locations,
+            pages,
+            regular,
+            depth + 1,
+        );
+    }
+    builder
+}
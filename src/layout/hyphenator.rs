@@ -0,0 +1,120 @@
+use crate::layout::Hyphenator;
+use alloc::{boxed::Box, vec::Vec};
+
+/// The writing system of a word, as far as hyphenation is concerned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Script {
+    Latin,
+    Greek,
+    Cyrillic,
+    Armenian,
+    Georgian,
+    Hebrew,
+    Arabic,
+    Devanagari,
+    Bengali,
+    Tamil,
+    Thai,
+}
+
+impl Script {
+    /// Returns the script of the given character, or `None` if the character is not part of a
+    /// supported script (e.g. digits and punctuation).
+    pub fn of(c: char) -> Option<Script> {
+        match c {
+            'A'..='Z'
+            | 'a'..='z'
+            | '\u{00C0}'..='\u{00D6}'
+            | '\u{00D8}'..='\u{00F6}'
+            | '\u{00F8}'..='\u{024F}'
+            | '\u{1E00}'..='\u{1EFF}' => Some(Script::Latin),
+            '\u{0370}'..='\u{03FF}' | '\u{1F00}'..='\u{1FFF}' => Some(Script::Greek),
+            '\u{0400}'..='\u{052F}' => Some(Script::Cyrillic),
+            '\u{0530}'..='\u{058F}' => Some(Script::Armenian),
+            '\u{10A0}'..='\u{10FF}' => Some(Script::Georgian),
+            '\u{0590}'..='\u{05FF}' => Some(Script::Hebrew),
+            '\u{0600}'..='\u{06FF}' => Some(Script::Arabic),
+            '\u{0900}'..='\u{097F}' => Some(Script::Devanagari),
+            '\u{0980}'..='\u{09FF}' => Some(Script::Bengali),
+            '\u{0B80}'..='\u{0BFF}' => Some(Script::Tamil),
+            '\u{0E00}'..='\u{0E7F}' => Some(Script::Thai),
+            _ => None,
+        }
+    }
+
+    /// Returns the script of the first character in `word` that belongs to a supported script.
+    pub fn of_word(word: &str) -> Option<Script> {
+        word.chars().find_map(Script::of)
+    }
+}
+
+/// A hyphenator that routes each word to an inner hyphenator based on the word's script.
+///
+/// Words in scripts without a registered hyphenator are not hyphenated.
+#[derive(Default)]
+pub struct CompositeHyphenator<'a> {
+    hyphenators: Vec<(Script, Box<dyn Hyphenator + 'a>)>,
+}
+
+impl<'a> CompositeHyphenator<'a> {
+    pub fn new() -> Self {
+        CompositeHyphenator {
+            hyphenators: Vec::new(),
+        }
+    }
+
+    /// Registers the hyphenator for the given script, replacing any existing hyphenator.
+    pub fn with<H: Hyphenator + 'a>(mut self, script: Script, hyphenator: H) -> Self {
+        self.hyphenators.retain(|(s, _)| *s != script);
+        self.hyphenators.push((script, Box::new(hyphenator)));
+        self
+    }
+}
+
+impl<'a> Hyphenator for CompositeHyphenator<'a> {
+    fn hyphenate(&self, word: &str, breaks: &mut Vec<usize>) {
+        let hyphenator = Script::of_word(word)
+            .and_then(|script| self.hyphenators.iter().find(|(s, _)| *s == script));
+        match hyphenator {
+            Some((_, h)) => h.hyphenate(word, breaks),
+            None => breaks.clear(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    /// A hyphenator that breaks every word at the same offset.
+    struct At(usize);
+
+    impl Hyphenator for At {
+        fn hyphenate(&self, _word: &str, breaks: &mut Vec<usize>) {
+            breaks.clear();
+            breaks.push(self.0);
+        }
+    }
+
+    #[test]
+    fn words_are_routed_by_script() {
+        let hyphenator = CompositeHyphenator::new()
+            .with(Script::Latin, At(2))
+            .with(Script::Cyrillic, At(4));
+
+        let mut breaks = Vec::new();
+        hyphenator.hyphenate("hyphenation", &mut breaks);
+        assert_eq!(breaks, vec![2]);
+        hyphenator.hyphenate("перенос", &mut breaks);
+        assert_eq!(breaks, vec![4]);
+
+        // Words in other scripts are not hyphenated.
+        hyphenator.hyphenate("συλλαβισμός", &mut breaks);
+        assert_eq!(breaks, vec![]);
+
+        // Words are routed by their first letter, not by leading punctuation.
+        hyphenator.hyphenate("«перенос»", &mut breaks);
+        assert_eq!(breaks, vec![4]);
+    }
+}
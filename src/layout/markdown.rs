@@ -1,11 +1,15 @@
 use crate::{
-    layout::{Align, Builder, FontStyle, Fonts, Hyphenator, ParagraphBuilder},
-    Command, Header, Style,
+    layout::{
+        Align, Builder, FontStyle, Fonts, HeaderScope, Hyphenator, ListKind, ParagraphBuilder,
+        ParagraphOptions, StructureKind, StructureNode, WhitespaceMetrics,
+    },
+    Command, Header, ImageTable, Metadata, Style, VERSION,
 };
 
 use alloc::string::String;
 use alloc::vec::*;
 use embedded_graphics::primitives::Rectangle;
+use hashbrown::HashMap;
 use markdown::event::{Event, Kind, Name};
 use markdown::util::{
     constant::CHARACTER_REFERENCES,
@@ -22,7 +26,10 @@ pub struct Options {
     emphasis: Option<Style>,
     strong: Option<Style>,
     heading: Option<Vec<Style>>,
+    code: Option<Style>,
+    footnote_links: bool,
     title: Option<String>,
+    language: Option<String>,
 }
 
 impl Options {
@@ -32,7 +39,10 @@ impl Options {
             emphasis: None,
             strong: None,
             heading: None,
+            code: None,
+            footnote_links: false,
             title: None,
+            language: None,
         }
     }
 
@@ -51,10 +61,28 @@ impl Options {
         self
     }
 
+    pub fn with_code(mut self, code: Option<Style>) -> Self {
+        self.code = code;
+        self
+    }
+
+    /// Follow the text of each link with a superscript number that refers to the link's
+    /// destination in a list of footnotes at the end of the document. Links to the same
+    /// destination share a number.
+    pub fn with_footnote_links(mut self, footnote_links: bool) -> Self {
+        self.footnote_links = footnote_links;
+        self
+    }
+
     pub fn with_title<S: AsRef<str>>(mut self, title: Option<S>) -> Self {
         self.title = title.map(|s| s.as_ref().into());
         self
     }
+
+    pub fn with_language<S: AsRef<str>>(mut self, language: Option<S>) -> Self {
+        self.language = language.map(|s| s.as_ref().into());
+        self
+    }
 }
 
 enum BuilderState<'a, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> {
@@ -69,6 +97,13 @@ impl<'a, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> BuilderState<'a, S, F
         let _ = core::mem::replace(self, next);
     }
 
+    fn doc(&mut self) -> &mut Builder<S, F, H> {
+        match self {
+            BuilderState::Doc(ref mut d) => d,
+            _ => panic!("builder is not in a document"),
+        }
+    }
+
     fn paragraph(&mut self) -> &mut ParagraphBuilder<'a, S, F, H> {
         match self {
             BuilderState::Paragraph(ref mut p) => p,
@@ -90,6 +125,25 @@ struct LayoutContext<'a, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> {
     index: usize,
     in_paragraph: bool,
     in_link_destination: bool,
+    // True if the next paragraph begins a list item.
+    in_list_item: bool,
+    in_code: bool,
+    in_code_fence: bool,
+    // True once the first line of the current code block has been seen.
+    in_code_content: bool,
+    in_code_text: bool,
+    // Destinations of link reference definitions, by normalized label.
+    definitions: HashMap<String, String>,
+    // Label and destination of the current link.
+    link_label: Option<String>,
+    link_destination: Option<String>,
+    // Destinations of footnote links, in order of their numbers.
+    footnotes: Vec<String>,
+    // Left edges and widths of the current table's columns.
+    table_columns: Vec<(f32, f32)>,
+    // Index of the current table cell within its row.
+    table_column: usize,
+    in_table_head: bool,
 }
 
 impl<'a, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> LayoutContext<'a, S, F, H> {
@@ -110,6 +164,18 @@ impl<'a, S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> LayoutContext<'a, S,
             index: 0,
             in_paragraph: false,
             in_link_destination: false,
+            in_list_item: false,
+            in_code: false,
+            in_code_fence: false,
+            in_code_content: false,
+            in_code_text: false,
+            definitions: HashMap::new(),
+            link_label: None,
+            link_destination: None,
+            footnotes: Vec::new(),
+            table_columns: Vec::new(),
+            table_column: 0,
+            in_table_head: false,
         }
     }
 }
@@ -123,13 +189,68 @@ pub fn build<S: FontStyle, F: Fonts<Style = S>, H: Hyphenator>(
     hyphenator: H,
     options: Options,
 ) -> Result<(Header, Vec<Command<String>>), Error> {
+    build_document(
+        events,
+        bytes,
+        bounding_box,
+        fonts,
+        hyphenator,
+        options,
+        false,
+    )
+    .map(|(header, commands, _)| (header, commands))
+}
+
+/// Turn events and bytes into an edf document, recording the document's logical structure
+/// (headings, paragraphs, and lists) alongside its commands.
+pub fn build_with_structure<S: FontStyle, F: Fonts<Style = S>, H: Hyphenator>(
+    events: &[Event],
+    bytes: &[u8],
+    bounding_box: Rectangle,
+    fonts: F,
+    hyphenator: H,
+    options: Options,
+) -> Result<(Header, Vec<Command<String>>, Vec<StructureNode>), Error> {
+    build_document(
+        events,
+        bytes,
+        bounding_box,
+        fonts,
+        hyphenator,
+        options,
+        true,
+    )
+    .map(|(header, commands, structure)| (header, commands, structure.unwrap_or_default()))
+}
+
+fn build_document<S: FontStyle, F: Fonts<Style = S>, H: Hyphenator>(
+    events: &[Event],
+    bytes: &[u8],
+    bounding_box: Rectangle,
+    fonts: F,
+    hyphenator: H,
+    options: Options,
+    record_structure: bool,
+) -> Result<(Header, Vec<Command<String>>, Option<Vec<StructureNode>>), Error> {
     let default_style = match fonts.get_style(&options.regular) {
         None => return Err(Error::Generic("missing font for regular style")),
         Some(s) => s,
     };
 
-    let builder = Builder::new(bounding_box, fonts, default_style, hyphenator);
+    let mut builder = Builder::new(
+        bounding_box,
+        fonts,
+        default_style,
+        hyphenator,
+        WhitespaceMetrics::default(),
+    );
+    if record_structure {
+        builder.record_structure();
+    }
     let mut context = LayoutContext::new(events, bytes, options, builder);
+    if context.options.footnote_links {
+        context.definitions = definitions(events, bytes);
+    }
 
     let mut index = 0;
     while index < events.len() {
@@ -137,15 +258,87 @@ pub fn build<S: FontStyle, F: Fonts<Style = S>, H: Hyphenator>(
         index += 1;
     }
 
-    let builder = match context.builder {
+    let mut builder = match context.builder {
         BuilderState::Paragraph(p) => p.finish(),
         BuilderState::Doc(b) => b,
         _ => panic!("unexpected state"),
     };
+    if !context.footnotes.is_empty() {
+        builder = footnotes(builder, &context.footnotes);
+    }
+    let structure = builder.take_structure();
     let (styles, commands) = builder.finish();
     let title = context.options.title.unwrap_or("Untitled".into());
-    let header = Header { styles, title };
-    Ok((header, commands))
+    let language = context.options.language;
+    let header = Header {
+        version: VERSION,
+        styles,
+        title,
+        images: ImageTable::new(),
+        language,
+        revision: None,
+        fonts: Vec::new(),
+        metadata: Metadata::default(),
+        page: None,
+    };
+    Ok((header, commands, structure))
+}
+
+/// Collects the destinations of the link reference definitions in a document by normalized label.
+fn definitions(events: &[Event], bytes: &[u8]) -> HashMap<String, String> {
+    let mut definitions = HashMap::new();
+    let mut label = None;
+    for (index, event) in events.iter().enumerate() {
+        if event.kind != Kind::Exit {
+            continue;
+        }
+        let slice = || {
+            let event_pos = SlicePosition::from_exit_event(events, index);
+            String::from(Slice::from_position(bytes, &event_pos).as_str())
+        };
+        match event.name {
+            Name::DefinitionLabelString => label = Some(normalize_label(&slice())),
+            Name::DefinitionDestinationString => {
+                // The first definition of a label takes precedence.
+                if let Some(label) = label.take() {
+                    definitions.entry(label).or_insert_with(slice);
+                }
+            }
+            _ => {}
+        }
+    }
+    definitions
+}
+
+/// Normalizes a link label for matching: labels are compared case-insensitively with runs of
+/// whitespace collapsed.
+fn normalize_label(label: &str) -> String {
+    label
+        .split_whitespace()
+        .map(|word| word.to_lowercase())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Lays out a numbered paragraph for each footnote link destination.
+fn footnotes<S: FontStyle, F: Fonts<Style = S>, H: Hyphenator>(
+    mut builder: Builder<S, F, H>,
+    destinations: &[String],
+) -> Builder<S, F, H> {
+    builder.advance_line();
+    builder.horizontal_rule(None);
+
+    for (i, destination) in destinations.iter().enumerate() {
+        let number = alloc::format!("{}.", i + 1);
+        builder.begin_structure();
+        let mut paragraph = builder.paragraph(None);
+        paragraph.word(&number);
+        paragraph.whitespace();
+        paragraph.text(destination);
+        builder = paragraph.finish();
+        builder.end_structure(StructureKind::Paragraph);
+    }
+    builder
 }
 
 struct Handlers<S, F, H> {
@@ -174,13 +367,27 @@ impl<S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> Handlers<S, F, H> {
             // Block quote
             //
             // Add block quoute level.
-            Name::BlockQuote => {} // TODO
+            Name::BlockQuote => {
+                let doc = context.builder.doc();
+                doc.begin_structure();
+                doc.begin_blockquote(true);
+            }
 
             // Setext Heading
             //
             // Reset the heading level.
             Name::HeadingSetext => context.heading_level = 0,
 
+            // Code block
+            //
+            // Begin a new left-aligned paragraph in code style.
+            Name::CodeFenced | Name::CodeIndented => Self::on_enter_code(context),
+
+            // Code fence
+            //
+            // Ignore the fence's info string.
+            Name::CodeFencedFence => context.in_code_fence = true,
+
             // Heading text
             //
             // Begin new paragraph.
@@ -189,25 +396,63 @@ impl<S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> Handlers<S, F, H> {
             // List
             //
             // Add list level.
-            Name::ListOrdered | Name::ListUnordered => {} // TODO
+            Name::ListOrdered => Self::on_enter_list(context, ListKind::Ordered),
+            Name::ListUnordered => Self::on_enter_list(context, ListKind::Unordered),
 
             // Paragraph
             //
             // Begin a new paragraph + add an indent.
             Name::Paragraph => Self::on_enter_paragraph(context),
 
+            // Table
+            //
+            // Measure the table's columns.
+            Name::GfmTable => Self::on_enter_table(context),
+            Name::GfmTableHead => context.in_table_head = true,
+
+            // Table row
+            //
+            // Begin a row of cells.
+            Name::GfmTableRow => {
+                context.table_column = 0;
+                let doc = context.builder.doc();
+                doc.begin_structure();
+                doc.begin_row();
+            }
+
+            // Table cell
+            //
+            // Begin a new paragraph in the cell's column.
+            Name::GfmTableCell => Self::on_enter_table_cell(context),
+
             // List content
 
             // ListItem
             //
-            // Unclear. Something about bboxes. Ignore for now.
-            Name::ListItem => {} // TODO
+            // Mark the next paragraph as the start of a list item.
+            Name::ListItem => {
+                context.in_list_item = true;
+                context.builder.doc().begin_structure();
+            }
 
             // Text content
             Name::CharacterReferenceMarker => context.character_reference_marker = b'&',
             Name::CharacterReferenceMarkerHexadecimal => context.character_reference_marker = b'x',
             Name::CharacterReferenceMarkerNumeric => context.character_reference_marker = b'#',
 
+            // Inline code
+            //
+            // Line endings within the span do not break it.
+            Name::CodeText => context.in_code_text = true,
+
+            // Link
+            //
+            // Forget the previous link's label and destination.
+            Name::Link => {
+                context.link_label = None;
+                context.link_destination = None;
+            }
+
             // Emphasis
             //
             // Push bold style.
@@ -249,12 +494,26 @@ impl<S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> Handlers<S, F, H> {
             // Block quote
             //
             // Remove block quoute level.
-            Name::BlockQuote => {} // TODO
+            Name::BlockQuote => {
+                let doc = context.builder.doc();
+                doc.end_blockquote();
+                doc.end_structure(StructureKind::BlockQuote);
+            }
 
             // Code block
             //
-            // Layout code block.
-            Name::CodeFlowChunk => {} // TODO
+            // Layout current paragraph and pop code style.
+            Name::CodeFenced | Name::CodeIndented => Self::on_exit_code(context),
+
+            // Code fence
+            //
+            // Ignore the fence's info string.
+            Name::CodeFencedFence => context.in_code_fence = false,
+
+            // Code block content
+            //
+            // Itemize verbatim into current paragraph.
+            Name::CodeFlowChunk => Self::on_exit_code_flow_chunk(context),
 
             // Atx Heading Sequence
             //
@@ -269,13 +528,47 @@ impl<S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> Handlers<S, F, H> {
             // List
             //
             // Remove list level.
-            Name::ListOrdered | Name::ListUnordered => {} // TODO
+            Name::ListOrdered => Self::on_exit_list(context, ListKind::Ordered),
+            Name::ListUnordered => Self::on_exit_list(context, ListKind::Unordered),
+
+            // List item
+            //
+            // Forget an item that had no paragraph.
+            Name::ListItem => {
+                context.in_list_item = false;
+                context.builder.doc().end_structure(StructureKind::ListItem);
+            }
 
             // Paragraph
             //
             // Layout current paragraph.
             Name::Paragraph => Self::on_exit_paragraph(context),
 
+            // Table
+            Name::GfmTable => context.builder.doc().end_structure(StructureKind::Table),
+
+            // Table head
+            //
+            // Rule off the head from the body.
+            Name::GfmTableHead => {
+                context.in_table_head = false;
+                context.builder.doc().horizontal_rule(None);
+            }
+
+            // Table row
+            //
+            // Continue below the row's tallest cell.
+            Name::GfmTableRow => {
+                let doc = context.builder.doc();
+                doc.end_row();
+                doc.end_structure(StructureKind::TableRow);
+            }
+
+            // Table cell
+            //
+            // Layout current paragraph.
+            Name::GfmTableCell => Self::on_exit_table_cell(context),
+
             // Thematic break
             //
             // Draw a thematic break.
@@ -300,8 +593,9 @@ impl<S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> Handlers<S, F, H> {
 
             // Inline code
             //
-            // Itemize as a single box into current paragraph.
-            Name::CodeTextData => {} // TODO
+            // Itemize as a single box in code style into current paragraph.
+            Name::CodeText => context.in_code_text = false,
+            Name::CodeTextData => Self::on_exit_code_text_data(context),
 
             // Emphasis
             //
@@ -328,8 +622,15 @@ impl<S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> Handlers<S, F, H> {
 
             // Link
             //
-            // Unclear. Replace with label for now.
-            Name::Link => {} // TODO
+            // Follow the label with a footnote marker, if enabled.
+            Name::Link => Self::on_exit_link(context),
+
+            // Link label and destination
+            //
+            // Record for the link's footnote.
+            Name::LabelText | Name::ReferenceString | Name::ResourceDestinationString => {
+                Self::on_exit_link_part(context)
+            }
 
             // Reference, Resource
             //
@@ -367,6 +668,9 @@ impl<S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> Handlers<S, F, H> {
                 if !doc.is_empty() {
                     doc.page_break();
                 }
+                doc.begin_structure();
+                // A heading travels with at least the first line of its body.
+                doc.keep_with_next();
                 BuilderState::Paragraph(doc.paragraph(None))
             }
             _ => panic!("expected a document builder"),
@@ -382,9 +686,24 @@ impl<S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> Handlers<S, F, H> {
 
     fn on_exit_heading(context: &mut LayoutContext<S, F, H>) {
         context.in_paragraph = false;
+
+        // The level of a setext heading is given by its underline.
+        let level = if matches!(context.events[context.index].name, Name::HeadingSetext) {
+            let event_pos = SlicePosition::from_exit_event(context.events, context.index);
+            let slice = Slice::from_position(context.bytes, &event_pos);
+            if slice.as_str().trim_end().ends_with('=') {
+                1
+            } else {
+                2
+            }
+        } else {
+            context.heading_level
+        };
+
         context.builder.map(|b| match b {
             BuilderState::Paragraph(p) => {
                 let mut doc = p.finish();
+                doc.end_structure(StructureKind::Heading { level });
                 doc.advance_line();
                 doc.set_style(&context.options.regular);
                 BuilderState::Doc(doc)
@@ -394,28 +713,284 @@ impl<S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> Handlers<S, F, H> {
     }
 
     fn on_enter_paragraph(context: &mut LayoutContext<S, F, H>) {
-        // Start a new paragraph and push an indent.
+        // Start a new paragraph. The first paragraph of a list item is preceded by the item's
+        // marker; all others get an indent.
         context.in_paragraph = true;
 
+        let list_item = core::mem::replace(&mut context.in_list_item, false);
         context.builder.map(|b| match b {
-            BuilderState::Doc(doc) => BuilderState::Paragraph(doc.paragraph(None)),
+            BuilderState::Doc(mut doc) => {
+                doc.begin_structure();
+                if list_item {
+                    BuilderState::Paragraph(doc.list_item(None))
+                } else {
+                    BuilderState::Paragraph(doc.paragraph(None))
+                }
+            }
             _ => panic!("expected a document builder"),
         });
 
-        context.builder.paragraph().indent(4.0);
+        if !list_item {
+            context.builder.paragraph().indent(4.0);
+        }
     }
 
     fn on_exit_paragraph(context: &mut LayoutContext<S, F, H>) {
         context.in_paragraph = false;
 
         context.builder.map(|b| match b {
-            BuilderState::Paragraph(p) => BuilderState::Doc(p.finish()),
+            BuilderState::Paragraph(p) => {
+                let mut doc = p.finish();
+                doc.end_structure(StructureKind::Paragraph);
+                BuilderState::Doc(doc)
+            }
+            _ => panic!("expected a paragraph builder"),
+        });
+    }
+
+    fn on_enter_list(context: &mut LayoutContext<S, F, H>, kind: ListKind) {
+        let doc = context.builder.doc();
+        doc.begin_structure();
+        doc.begin_list(kind);
+    }
+
+    fn on_exit_list(context: &mut LayoutContext<S, F, H>, kind: ListKind) {
+        let doc = context.builder.doc();
+        doc.end_list();
+        doc.end_structure(StructureKind::List { kind });
+    }
+
+    fn on_enter_table(context: &mut LayoutContext<S, F, H>) {
+        // Measure the widest cell in each column. Cells are measured by their text alone.
+        let mut widths: Vec<f32> = Vec::new();
+        let mut column = 0;
+        let mut in_head = false;
+        let mut text = String::new();
+        let mut depth = 0;
+        for index in context.index..context.events.len() {
+            let event = &context.events[index];
+            match (event.kind == Kind::Enter, &event.name) {
+                (true, Name::GfmTable) => depth += 1,
+                (false, Name::GfmTable) => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                (true, Name::GfmTableHead) => in_head = true,
+                (false, Name::GfmTableHead) => in_head = false,
+                (true, Name::GfmTableRow) => column = 0,
+                (false, Name::GfmTableCell) => {
+                    let style = match (in_head, &context.options.strong) {
+                        (true, Some(strong)) => strong,
+                        _ => &context.options.regular,
+                    };
+                    let width = match context.builder.doc().font_style(style) {
+                        None => 0.0,
+                        Some(style) => style.measure_string(&text).bounding_box.size.width as f32,
+                    };
+                    if column == widths.len() {
+                        widths.push(0.0);
+                    }
+                    widths[column] = widths[column].max(width);
+                    column += 1;
+                    text.clear();
+                }
+                (false, Name::Data | Name::CharacterEscapeValue | Name::CodeTextData) => {
+                    let event_pos = SlicePosition::from_exit_event(context.events, index);
+                    text.push_str(Slice::from_position(context.bytes, &event_pos).as_str());
+                }
+                _ => {}
+            }
+        }
+
+        // Columns are separated by an em-sized gap and share the available width in proportion
+        // to their widest cells.
+        let doc = context.builder.doc();
+        let gap = doc
+            .font_style(&context.options.regular)
+            .map_or(0, |s| s.em_px()) as f32;
+        let available = doc.content_width() - gap * widths.len().saturating_sub(1) as f32;
+        let total: f32 = widths.iter().sum();
+        let count = widths.len() as f32;
+
+        context.table_columns.clear();
+        let mut left = 0.0;
+        for width in widths {
+            let width = if total > 0.0 {
+                available * width / total
+            } else {
+                available / count
+            };
+            context.table_columns.push((left, width.max(0.0)));
+            left += width + gap;
+        }
+
+        doc.begin_structure();
+    }
+
+    fn on_enter_table_cell(context: &mut LayoutContext<S, F, H>) {
+        context.in_paragraph = true;
+
+        let (left, width) = match context.table_columns.get(context.table_column) {
+            Some(column) => *column,
+            None => (0.0, context.builder.doc().content_width()),
+        };
+        context.builder.map(|b| match b {
+            BuilderState::Doc(mut doc) => {
+                doc.begin_structure();
+                BuilderState::Paragraph(doc.row_cell(left, width))
+            }
+            _ => panic!("expected a document builder"),
+        });
+
+        if context.in_table_head {
+            if let Some(ref strong) = context.options.strong {
+                context.builder.paragraph().set_style(strong);
+            }
+        }
+    }
+
+    fn on_exit_table_cell(context: &mut LayoutContext<S, F, H>) {
+        context.in_paragraph = false;
+        context.table_column += 1;
+
+        let kind = if context.in_table_head {
+            StructureKind::TableHeaderCell {
+                scope: HeaderScope::Column,
+            }
+        } else {
+            StructureKind::TableDataCell
+        };
+        context.builder.map(|b| match b {
+            BuilderState::Paragraph(p) => {
+                let mut doc = p.finish();
+                doc.end_structure(kind);
+                doc.set_style(&context.options.regular);
+                BuilderState::Doc(doc)
+            }
+            _ => panic!("expected a paragraph builder"),
+        });
+    }
+
+    fn on_enter_code(context: &mut LayoutContext<S, F, H>) {
+        context.in_code = true;
+
+        context.builder.map(|b| match b {
+            BuilderState::Doc(mut doc) => {
+                doc.begin_structure();
+                BuilderState::Paragraph(doc.paragraph(Some(ParagraphOptions {
+                    align: Align::Left,
+                    justify_last_line: false,
+                    margin_bottom_px: 0.0,
+                    margin_left_px: 0.0,
+                    margin_right_px: 0.0,
+                    margin_top_px: 0.0,
+                })))
+            }
+            _ => panic!("expected a document builder"),
+        });
+
+        if let Some(ref style) = context.options.code {
+            context.builder.paragraph().set_style(style);
+        }
+    }
+
+    fn on_exit_code(context: &mut LayoutContext<S, F, H>) {
+        context.in_code = false;
+        context.in_code_content = false;
+        context.builder.map(|b| match b {
+            BuilderState::Paragraph(p) => {
+                let mut doc = p.finish();
+                doc.end_structure(StructureKind::CodeBlock);
+                doc.set_style(&context.options.regular);
+                BuilderState::Doc(doc)
+            }
             _ => panic!("expected a paragraph builder"),
         });
     }
 
-    fn on_exit_thematic_break(_context: &mut LayoutContext<S, F, H>) {
-        // TODO
+    fn on_exit_code_flow_chunk(context: &mut LayoutContext<S, F, H>) {
+        let event_pos = SlicePosition::from_exit_event(context.events, context.index);
+        let slice = Slice::from_position(context.bytes, &event_pos);
+        context.builder.paragraph().verbatim(slice.as_str());
+        context.in_code_content = true;
+    }
+
+    fn on_exit_code_text_data(context: &mut LayoutContext<S, F, H>) {
+        let event_pos = SlicePosition::from_exit_event(context.events, context.index);
+        let slice = Slice::from_position(context.bytes, &event_pos);
+        let code = context
+            .options
+            .code
+            .as_ref()
+            .unwrap_or(&context.options.regular);
+
+        let paragraph = context.builder.paragraph();
+        paragraph.set_style(code);
+        paragraph.unbreakable(slice.as_str());
+        paragraph.set_style(&context.options.regular);
+    }
+
+    fn on_exit_link_part(context: &mut LayoutContext<S, F, H>) {
+        let event_pos = SlicePosition::from_exit_event(context.events, context.index);
+        let slice = String::from(Slice::from_position(context.bytes, &event_pos).as_str());
+        match context.events[context.index].name {
+            Name::LabelText => context.link_label = Some(slice),
+            Name::ReferenceString => {
+                context.link_destination =
+                    context.definitions.get(&normalize_label(&slice)).cloned()
+            }
+            _ => context.link_destination = Some(slice),
+        }
+    }
+
+    fn on_exit_link(context: &mut LayoutContext<S, F, H>) {
+        if !context.options.footnote_links {
+            return;
+        }
+
+        // Collapsed and shortcut references are named by their label.
+        let destination = match context.link_destination.take() {
+            Some(destination) => destination,
+            None => match context
+                .link_label
+                .take()
+                .and_then(|label| context.definitions.get(&normalize_label(&label)))
+            {
+                None => return,
+                Some(destination) => destination.clone(),
+            },
+        };
+
+        let number = match context.footnotes.iter().position(|d| *d == destination) {
+            Some(i) => i + 1,
+            None => {
+                context.footnotes.push(destination);
+                context.footnotes.len()
+            }
+        };
+
+        // The marker is raised by a third of an em.
+        let paragraph = context.builder.paragraph();
+        let rise = match paragraph.font_style(&context.options.regular) {
+            None => 0,
+            Some(style) => (style.em_px() / 3) as i16,
+        };
+        paragraph.push_raw(Command::MoveBy { dx: 0, dy: -rise });
+        for c in alloc::format!("{number}").chars() {
+            paragraph.char(c);
+        }
+        paragraph.push_raw(Command::MoveBy { dx: 0, dy: rise });
+    }
+
+    fn on_exit_thematic_break(context: &mut LayoutContext<S, F, H>) {
+        // Thematic breaks only occur in flow content, so any paragraph has already ended. The
+        // rule is set off by a blank line on either side.
+        let doc = context.builder.doc();
+        doc.advance_line();
+        doc.horizontal_rule(None);
+        doc.advance_line();
     }
 
     fn on_exit_autolink(context: &mut LayoutContext<S, F, H>) {
@@ -425,7 +1000,7 @@ impl<S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> Handlers<S, F, H> {
     }
 
     fn on_exit_data(context: &mut LayoutContext<S, F, H>) {
-        if !context.in_link_destination {
+        if !context.in_link_destination && !context.in_code_fence {
             let event_pos = SlicePosition::from_exit_event(context.events, context.index);
             let slice = Slice::from_position(context.bytes, &event_pos);
             context.builder.paragraph().text(slice.as_str());
@@ -437,6 +1012,10 @@ impl<S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> Handlers<S, F, H> {
     }
 
     fn on_exit_character_reference(context: &mut LayoutContext<S, F, H>) {
+        if context.in_code_fence {
+            return;
+        }
+
         let event_pos = SlicePosition::from_exit_event(context.events, context.index);
         let slice = Slice::from_position(context.bytes, &event_pos);
         match context.character_reference_marker {
@@ -462,7 +1041,14 @@ impl<S: FontStyle, F: Fonts<Style = S>, H: Hyphenator> Handlers<S, F, H> {
     }
 
     fn on_exit_line_ending(context: &mut LayoutContext<S, F, H>) {
-        if context.in_paragraph {
+        if context.in_code {
+            // Line endings before the first line of code belong to the opening fence.
+            if context.in_code_content {
+                context.builder.paragraph().verbatim("\n");
+            }
+        } else if context.in_code_text {
+            context.builder.paragraph().char('\u{a0}');
+        } else if context.in_paragraph {
             context.builder.paragraph().soft_line_break();
         }
     }
@@ -491,3 +1077,80 @@ fn decode_named_char(value: &str) -> Option<&'static str> {
         .find(|d| d.0 == value)
         .map(|d| d.1)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::testing::TestFonts;
+    use embedded_graphics::geometry::{Point, Size};
+    use markdown::{parser, ParseOptions};
+
+    fn regular() -> Style {
+        Style {
+            font_name: "Regular".into(),
+            em_px: 20,
+        }
+    }
+
+    /// Lays out markdown on a 400x600 page whose regular text is 20px, recording its structure.
+    fn layout(source: &str) -> (Header, Vec<Command<String>>, Vec<StructureNode>) {
+        layout_with(source, Options::new(regular()))
+    }
+
+    fn layout_with(
+        source: &str,
+        options: Options,
+    ) -> (Header, Vec<Command<String>>, Vec<StructureNode>) {
+        let (events, state) = parser::parse(source, &ParseOptions::default()).unwrap();
+        let bounds = Rectangle::new(Point::zero(), Size::new(400, 600));
+        build_with_structure(&events, state.bytes, bounds, TestFonts, (), options).unwrap()
+    }
+
+    #[test]
+    fn structure_follows_blocks() {
+        let (_, commands, structure) = layout("# Title\n\nFirst.\n\nSecond.\n");
+        let kinds: Vec<StructureKind> = structure.iter().map(|node| node.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                StructureKind::Heading { level: 1 },
+                StructureKind::Paragraph,
+                StructureKind::Paragraph,
+            ]
+        );
+
+        // The nodes cover successive, non-empty runs of commands.
+        for pair in structure.windows(2) {
+            assert!(
+                pair[0].commands.end <= pair[1].commands.start,
+                "{structure:?}"
+            );
+        }
+        for node in &structure {
+            assert!(!node.commands.is_empty(), "{node:?}");
+            assert!(node.commands.end <= commands.len());
+        }
+    }
+
+    #[test]
+    fn code_spans_switch_styles() {
+        let code = Style {
+            font_name: "Code".into(),
+            em_px: 20,
+        };
+        let options = Options::new(regular()).with_code(Some(code.clone()));
+        let (header, commands, _) = layout_with("a `b c` d\n", options);
+        assert_eq!(header.styles, [regular(), code]);
+
+        // The span is shown in the code style, and the regular style is restored after it.
+        let runs: Vec<String> = commands
+            .iter()
+            .filter_map(|c| match c {
+                Command::SetStyle { s } => Some(alloc::format!("SetStyle {s}")),
+                Command::Show { str } => Some(String::from(str.trim())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(runs, ["a", "SetStyle 1", "b c", "SetStyle 0", "d"]);
+    }
+}
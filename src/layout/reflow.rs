@@ -0,0 +1,210 @@
+use crate::{
+    layout::{Builder, FontStyle, Fonts, Hyphenator, WhitespaceMetrics},
+    read, Command, Document, Header,
+};
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use embedded_graphics::primitives::Rectangle;
+
+enum Run {
+    SetStyle(u16),
+    Indent(u16),
+    Text(String),
+}
+
+/// Accumulates the paragraphs of a document from its command stream.
+struct Paragraphs {
+    paragraphs: Vec<Vec<Run>>,
+    runs: Vec<Run>,
+}
+
+impl Paragraphs {
+    fn flush(&mut self) {
+        if self.runs.iter().any(|r| matches!(r, Run::Text(_))) {
+            self.paragraphs.push(core::mem::take(&mut self.runs));
+        } else {
+            self.runs.clear();
+        }
+    }
+
+    fn push_text(&mut self, text: &str) {
+        match self.runs.last_mut() {
+            Some(Run::Text(t)) => t.push_str(text),
+            _ => self.runs.push(Run::Text(text.into())),
+        }
+    }
+
+    /// Joins the current line to the next line of the same paragraph.
+    fn join_line(&mut self) {
+        match self.runs.last_mut() {
+            // Assume that a trailing hyphen was inserted at a soft hyphenation point.
+            Some(Run::Text(t)) if t.len() > 1 && t.ends_with('-') => {
+                t.pop();
+            }
+            _ => self.push_text(" "),
+        }
+    }
+}
+
+impl Document {
+    /// Re-paginates the document for a new bounding box.
+    ///
+    /// Since the document does not retain its source, this is a best-effort operation:
+    /// paragraphs are reconstructed from the command stream by treating leading indents, explicit
+    /// cursor movement, blank lines, and unjustified lines as paragraph boundaries, and
+    /// line-ending hyphens are assumed to be soft hyphens. Only text and styles are preserved.
+    pub fn reflow<S: FontStyle, F: Fonts<Style = S>, H: Hyphenator>(
+        &self,
+        bounding_box: Rectangle,
+        fonts: F,
+        hyphenator: H,
+    ) -> Result<(Header, Vec<Command<String>>), read::Error> {
+        let mut paragraphs = Paragraphs {
+            paragraphs: Vec::new(),
+            runs: Vec::new(),
+        };
+
+        let mut line_empty = true;
+        let mut justified = false;
+        for offset in &self.trailer.pages {
+            let source = self
+                .bytes
+                .get(*offset as usize..)
+                .ok_or(read::Error::InvalidEncoding)?;
+            for command in read::page(&self.header, source)? {
+                match command {
+                    Command::SetAdjustmentRatio { r } => justified = r != 0.0,
+                    Command::Advance { dx } if line_empty => {
+                        paragraphs.flush();
+                        paragraphs.runs.push(Run::Indent(dx));
+                    }
+                    Command::SetCursor { .. } => paragraphs.flush(),
+                    Command::SetStyle { s } => paragraphs.runs.push(Run::SetStyle(s)),
+                    Command::Show { str } => {
+                        paragraphs.push_text(str);
+                        line_empty = false;
+                    }
+                    Command::ShowPositioned { glyphs } => {
+                        let text: String = glyphs.iter().map(|(c, _)| c).collect();
+                        paragraphs.push_text(&text);
+                        line_empty = false;
+                    }
+                    Command::LineBreak | Command::PageBreak => {
+                        if line_empty || !justified {
+                            paragraphs.flush();
+                        } else {
+                            paragraphs.join_line();
+                        }
+                        line_empty = true;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        paragraphs.flush();
+
+        let default_style = self
+            .header
+            .styles
+            .first()
+            .and_then(|s| fonts.get_style(s))
+            .ok_or(read::Error::InvalidStyleIndex)?;
+
+        let mut builder = Builder::new(
+            bounding_box,
+            fonts,
+            default_style,
+            hyphenator,
+            WhitespaceMetrics::default(),
+        );
+        for runs in &paragraphs.paragraphs {
+            let mut p = builder.paragraph(None);
+            for run in runs {
+                match run {
+                    Run::SetStyle(s) => {
+                        if let Some(style) = self.header.styles.get(*s as usize) {
+                            p.set_style(style);
+                        }
+                    }
+                    Run::Indent(dx) => p.indent_px(*dx as f32),
+                    Run::Text(text) => p.text(text),
+                }
+            }
+            builder = p.finish();
+        }
+
+        let (styles, commands) = builder.finish();
+        let header = Header {
+            styles,
+            ..self.header.clone()
+        };
+        Ok((header, commands))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        layout::testing::{TestFonts, TestStyle},
+        write, ImageTable, Metadata, StringPool, VERSION,
+    };
+    use embedded_graphics::geometry::{Point, Size};
+    use no_std_io::io;
+
+    /// Returns the number of lines of text on each page.
+    fn lines_per_page(commands: &[Command<String>]) -> Vec<usize> {
+        commands
+            .split(|c| matches!(c, Command::PageBreak))
+            .map(|page| {
+                page.split(|c| matches!(c, Command::LineBreak))
+                    .filter(|line| line.iter().any(|c| matches!(c, Command::Show { .. })))
+                    .count()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn narrower_pages_take_more_lines() {
+        let bounds = |width| Rectangle::new(Point::zero(), Size::new(width, 200));
+
+        // Lay out and encode a document of a few paragraphs.
+        let mut builder = Builder::new(
+            bounds(400),
+            TestFonts,
+            TestStyle::new("Test", 20),
+            (),
+            WhitespaceMetrics::default(),
+        );
+        for _ in 0..3 {
+            let mut p = builder.paragraph(None);
+            p.text("the quick brown fox jumps over the lazy dog and keeps on running");
+            builder = p.finish();
+        }
+        let (styles, commands) = builder.finish();
+        let header = Header {
+            version: VERSION,
+            title: "Test".into(),
+            styles,
+            images: ImageTable::new(),
+            language: None,
+            revision: None,
+            fonts: Vec::new(),
+            metadata: Metadata::default(),
+            page: None,
+        };
+        let mut w = write::VecWriter::new();
+        write::doc(&mut w, VERSION, &header, &StringPool::new(), &commands).unwrap();
+        let docs = read::documents(&mut io::Cursor::new(w.into_inner())).unwrap();
+
+        let (_, wide) = docs[0].reflow(bounds(400), TestFonts, ()).unwrap();
+        let (_, narrow) = docs[0].reflow(bounds(200), TestFonts, ()).unwrap();
+        let (wide, narrow) = (lines_per_page(&wide), lines_per_page(&narrow));
+
+        // Reflowing at the original width keeps the original lines.
+        assert_eq!(wide, lines_per_page(&commands));
+        assert!(narrow.iter().sum::<usize>() > wide.iter().sum::<usize>());
+        assert!(narrow.len() > wide.len(), "{wide:?} {narrow:?}");
+    }
+}
@@ -0,0 +1,71 @@
+//! Fonts for testing layout.
+
+use crate::{
+    layout::{FontStyle, Fonts, TextMetrics},
+    Style,
+};
+use alloc::string::String;
+use embedded_graphics::{
+    geometry::{Point, Size},
+    primitives::Rectangle,
+};
+
+/// A font whose glyphs are all half an em wide and whose x-height is two fifths of an em.
+#[derive(Clone)]
+pub struct TestStyle {
+    pub font_name: String,
+    pub em_px: u16,
+}
+
+impl TestStyle {
+    pub fn new(font_name: &str, em_px: u16) -> Self {
+        TestStyle {
+            font_name: String::from(font_name),
+            em_px,
+        }
+    }
+}
+
+impl crate::fonts::FontStyle for TestStyle {
+    fn font_name(&self) -> &str {
+        &self.font_name
+    }
+
+    fn em_px(&self) -> u16 {
+        self.em_px
+    }
+
+    fn line_height(&self) -> u16 {
+        self.em_px * 5 / 4
+    }
+
+    fn baseline(&self) -> u16 {
+        self.em_px / 4
+    }
+
+    fn x_height(&self) -> Option<u16> {
+        Some(self.em_px * 2 / 5)
+    }
+}
+
+impl FontStyle for TestStyle {
+    fn measure_string(&self, text: &str) -> TextMetrics {
+        let width = text.chars().count() as u32 * self.em_px as u32 / 2;
+        let height = self.em_px as u32 * 5 / 4;
+        TextMetrics {
+            bounding_box: Rectangle::new(Point::zero(), Size::new(width, height)),
+        }
+    }
+}
+
+/// A font store with a [`TestStyle`] for every style.
+#[derive(Clone)]
+pub struct TestFonts;
+
+impl Fonts for TestFonts {
+    type Style = TestStyle;
+
+    fn get_style(&self, style: &Style) -> Option<TestStyle> {
+        Some(TestStyle::new(&style.font_name, style.em_px))
+    }
+}
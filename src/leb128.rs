@@ -1,4 +1,5 @@
 pub(crate) const CONTINUATION_BIT: u8 = 1 << 7;
+pub(crate) const SIGN_BIT: u8 = 1 << 6;
 
 #[inline]
 pub(crate) fn low_bits_of_byte(byte: u8) -> u8 {
@@ -13,7 +14,7 @@ pub(crate) fn low_bits_of_u64(val: u64) -> u8 {
 
 /// A module for reading LEB128-encoded signed and unsigned integers.
 pub mod read {
-    use super::{low_bits_of_byte, CONTINUATION_BIT};
+    use super::{low_bits_of_byte, CONTINUATION_BIT, SIGN_BIT};
     use core::fmt;
     use no_std_io::io;
 
@@ -58,10 +59,9 @@ pub mod read {
             let mut buf = [0];
             r.read_exact(&mut buf)?;
 
+            // The tenth byte holds the last bit of a u64 and must end the encoding. Stop reading
+            // here rather than draining further continuation bytes, which may be unbounded.
             if shift == 63 && buf[0] != 0x00 && buf[0] != 0x01 {
-                while buf[0] & CONTINUATION_BIT != 0 {
-                    r.read_exact(&mut buf)?;
-                }
                 return Err(Error::Overflow);
             }
 
@@ -75,6 +75,45 @@ pub mod read {
             shift += 7;
         }
     }
+
+    /// Decode a signed LEB128-encoded number from the `no_std_io::io::Read` stream `r`.
+    ///
+    /// On success, return the number.
+    pub fn signed<R>(r: &mut R) -> Result<i64, Error>
+    where
+        R: ?Sized + io::Read,
+    {
+        let mut result = 0;
+        let mut shift = 0;
+        let size = 64;
+        let mut byte;
+
+        loop {
+            let mut buf = [0];
+            r.read_exact(&mut buf)?;
+
+            byte = buf[0];
+            // As for `unsigned`, the tenth byte must end the encoding.
+            if shift == 63 && byte != 0x00 && byte != 0x7f {
+                return Err(Error::Overflow);
+            }
+
+            let low_bits = low_bits_of_byte(byte) as i64;
+            result |= low_bits << shift;
+            shift += 7;
+
+            if byte & CONTINUATION_BIT == 0 {
+                break;
+            }
+        }
+
+        if shift < size && (SIGN_BIT & byte) == SIGN_BIT {
+            // Sign extend the result.
+            result |= !0 << shift;
+        }
+
+        Ok(result)
+    }
 }
 
 /// A module for writing LEB128-encoded signed and unsigned integers.
@@ -82,6 +121,12 @@ pub mod write {
     use super::{low_bits_of_u64, CONTINUATION_BIT};
     use no_std_io::io;
 
+    /// Return the number of bytes that `unsigned` writes for `val`.
+    pub fn size(val: u64) -> usize {
+        let bits = (64 - val.leading_zeros()).max(1) as usize;
+        bits.div_ceil(7)
+    }
+
     /// Write `val` to the `no_std_io::io::Write` stream `w` as an unsigned LEB128 value.
     ///
     /// On success, return the number of bytes written to `w`.
@@ -107,4 +152,119 @@ pub mod write {
             }
         }
     }
+
+    /// Write `val` to the `no_std_io::io::Write` stream `w` as a signed LEB128 value.
+    ///
+    /// On success, return the number of bytes written to `w`.
+    pub fn signed<W>(w: &mut W, mut val: i64) -> Result<usize, io::Error>
+    where
+        W: ?Sized + io::Write,
+    {
+        let mut bytes_written = 0;
+        loop {
+            let mut byte = val as u8;
+            // Keep the sign bit for testing
+            val >>= 6;
+            let done = val == 0 || val == -1;
+            if done {
+                byte &= !CONTINUATION_BIT;
+            } else {
+                // Remove the sign bit
+                val >>= 1;
+                // More bytes to come, so set the continuation bit.
+                byte |= CONTINUATION_BIT;
+            }
+
+            let buf = [byte];
+            w.write_all(&buf)?;
+            bytes_written += 1;
+
+            if done {
+                return Ok(bytes_written);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read, write};
+    use crate::write::VecWriter;
+    use no_std_io::io;
+
+    // Values are written to a `VecWriter` and read from a `Cursor` so that the tests also run
+    // without `std`.
+    fn encode(f: impl FnOnce(&mut VecWriter) -> Result<usize, io::Error>) -> VecWriter {
+        let mut w = VecWriter::new();
+        let written = f(&mut w).unwrap();
+        assert_eq!(written, w.as_slice().len());
+        w
+    }
+
+    fn signed_round_trip(val: i64) -> usize {
+        let w = encode(|w| write::signed(w, val));
+        let mut r = io::Cursor::new(w.as_slice());
+        assert_eq!(read::signed(&mut r).unwrap(), val);
+        assert_eq!(r.position() as usize, w.as_slice().len());
+        w.as_slice().len()
+    }
+
+    #[test]
+    fn signed_round_trips() {
+        for val in [i64::MIN, -1, 0, 1, i64::MAX] {
+            signed_round_trip(val);
+        }
+        assert_eq!(signed_round_trip(i64::MIN), 10);
+        assert_eq!(signed_round_trip(i64::MAX), 10);
+    }
+
+    #[test]
+    fn signed_one_byte_boundaries() {
+        assert_eq!(signed_round_trip(-64), 1);
+        assert_eq!(signed_round_trip(63), 1);
+        assert_eq!(signed_round_trip(-65), 2);
+        assert_eq!(signed_round_trip(64), 2);
+    }
+
+    #[test]
+    fn overlong_input_errors_after_ten_bytes() {
+        let input = [0x80; 16];
+        let mut r = io::Cursor::new(&input[..]);
+        assert!(matches!(read::unsigned(&mut r), Err(read::Error::Overflow)));
+        assert_eq!(r.position(), 10);
+
+        let mut r = io::Cursor::new(&input[..]);
+        assert!(matches!(read::signed(&mut r), Err(read::Error::Overflow)));
+        assert_eq!(r.position(), 10);
+    }
+
+    fn encoded_len(val: u64) -> usize {
+        encode(|w| write::unsigned(w, val)).as_slice().len()
+    }
+
+    #[test]
+    fn size_matches_unsigned() {
+        assert_eq!(write::size(0), 1);
+        assert_eq!(encoded_len(0), 1);
+        assert_eq!(write::size(u64::MAX), 10);
+        assert_eq!(encoded_len(u64::MAX), 10);
+
+        // Values on either side of each power of two cover every encoded length.
+        for shift in 0..64 {
+            let val = 1u64 << shift;
+            for val in [val - 1, val, val + 1] {
+                assert_eq!(write::size(val), encoded_len(val), "{val}");
+            }
+        }
+
+        // A xorshift generator gives a repeatable spread of other values.
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        for _ in 0..10_000 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let val = state >> (state % 64);
+            assert_eq!(write::size(val), encoded_len(val), "{val}");
+        }
+    }
 }
@@ -9,6 +9,9 @@ pub mod fonts;
 #[cfg(feature = "font_db")]
 pub mod font_db;
 
+#[cfg(feature = "mono_font")]
+pub mod mono_font;
+
 #[cfg(feature = "layout")]
 pub mod layout;
 
@@ -23,48 +26,334 @@ pub struct Style {
     pub em_px: u16,
 }
 
+/// The oldest version of the format that can be read.
+pub const MIN_VERSION: u8 = 1;
+
+/// The newest version of the format, which is written by default.
+///
+/// Version 2 adds the header's image table and language tag, the trailer's URL pool and anchor
+/// vector, and the `SetColor`, `SetDecoration`, `DrawImage`, `Link`, `Anchor`, `MoveBy`,
+/// `FillRect`, and `ShowPositioned` commands.
+///
+/// Version 3 adds the header's revision.
+///
+/// Version 4 adds the header's embedded fonts.
+///
+/// Version 5 adds the header's metadata.
+///
+/// Version 6 adds the header's page geometry.
+pub const VERSION: u8 = 6;
+
+#[derive(Clone)]
 pub struct Header {
+    /// The version of the format. Ignored when writing; see `write::doc`.
+    pub version: u8,
     pub title: String,
     pub styles: Vec<Style>,
+    pub images: ImageTable,
+    /// The BCP-47 tag of the document's language, if known.
+    pub language: Option<String>,
+    /// An identifier for the revision of the document's source, such as a modification time or
+    /// content hash, if known. Readers can use it as a cache key for rendered pages.
+    pub revision: Option<u64>,
+    /// Fonts embedded in the document, so that it can be displayed without access to the fonts
+    /// its styles name.
+    pub fonts: Vec<EmbeddedFont>,
+    /// Descriptive metadata, such as the document's author.
+    pub metadata: Metadata,
+    /// The geometry of the pages the document was laid out for, if known. Pages are positioned
+    /// in absolute coordinates, so they should be displayed using the same geometry.
+    pub page: Option<PageGeometry>,
+}
+
+/// The size and margins of a page, in pixels. Page content is laid out within the margins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PageGeometry {
+    pub width: u16,
+    pub height: u16,
+    pub top_margin: u16,
+    pub right_margin: u16,
+    pub bottom_margin: u16,
+    pub left_margin: u16,
+}
+
+impl PageGeometry {
+    /// The bounds of the whole page.
+    pub fn bounds(&self) -> embedded_graphics::primitives::Rectangle {
+        use embedded_graphics::{
+            geometry::{Point, Size},
+            primitives::Rectangle,
+        };
+
+        Rectangle::new(
+            Point::new(0, 0),
+            Size::new(self.width as u32, self.height as u32),
+        )
+    }
+
+    /// The bounds of the page's content, inside its margins.
+    pub fn content_box(&self) -> embedded_graphics::primitives::Rectangle {
+        use embedded_graphics::{
+            geometry::{Point, Size},
+            primitives::Rectangle,
+        };
+
+        let width =
+            (self.width as u32).saturating_sub(self.left_margin as u32 + self.right_margin as u32);
+        let height =
+            (self.height as u32).saturating_sub(self.top_margin as u32 + self.bottom_margin as u32);
+        Rectangle::new(
+            Point::new(self.left_margin as i32, self.top_margin as i32),
+            Size::new(width, height),
+        )
+    }
+}
+
+/// Descriptive metadata about a document, for display in a reader's library.
+///
+/// Metadata is encoded as a list of key/value entries. Entries with keys that are not known to
+/// this version of the format are kept in `other`, so that they survive being read and written
+/// again.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metadata {
+    pub author: Option<String>,
+    /// The date of publication, e.g. as an ISO 8601 date, if known.
+    pub date: Option<String>,
+    /// Entries with unknown keys, as `(key, value)` pairs.
+    pub other: Vec<(String, Vec<u8>)>,
+}
+
+impl Metadata {
+    pub fn is_empty(&self) -> bool {
+        self.author.is_none() && self.date.is_none() && self.other.is_empty()
+    }
+}
+
+/// A font embedded in a document, referenced by the `font_name` of its styles.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmbeddedFont {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    /// Raw 8-bit grayscale pixels in row-major order.
+    Gray8,
+    /// A PNG-encoded image.
+    Png,
+}
+
+impl ImageFormat {
+    fn from_u8(b: u8) -> Option<Self> {
+        match b {
+            0 => Some(ImageFormat::Gray8),
+            1 => Some(ImageFormat::Png),
+            _ => None,
+        }
+    }
+
+    fn to_u8(self) -> u8 {
+        match self {
+            ImageFormat::Gray8 => 0,
+            ImageFormat::Png => 1,
+        }
+    }
+}
+
+/// An image stored in an image table.
+pub struct Image<'a> {
+    pub format: ImageFormat,
+    pub data: &'a [u8],
+}
+
+/// A table of images referenced by `Command::DrawImage`.
+///
+/// Each entry is encoded as a format byte followed by the LEB128-encoded length of the image data
+/// and the data itself. Entries are identified by their byte offset within the table.
+#[derive(Debug, Clone, Default)]
+pub struct ImageTable {
+    data: Vec<u8>,
+}
+
+impl ImageTable {
+    pub fn new() -> Self {
+        ImageTable { data: Vec::new() }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Adds an image to the table and returns its offset.
+    pub fn push(&mut self, format: ImageFormat, data: &[u8]) -> u32 {
+        let offset = self.data.len() as u32;
+        self.data
+            .reserve(1 + leb128::write::size(data.len() as u64) + data.len());
+        self.data.push(format.to_u8());
+        let mut len = [0; 10];
+        let n = leb128::write::unsigned(&mut &mut len[..], data.len() as u64).unwrap_or(0);
+        self.data.extend_from_slice(&len[..n]);
+        self.data.extend_from_slice(data);
+        offset
+    }
+
+    /// Returns the image at the given offset, if any.
+    pub fn get(&self, offset: u32) -> Option<Image<'_>> {
+        let source = self.data.get(offset as usize..)?;
+        let format = ImageFormat::from_u8(*source.first()?)?;
+        let mut r = no_std_io::io::Cursor::new(&source[1..]);
+        let len = leb128::read::unsigned(&mut r).ok()? as usize;
+        let start = 1 + r.position() as usize;
+        let data = source.get(start..start.checked_add(len)?)?;
+        Some(Image { format, data })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
 }
 
 pub struct Trailer {
     pub pages: Vec<u32>,
+    pub urls: StringPool,
+    /// Anchors as `(id, page_index, x, y)` tuples.
+    pub anchors: Vec<(u16, u32, u16, u16)>,
+}
+
+/// A pool of strings referenced by offset, e.g. the link targets referenced by `Command::Link`.
+///
+/// Each entry is encoded as the LEB128-encoded length of the string followed by its UTF-8 bytes.
+#[derive(Debug, Clone, Default)]
+pub struct StringPool {
+    data: Vec<u8>,
+}
+
+impl StringPool {
+    pub fn new() -> Self {
+        StringPool { data: Vec::new() }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Adds a string to the pool and returns its offset.
+    pub fn push(&mut self, s: &str) -> u32 {
+        let offset = self.data.len() as u32;
+        self.data
+            .reserve(leb128::write::size(s.len() as u64) + s.len());
+        let mut len = [0; 10];
+        let n = leb128::write::unsigned(&mut &mut len[..], s.len() as u64).unwrap_or(0);
+        self.data.extend_from_slice(&len[..n]);
+        self.data.extend_from_slice(s.as_bytes());
+        offset
+    }
+
+    /// Returns the string at the given offset, if any.
+    pub fn get(&self, offset: u32) -> Option<&str> {
+        let source = self.data.get(offset as usize..)?;
+        let mut r = no_std_io::io::Cursor::new(source);
+        let len = leb128::read::unsigned(&mut r).ok()? as usize;
+        let start = r.position() as usize;
+        let bytes = source.get(start..start.checked_add(len)?)?;
+        core::str::from_utf8(bytes).ok()
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// A complete document read from a stream that may contain several documents.
+pub struct Document {
+    pub header: Header,
+    pub trailer: Trailer,
+    /// The document's bytes. Page offsets in the trailer are relative to the start of this buffer.
+    pub bytes: Vec<u8>,
 }
 
 #[derive(Debug, Clone)]
 pub enum Command<S: Clone> {
     /// No-op.
     Nop,
-    /// Move the cursor `tab_stop` points in the current text direction.
+    /// Move the cursor to the next tab stop in the current text direction. Tab stops are every
+    /// `tab_stop` points from the page's origin, as given by the current style.
     HTab,
     /// Advance the cursor `line_height` points perpendicular to the current text direction.
     LineBreak,
-    /// Move the cursor `tab_stop` points in perpendicular to the current text direction.
+    /// Move the cursor to the next tab stop perpendicular to the current text direction.
     VTab,
     /// End the current page.
     PageBreak,
     /// Draws a UTF-8-encoded string at the current cursor, then advances the cursor by the width
     /// of the string.
     Show { str: S },
+    /// Draws each glyph at the current cursor, then advances the cursor by the glyph's x-advance.
+    /// Unlike `Show`, glyph advances are not computed from font metrics.
+    ShowPositioned { glyphs: Vec<(char, i16)> },
     /// Advances the cursor by dx points.
     Advance { dx: u16 },
     /// Moves the cursor to the given position.
     SetCursor { x: u16, y: u16 },
+    /// Moves the cursor by the given offset.
+    MoveBy { dx: i16, dy: i16 },
+    /// Fills a `w` by `h` rectangle whose top-left corner is at the current cursor using the
+    /// current color. Does not move the cursor.
+    FillRect { w: u16, h: u16 },
     /// Sets the current style to that indicated by the given index.
     SetStyle { s: u16 },
-    /// Sets the current whitespace adjustment ratio to the given amount
+    /// Sets the current whitespace adjustment ratio to the given amount. Spaces are a third of the
+    /// current style's em wide, and a ratio of 1 or -1 stretches them by half or shrinks them by a
+    /// third of that width. Non-breaking spaces are not adjusted.
     SetAdjustmentRatio { r: f32 },
     /// Sets the current line metrics.
     SetLineMetrics { height: u16, baseline: u16 },
+    /// Sets the current text color.
+    SetColor { r: u8, g: u8, b: u8 },
+    /// Sets the current text decorations.
+    SetDecoration {
+        underline: bool,
+        strikethrough: bool,
+    },
+    /// Draws the image at `data_offset` in the header's image table at the current cursor, scaled
+    /// to the given size, then advances the cursor by `width`.
+    DrawImage {
+        width: u16,
+        height: u16,
+        data_offset: u32,
+    },
+    /// Marks the next `len` bytes of shown text as a link to the URL at `target_offset` in the
+    /// trailer's URL pool.
+    Link { target_offset: u32, len: u16 },
+    /// Marks the current cursor position as the location of the anchor with the given ID.
+    Anchor { id: u16 },
     /// Ends the command stream.
     End,
 }
 
+impl<S: Clone> Command<S> {
+    /// Returns the oldest version of the format that can encode this command.
+    pub fn min_version(&self) -> u8 {
+        match self {
+            Command::SetColor { .. }
+            | Command::SetDecoration { .. }
+            | Command::DrawImage { .. }
+            | Command::Link { .. }
+            | Command::Anchor { .. }
+            | Command::MoveBy { .. }
+            | Command::FillRect { .. }
+            | Command::ShowPositioned { .. } => 2,
+            _ => 1,
+        }
+    }
+}
+
 pub mod read {
     extern crate alloc;
     use alloc::{
         string::{self, String},
+        vec,
         vec::Vec,
     };
 
@@ -82,6 +371,11 @@ pub mod read {
         InvalidEncoding,
         InvalidCommand,
         InvalidStyleIndex,
+        InvalidImageOffset,
+        UnsupportedVersion(u8),
+        InvalidOffset,
+        /// A page does not end with a `PageBreak` or `End` command.
+        UnterminatedPage,
     }
 
     impl From<io::Error> for Error {
@@ -125,6 +419,12 @@ pub mod read {
                 Error::InvalidEncoding => write!(f, "invalid encoding"),
                 Error::InvalidCommand => write!(f, "invalid command"),
                 Error::InvalidStyleIndex => write!(f, "invalid style index"),
+                Error::InvalidImageOffset => write!(f, "invalid image offset"),
+                Error::UnsupportedVersion(v) => write!(f, "unsupported version {}", v),
+                Error::InvalidOffset => write!(f, "invalid page offset"),
+                Error::UnterminatedPage => {
+                    write!(f, "page does not end with a page break or end command")
+                }
             }
         }
     }
@@ -141,6 +441,21 @@ pub mod read {
         Ok(String::from_utf8(bytes)?)
     }
 
+    /// Reads `len` bytes. The buffer grows as the bytes are read, so a corrupt length fails at the
+    /// end of the input rather than allocating memory for bytes that are not there.
+    fn read_bytes<R: io::Read>(r: &mut R, len: usize) -> Result<Vec<u8>, Error> {
+        const CHUNK_LEN: usize = 64 * 1024;
+
+        let mut bytes = vec![0; len.min(CHUNK_LEN)];
+        r.read_exact(bytes.as_mut_slice())?;
+        while bytes.len() < len {
+            let start = bytes.len();
+            bytes.resize(len.min(start + CHUNK_LEN), 0);
+            r.read_exact(&mut bytes[start..])?;
+        }
+        Ok(bytes)
+    }
+
     fn read_style<R: io::Read>(r: &mut R) -> Result<Style, Error> {
         let font_name = read_string(r)?;
         let em_px: u16 = leb128::read::unsigned(r)?.try_into()?;
@@ -151,10 +466,16 @@ pub mod read {
         // check magic number
         let mut buf = [0; 4];
         r.read_exact(&mut buf)?;
-        if buf != [0x0e, 0xdf, 0x01, 0x00] {
+        if buf[0] != 0x0e || buf[1] != 0xdf || buf[3] != 0x00 {
             return Err(Error::InvalidMagicNumber);
         }
 
+        // check version
+        let version = buf[2];
+        if !(MIN_VERSION..=VERSION).contains(&version) {
+            return Err(Error::UnsupportedVersion(version));
+        }
+
         // read title
         let title = read_string(r)?;
 
@@ -166,7 +487,103 @@ pub mod read {
             styles.push(read_style(r)?);
         }
 
-        Ok(Header { title, styles })
+        // read image table and language tag
+        let mut images = ImageTable::new();
+        let mut language = None;
+        if version >= 2 {
+            let len: u32 = leb128::read::unsigned(r)?.try_into()?;
+            images.data.reserve_exact(len as usize);
+            images.data.resize(len as usize, 0);
+            r.read_exact(images.data.as_mut_slice())?;
+
+            let tag = read_string(r)?;
+            if !tag.is_empty() {
+                language = Some(tag);
+            }
+        }
+
+        // read revision
+        let mut revision = None;
+        if version >= 3 {
+            let mut buf = [0; 1];
+            r.read_exact(&mut buf)?;
+            match buf[0] {
+                0 => {}
+                1 => {
+                    let mut buf = [0; 8];
+                    r.read_exact(&mut buf)?;
+                    revision = Some(u64::from_le_bytes(buf));
+                }
+                _ => return Err(Error::InvalidEncoding),
+            }
+        }
+
+        // read embedded fonts
+        let mut fonts = Vec::new();
+        if version >= 4 {
+            let len: u32 = leb128::read::unsigned(r)?.try_into()?;
+            for _ in 0..len {
+                let name = read_string(r)?;
+                let len: u32 = leb128::read::unsigned(r)?.try_into()?;
+                let data = read_bytes(r, len as usize)?;
+                fonts.push(EmbeddedFont { name, data });
+            }
+        }
+
+        // read metadata
+        let mut metadata = Metadata::default();
+        if version >= 5 {
+            let len: u32 = leb128::read::unsigned(r)?.try_into()?;
+            for _ in 0..len {
+                let key = read_string(r)?;
+                let len: u32 = leb128::read::unsigned(r)?.try_into()?;
+                let value = read_bytes(r, len as usize)?;
+                match key.as_str() {
+                    "author" => metadata.author = Some(String::from_utf8(value)?),
+                    "date" => metadata.date = Some(String::from_utf8(value)?),
+                    _ => metadata.other.push((key, value)),
+                }
+            }
+        }
+
+        // read page geometry
+        let mut page = None;
+        if version >= 6 {
+            let mut buf = [0; 1];
+            r.read_exact(&mut buf)?;
+            match buf[0] {
+                0 => {}
+                1 => {
+                    let mut values = [0u16; 6];
+                    for value in &mut values {
+                        *value = leb128::read::unsigned(r)?.try_into()?;
+                    }
+                    let [width, height, top_margin, right_margin, bottom_margin, left_margin] =
+                        values;
+                    page = Some(PageGeometry {
+                        width,
+                        height,
+                        top_margin,
+                        right_margin,
+                        bottom_margin,
+                        left_margin,
+                    });
+                }
+                _ => return Err(Error::InvalidEncoding),
+            }
+        }
+
+        Ok(Header {
+            version,
+            title,
+            styles,
+            images,
+            language,
+            revision,
+            fonts,
+            metadata,
+            page,
+        })
     }
 
     pub fn seek_trailer<R: io::Read + io::Seek>(r: &mut R) -> Result<u64, Error> {
@@ -181,7 +598,7 @@ pub mod read {
         }
     }
 
-    pub fn trailer<R: io::Read>(r: &mut R) -> Result<Trailer, Error> {
+    pub fn trailer<R: io::Read>(header: &Header, r: &mut R) -> Result<Trailer, Error> {
         // read page vector
         let len: u32 = leb128::read::unsigned(r)?.try_into()?;
         let mut pages = Vec::new();
@@ -191,16 +608,128 @@ pub mod read {
             pages.push(offset);
         }
 
-        Ok(Trailer { pages })
+        let mut urls = StringPool::new();
+        let mut anchors = Vec::new();
+        if header.version >= 2 {
+            // read URL pool
+            let len: u32 = leb128::read::unsigned(r)?.try_into()?;
+            urls.data.reserve_exact(len as usize);
+            urls.data.resize(len as usize, 0);
+            r.read_exact(urls.data.as_mut_slice())?;
+
+            // read anchor vector
+            let len: u32 = leb128::read::unsigned(r)?.try_into()?;
+            anchors.reserve_exact(len as usize);
+            for _ in 0..len {
+                let id: u16 = leb128::read::unsigned(r)?.try_into()?;
+                let page: u32 = leb128::read::unsigned(r)?.try_into()?;
+                let x: u16 = leb128::read::unsigned(r)?.try_into()?;
+                let y: u16 = leb128::read::unsigned(r)?.try_into()?;
+                anchors.push((id, page, x, y));
+            }
+        }
+
+        Ok(Trailer {
+            pages,
+            urls,
+            anchors,
+        })
+    }
+
+    /// Checks that the trailer's page offsets are strictly increasing and lie within a document
+    /// of `total_len` bytes, so that they can be used to slice the document.
+    pub fn validate(_header: &Header, trailer: &Trailer, total_len: usize) -> Result<(), Error> {
+        let mut prev = None;
+        for &offset in &trailer.pages {
+            if offset as usize > total_len || prev.is_some_and(|prev| offset <= prev) {
+                return Err(Error::InvalidOffset);
+            }
+            prev = Some(offset);
+        }
+        Ok(())
+    }
+
+    /// A problem with a page that does not prevent it from being displayed.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Warning {
+        /// The page shows text before setting its style and line metrics, so the text is drawn
+        /// in whatever style the reader starts the page with.
+        ShowBeforeStyle,
+    }
+
+    impl fmt::Display for Warning {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Warning::ShowBeforeStyle => {
+                    write!(f, "text is shown before the style and line metrics are set")
+                }
+            }
+        }
+    }
+
+    /// Checks each page of a validated document for problems that do not prevent it from being
+    /// displayed, returning the index of each affected page along with the problem.
+    ///
+    /// Pages must set their style and line metrics before showing any text, as readers may
+    /// display them without reading the pages that precede them. The first page may rely on the
+    /// header's first style, but must still set its line metrics.
+    pub fn verify(
+        header: &Header,
+        trailer: &Trailer,
+        source: &[u8],
+    ) -> Result<Vec<(u32, Warning)>, Error> {
+        let mut warnings = Vec::new();
+        for (index, offset) in trailer.pages.iter().enumerate() {
+            let source = source.get(*offset as usize..).ok_or(Error::InvalidOffset)?;
+            let mut style = index == 0;
+            let mut metrics = false;
+            for command in page_iter(header, source) {
+                match command? {
+                    Command::SetStyle { .. } => style = true,
+                    Command::SetLineMetrics { .. } => metrics = true,
+                    Command::Show { .. } | Command::ShowPositioned { .. } => {
+                        if !(style && metrics) {
+                            warnings.push((index as u32, Warning::ShowBeforeStyle));
+                        }
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(warnings)
+    }
+
+    /// Resolves the anchor with the given ID to a `(page_index, x, y)` location.
+    pub fn anchor_location(trailer: &Trailer, id: u16) -> Option<(u32, u16, u16)> {
+        trailer
+            .anchors
+            .iter()
+            .find(|a| a.0 == id)
+            .map(|&(_, page, x, y)| (page, x, y))
+    }
+
+    // Operands are read from in-memory slices, so any I/O error means that the command is truncated.
+    fn unsigned(r: &mut io::Cursor<&[u8]>) -> Result<u64, Error> {
+        leb128::read::unsigned(r).map_err(|_| Error::InvalidEncoding)
+    }
+
+    fn signed(r: &mut io::Cursor<&[u8]>) -> Result<i64, Error> {
+        leb128::read::signed(r).map_err(|_| Error::InvalidEncoding)
     }
 
-    fn decode_command<'a>(
+    /// Decodes the command at the start of `source`, returning the command and its encoded length.
+    pub fn decode_command<'a>(
         header: &Header,
         source: &'a [u8],
     ) -> Result<(Command<&'a str>, usize), Error> {
-        let code = source[0];
+        let code = *source.first().ok_or(Error::InvalidEncoding)?;
         let source = &source[1..];
 
+        if header.version < 2 && (0x86..=0x8d).contains(&code) {
+            return Err(Error::InvalidCommand);
+        }
+
         let (command, len) = match code {
             0x09 => (Command::HTab, 0),
             0x0a => (Command::LineBreak, 0),
@@ -209,18 +738,18 @@ pub mod read {
             0x80 => (Command::Nop, 0),
             0x81 => {
                 let mut r = io::Cursor::new(source);
-                let dx: u16 = leb128::read::unsigned(&mut r)?.try_into()?;
+                let dx: u16 = unsigned(&mut r)?.try_into()?;
                 (Command::Advance { dx }, r.position() as usize)
             }
             0x82 => {
                 let mut r = io::Cursor::new(source);
-                let x: u16 = leb128::read::unsigned(&mut r)?.try_into()?;
-                let y: u16 = leb128::read::unsigned(&mut r)?.try_into()?;
+                let x: u16 = unsigned(&mut r)?.try_into()?;
+                let y: u16 = unsigned(&mut r)?.try_into()?;
                 (Command::SetCursor { x, y }, r.position() as usize)
             }
             0x83 => {
                 let mut r = io::Cursor::new(source);
-                let s: u16 = leb128::read::unsigned(&mut r)?.try_into()?;
+                let s: u16 = unsigned(&mut r)?.try_into()?;
                 if (s as usize) >= header.styles.len() {
                     return Err(Error::InvalidStyleIndex);
                 }
@@ -240,13 +769,91 @@ pub mod read {
             }
             0x85 => {
                 let mut r = io::Cursor::new(source);
-                let height: u16 = leb128::read::unsigned(&mut r)?.try_into()?;
-                let baseline: u16 = leb128::read::unsigned(&mut r)?.try_into()?;
+                let height: u16 = unsigned(&mut r)?.try_into()?;
+                let baseline: u16 = unsigned(&mut r)?.try_into()?;
                 (
                     Command::SetLineMetrics { height, baseline },
                     r.position() as usize,
                 )
             }
+            0x86 => {
+                if source.len() < 3 {
+                    return Err(Error::InvalidEncoding);
+                }
+                (
+                    Command::SetColor {
+                        r: source[0],
+                        g: source[1],
+                        b: source[2],
+                    },
+                    3,
+                )
+            }
+            0x87 => {
+                if source.is_empty() {
+                    return Err(Error::InvalidEncoding);
+                }
+                let flags = source[0];
+                (
+                    Command::SetDecoration {
+                        underline: flags & 0x01 != 0,
+                        strikethrough: flags & 0x02 != 0,
+                    },
+                    1,
+                )
+            }
+            0x88 => {
+                let mut r = io::Cursor::new(source);
+                let width: u16 = unsigned(&mut r)?.try_into()?;
+                let height: u16 = unsigned(&mut r)?.try_into()?;
+                let data_offset: u32 = unsigned(&mut r)?.try_into()?;
+                if header.images.get(data_offset).is_none() {
+                    return Err(Error::InvalidImageOffset);
+                }
+                (
+                    Command::DrawImage {
+                        width,
+                        height,
+                        data_offset,
+                    },
+                    r.position() as usize,
+                )
+            }
+            0x89 => {
+                let mut r = io::Cursor::new(source);
+                let target_offset: u32 = unsigned(&mut r)?.try_into()?;
+                let len: u16 = unsigned(&mut r)?.try_into()?;
+                (Command::Link { target_offset, len }, r.position() as usize)
+            }
+            0x8a => {
+                let mut r = io::Cursor::new(source);
+                let id: u16 = unsigned(&mut r)?.try_into()?;
+                (Command::Anchor { id }, r.position() as usize)
+            }
+            0x8b => {
+                let mut r = io::Cursor::new(source);
+                let dx: i16 = signed(&mut r)?.try_into()?;
+                let dy: i16 = signed(&mut r)?.try_into()?;
+                (Command::MoveBy { dx, dy }, r.position() as usize)
+            }
+            0x8c => {
+                let mut r = io::Cursor::new(source);
+                let w: u16 = unsigned(&mut r)?.try_into()?;
+                let h: u16 = unsigned(&mut r)?.try_into()?;
+                (Command::FillRect { w, h }, r.position() as usize)
+            }
+            0x8d => {
+                let mut r = io::Cursor::new(source);
+                let len: u32 = unsigned(&mut r)?.try_into()?;
+                let mut glyphs = Vec::new();
+                for _ in 0..len {
+                    let c: u32 = unsigned(&mut r)?.try_into()?;
+                    let c = char::from_u32(c).ok_or(Error::InvalidEncoding)?;
+                    let dx: i16 = signed(&mut r)?.try_into()?;
+                    glyphs.push((c, dx));
+                }
+                (Command::ShowPositioned { glyphs }, r.position() as usize)
+            }
             0xbf => (Command::End, 0),
             _ => return Err(Error::InvalidCommand),
         };
@@ -254,45 +861,449 @@ pub mod read {
         Ok((command, len + 1))
     }
 
-    pub fn page<'a>(header: &Header, mut source: &'a [u8]) -> Result<Vec<Command<&'a str>>, Error> {
-        let mut commands = Vec::new();
+    /// Returns the length of the command stream at the start of `source`, including the
+    /// terminating `End` command.
+    fn commands_len(header: &Header, source: &[u8]) -> Result<usize, Error> {
+        let mut i = 0;
+        while i < source.len() {
+            if source[i] < 0x20 || source[i] > 0x7f && source[i] < 0xc0 {
+                let (command, advance) = decode_command(header, &source[i..])?;
+                i += advance;
+                if matches!(command, Command::End) {
+                    return Ok(i);
+                }
+            } else {
+                match UTF8_CHAR_WIDTH[source[i] as usize] {
+                    0 => return Err(Error::InvalidEncoding),
+                    width => i += width as usize,
+                }
+            }
+        }
+        Err(Error::InvalidEncoding)
+    }
+
+    fn document(source: &[u8]) -> Result<(Document, usize), Error> {
+        let mut r = io::Cursor::new(source);
+        let header = header(&mut r)?;
+
+        let commands_start = r.position() as usize;
+        let trailer_start = commands_start + commands_len(&header, &source[commands_start..])?;
+
+        let mut r = io::Cursor::new(&source[trailer_start..]);
+        let trailer = trailer(&header, &mut r)?;
+
+        // check the trailer's back-pointer
+        let trailer_len = r.position() as usize;
+        let back_pointer = trailer_start + trailer_len;
+        if source.len() < back_pointer + 4 {
+            return Err(Error::InvalidEncoding);
+        }
+        let buf = [
+            source[back_pointer],
+            source[back_pointer + 1],
+            source[back_pointer + 2],
+            source[back_pointer + 3],
+        ];
+        if i32::from_le_bytes(buf) != -(trailer_len as i32) {
+            return Err(Error::InvalidEncoding);
+        }
+
+        let len = trailer_start + trailer_len + 4;
+        validate(&header, &trailer, len)?;
+
+        let bytes = Vec::from(&source[..len]);
+        Ok((
+            Document {
+                header,
+                trailer,
+                bytes,
+            },
+            len,
+        ))
+    }
+
+    /// Reads each of the documents in a stream of concatenated documents, starting at the
+    /// current position and continuing until the end of the stream.
+    pub fn documents<R: io::Read + io::Seek>(r: &mut R) -> Result<Vec<Document>, Error> {
+        #[allow(clippy::seek_from_current)]
+        let start = r.seek(io::SeekFrom::Current(0))?;
+        let end = r.seek(io::SeekFrom::End(0))?;
+        r.seek(io::SeekFrom::Start(start))?;
+
+        let mut bytes = Vec::new();
+        bytes.reserve_exact((end - start) as usize);
+        bytes.resize((end - start) as usize, 0);
+        r.read_exact(bytes.as_mut_slice())?;
 
+        let mut documents = Vec::new();
+        let mut source = bytes.as_slice();
         while !source.is_empty() {
+            let (document, len) = document(source)?;
+            documents.push(document);
+            source = &source[len..];
+        }
+
+        Ok(documents)
+    }
+
+    /// A lazily-decoded page of commands. See `page_iter`.
+    pub struct PageIter<'h, 'a> {
+        header: &'h Header,
+        source: &'a [u8],
+        pending: Option<Command<&'a str>>,
+        done: bool,
+    }
+
+    impl<'h, 'a> PageIter<'h, 'a> {
+        fn command(&mut self, command: Command<&'a str>) -> Command<&'a str> {
+            self.done = matches!(command, Command::PageBreak | Command::End);
+            command
+        }
+
+        fn decode(&mut self) -> Result<Command<&'a str>, Error> {
+            let source = self.source;
+
             let mut i = 0;
             while i < source.len() {
                 if source[i] < 0x20 || source[i] > 0x7f && source[i] < 0xc0 {
                     break;
                 }
-                i += UTF8_CHAR_WIDTH[source[i] as usize] as usize;
+                match UTF8_CHAR_WIDTH[source[i] as usize] {
+                    0 => return Err(Error::InvalidEncoding),
+                    width => i += width as usize,
+                }
             }
-            if i != 0 {
-                commands.push(Command::Show {
-                    str: core::str::from_utf8(&source[..i])?,
-                });
+
+            if i == 0 {
+                let (command, advance) = decode_command(self.header, source)?;
+                self.source = &source[advance..];
+                return Ok(self.command(command));
             }
+
+            let bytes = source.get(..i).ok_or(Error::InvalidEncoding)?;
+            let show = Command::Show {
+                str: core::str::from_utf8(bytes)?,
+            };
             if i != source.len() {
-                let (command, advance) = decode_command(header, &source[i..])?;
-                commands.push(command);
-                if matches!(
-                    commands[commands.len() - 1],
-                    Command::PageBreak | Command::End
-                ) {
+                let (command, advance) = decode_command(self.header, &source[i..])?;
+                self.pending = Some(command);
+                i += advance;
+            }
+            self.source = &source[i..];
+            Ok(show)
+        }
+    }
+
+    impl<'h, 'a> Iterator for PageIter<'h, 'a> {
+        type Item = Result<Command<&'a str>, Error>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            if self.done {
+                return None;
+            }
+            if let Some(command) = self.pending.take() {
+                return Some(Ok(self.command(command)));
+            }
+            if self.source.is_empty() {
+                self.done = true;
+                return None;
+            }
+
+            let result = self.decode();
+            if result.is_err() {
+                self.done = true;
+            }
+            Some(result)
+        }
+    }
+
+    /// Lazily decodes the page at the start of `source`, yielding `Show` runs and commands one at
+    /// a time and stopping after the first `PageBreak` or `End` command. Iteration also stops
+    /// after the first error.
+    pub fn page_iter<'h, 'a>(header: &'h Header, source: &'a [u8]) -> PageIter<'h, 'a> {
+        PageIter {
+            header,
+            source,
+            pending: None,
+            done: false,
+        }
+    }
+
+    pub fn page<'a>(header: &Header, source: &'a [u8]) -> Result<Vec<Command<&'a str>>, Error> {
+        page_iter(header, source).collect()
+    }
+
+    /// Decodes the page at the start of `source` like `page`, pairing each command with the
+    /// offset of its encoding within `source`.
+    pub fn page_with_offsets<'a>(
+        header: &Header,
+        source: &'a [u8],
+    ) -> Result<Vec<(usize, Command<&'a str>)>, Error> {
+        decode_page(header, source).map_err(|(_, err)| err)
+    }
+
+    /// Checks that the page in `source` decodes and ends with a `PageBreak` or `End` command. On
+    /// failure, returns the offset within `source` of the first problem along with the problem.
+    pub fn check_page(header: &Header, source: &[u8]) -> Result<(), (usize, Error)> {
+        let commands = decode_page(header, source)?;
+        match commands.last() {
+            Some((_, Command::PageBreak | Command::End)) => Ok(()),
+            _ => Err((source.len(), Error::UnterminatedPage)),
+        }
+    }
+
+    /// A page's commands, each paired with the offset of its encoding.
+    type OffsetCommands<'a> = Vec<(usize, Command<&'a str>)>;
+
+    /// Decodes a page like `page_with_offsets`, pairing any error with the offset at which it
+    /// occurred.
+    fn decode_page<'a>(
+        header: &Header,
+        source: &'a [u8],
+    ) -> Result<OffsetCommands<'a>, (usize, Error)> {
+        let mut commands = Vec::new();
+        let mut i = 0;
+        while i < source.len() {
+            let start = i;
+            if source[i] < 0x20 || source[i] > 0x7f && source[i] < 0xc0 {
+                let (command, advance) =
+                    decode_command(header, &source[i..]).map_err(|err| (start, err))?;
+                let done = matches!(command, Command::PageBreak | Command::End);
+                commands.push((start, command));
+                if done {
                     break;
                 }
                 i += advance;
+                continue;
             }
-            source = &source[i..];
-        }
 
+            while i < source.len() && !(source[i] < 0x20 || source[i] > 0x7f && source[i] < 0xc0) {
+                match UTF8_CHAR_WIDTH[source[i] as usize] {
+                    0 => return Err((i, Error::InvalidEncoding)),
+                    width => i += width as usize,
+                }
+            }
+            let bytes = source
+                .get(start..i)
+                .ok_or((start, Error::InvalidEncoding))?;
+            let str = core::str::from_utf8(bytes)
+                .map_err(|err| (start + err.valid_up_to(), err.into()))?;
+            commands.push((start, Command::Show { str }));
+        }
         Ok(commands)
     }
+
+    /// A link region on a page, in page coordinates.
+    #[cfg(feature = "layout")]
+    pub struct Link<'a> {
+        pub url: &'a str,
+        pub bounding_box: embedded_graphics::primitives::Rectangle,
+    }
+
+    /// Returns the link regions on the page at the start of `source`. A link that spans
+    /// multiple lines produces one region per line.
+    #[cfg(feature = "layout")]
+    pub fn links<'a, F: crate::layout::Fonts>(
+        header: &Header,
+        trailer: &'a Trailer,
+        fonts: F,
+        source: &[u8],
+    ) -> Result<Vec<Link<'a>>, Error> {
+        use crate::fonts::FontStyle as _;
+        use crate::layout::FontStyle as _;
+        use embedded_graphics::{
+            geometry::{Point, Size},
+            primitives::Rectangle,
+        };
+
+        let mut style = match header.styles.first().and_then(|s| fonts.get_style(s)) {
+            None => return Ok(Vec::new()),
+            Some(s) => s,
+        };
+        let default_style = style.clone();
+
+        let mut glue_width = style.em_px() as f32 / 3.0;
+        let mut glue_stretch = glue_width / 2.0;
+        let mut glue_shrink = glue_width / 3.0;
+        let mut whitespace_width = glue_width;
+
+        let mut line_height = style.line_height() as i32;
+        let mut x = 0.0;
+        let mut y = 0;
+
+        let mut links = Vec::new();
+        let mut link: Option<(&'a str, usize, f32)> = None;
+
+        fn close<'a>(
+            links: &mut Vec<Link<'a>>,
+            link: &Option<(&'a str, usize, f32)>,
+            x: f32,
+            y: i32,
+            h: i32,
+        ) {
+            if let Some((url, _, start)) = link {
+                if x > *start {
+                    links.push(Link {
+                        url,
+                        bounding_box: Rectangle::new(
+                            Point::new(*start as i32, y),
+                            Size::new((x - start) as u32, h as u32),
+                        ),
+                    });
+                }
+            }
+        }
+
+        // Counts `len` bytes of shown text against the current link, closing it once exhausted.
+        fn consume<'a>(
+            links: &mut Vec<Link<'a>>,
+            link: &mut Option<(&'a str, usize, f32)>,
+            len: usize,
+            x: f32,
+            y: i32,
+            h: i32,
+        ) {
+            if let Some((url, remaining, start)) = *link {
+                let remaining = remaining.saturating_sub(len);
+                *link = Some((url, remaining, start));
+                if remaining == 0 {
+                    close(links, link, x, y, h);
+                    *link = None;
+                }
+            }
+        }
+
+        for command in page(header, source)? {
+            match command {
+                Command::LineBreak => {
+                    close(&mut links, &link, x, y, line_height);
+                    x = 0.0;
+                    y += line_height;
+                    whitespace_width = glue_width;
+                    link = link.map(|(url, len, _)| (url, len, x));
+                }
+                Command::PageBreak | Command::End => break,
+                Command::HTab => {
+                    let tab_stop = style.tab_stop().max(1) as i32;
+                    x = (((x as i32).div_euclid(tab_stop) + 1) * tab_stop) as f32;
+                }
+                Command::VTab => {
+                    close(&mut links, &link, x, y, line_height);
+                    let tab_stop = style.tab_stop().max(1) as i32;
+                    y = (y.div_euclid(tab_stop) + 1) * tab_stop;
+                    link = link.map(|(url, len, _)| (url, len, x));
+                }
+                Command::Advance { dx } => x += dx as f32,
+                Command::MoveBy { dx, dy: 0 } => x += dx as f32,
+                Command::MoveBy { dx, dy } => {
+                    close(&mut links, &link, x, y, line_height);
+                    x += dx as f32;
+                    y += dy as i32;
+                    link = link.map(|(url, len, _)| (url, len, x));
+                }
+                Command::SetCursor { x: cx, y: cy } => {
+                    close(&mut links, &link, x, y, line_height);
+                    x = cx as f32;
+                    y = cy as i32;
+                    link = link.map(|(url, len, _)| (url, len, x));
+                }
+                Command::SetAdjustmentRatio { r } => {
+                    whitespace_width = if r < 0.0 {
+                        glue_width + glue_shrink * r
+                    } else if r > 0.0 {
+                        glue_width + glue_stretch * r
+                    } else {
+                        glue_width
+                    };
+                }
+                Command::SetLineMetrics { height, .. } => line_height = height as i32,
+                Command::SetStyle { s } => {
+                    style = fonts
+                        .get_style(&header.styles[s as usize])
+                        .unwrap_or_else(|| default_style.clone());
+                    glue_width = style.em_px() as f32 / 3.0;
+                    glue_stretch = glue_width / 2.0;
+                    glue_shrink = glue_width / 3.0;
+                }
+                Command::Link { target_offset, len } => {
+                    close(&mut links, &link, x, y, line_height);
+                    link = trailer
+                        .urls
+                        .get(target_offset)
+                        .map(|url| (url, len as usize, x));
+                }
+                Command::DrawImage { width, .. } => x += width as f32,
+                Command::Show { str } => {
+                    for c in str.chars() {
+                        x += if matches!(c, '\u{a0}' | '\u{202f}') {
+                            glue_width
+                        } else if c.is_whitespace() {
+                            whitespace_width
+                        } else {
+                            let mut buf = [0; 4];
+                            let metrics = style.measure_string(c.encode_utf8(&mut buf));
+                            metrics.bounding_box.size.width as f32
+                        };
+
+                        consume(&mut links, &mut link, c.len_utf8(), x, y, line_height);
+                    }
+                }
+                Command::ShowPositioned { glyphs } => {
+                    for (c, dx) in glyphs {
+                        x += dx as f32;
+                        consume(&mut links, &mut link, c.len_utf8(), x, y, line_height);
+                    }
+                }
+                _ => {}
+            }
+        }
+        close(&mut links, &link, x, y, line_height);
+
+        Ok(links)
+    }
 }
 
 pub mod write {
     use super::*;
     use crate::leb128;
+    use core::fmt;
     use no_std_io::io;
 
+    #[derive(Debug)]
+    pub enum Error {
+        IoError(io::Error),
+        /// A page offset, table offset, style index, or anchor ID, or the trailer length, does
+        /// not fit in its encoding.
+        OffsetOverflow,
+        UnsupportedVersion(u8),
+        /// The document uses a feature that postdates the target version.
+        UnsupportedFeature,
+    }
+
+    impl From<io::Error> for Error {
+        fn from(err: io::Error) -> Error {
+            Error::IoError(err)
+        }
+    }
+
+    impl fmt::Display for Error {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Error::IoError(err) => write!(f, "I/O error: {}", err),
+                Error::OffsetOverflow => write!(f, "offset overflow"),
+                Error::UnsupportedVersion(v) => write!(f, "unsupported version {}", v),
+                Error::UnsupportedFeature => write!(f, "feature not supported by target version"),
+            }
+        }
+    }
+
+    #[cfg(feature = "std")]
+    impl std::error::Error for Error {}
+
+    fn offset(at: usize) -> Result<u32, Error> {
+        u32::try_from(at).map_err(|_| Error::OffsetOverflow)
+    }
+
     fn write_all<W: io::Write>(w: &mut W, bytes: &[u8]) -> Result<usize, io::Error> {
         w.write_all(bytes)?;
         Ok(bytes.len())
@@ -310,9 +1321,9 @@ pub mod write {
         Ok(n)
     }
 
-    fn encode_header<W: io::Write>(w: &mut W, h: &Header) -> Result<usize, io::Error> {
+    fn encode_header<W: io::Write>(w: &mut W, version: u8, h: &Header) -> Result<usize, io::Error> {
         // write magic
-        let magic = [0x0e, 0xdf, 0x01, 0x00];
+        let magic = [0x0e, 0xdf, version, 0x00];
         let mut n = write_all(w, &magic[..])?;
 
         // write title
@@ -324,38 +1335,165 @@ pub mod write {
             n += encode_style(w, s)?;
         }
 
+        // write image table and language tag
+        if version >= 2 {
+            let images = h.images.as_bytes();
+            n += leb128::write::unsigned(w, images.len() as u64)?;
+            n += write_all(w, images)?;
+
+            n += encode_string(w, h.language.as_deref().unwrap_or(""))?;
+        }
+
+        // write revision
+        if version >= 3 {
+            n += match h.revision {
+                None => write_all(w, &[0])?,
+                Some(revision) => write_all(w, &[1])? + write_all(w, &revision.to_le_bytes())?,
+            };
+        }
+
+        // write embedded fonts
+        if version >= 4 {
+            n += leb128::write::unsigned(w, h.fonts.len() as u64)?;
+            for font in &h.fonts {
+                n += encode_string(w, &font.name)?;
+                n += leb128::write::unsigned(w, font.data.len() as u64)?;
+                n += write_all(w, &font.data)?;
+            }
+        }
+
+        // write metadata
+        if version >= 5 {
+            let m = &h.metadata;
+            let known = [("author", &m.author), ("date", &m.date)];
+            let known = known
+                .iter()
+                .filter_map(|(key, value)| value.as_ref().map(|v| (*key, v.as_bytes())));
+            let other = m
+                .other
+                .iter()
+                .map(|(key, value)| (key.as_str(), &value[..]));
+            let entries: Vec<(&str, &[u8])> = known.chain(other).collect();
+
+            n += leb128::write::unsigned(w, entries.len() as u64)?;
+            for (key, value) in entries {
+                n += encode_string(w, key)?;
+                n += leb128::write::unsigned(w, value.len() as u64)?;
+                n += write_all(w, value)?;
+            }
+        }
+
+        // write page geometry
+        if version >= 6 {
+            n += match &h.page {
+                None => write_all(w, &[0])?,
+                Some(page) => {
+                    let values = [
+                        page.width,
+                        page.height,
+                        page.top_margin,
+                        page.right_margin,
+                        page.bottom_margin,
+                        page.left_margin,
+                    ];
+                    let mut n = write_all(w, &[1])?;
+                    for value in values {
+                        n += leb128::write::unsigned(w, value as u64)?;
+                    }
+                    n
+                }
+            };
+        }
+
         Ok(n)
     }
 
-    fn encode_pages<W: io::Write, S: AsRef<str> + Clone>(
-        w: &mut W,
-        at: usize,
-        pages: &[Command<S>],
-    ) -> Result<(Vec<u32>, usize), io::Error> {
-        let mut page_offsets = Vec::new();
-        page_offsets.push(at as u32);
+    type Anchor = (u16, u32, u16, u16);
 
-        let mut n = 0;
-        for c in pages {
+    /// Encodes a command stream, recording page offsets and anchor locations as it goes.
+    struct PageEncoder {
+        /// The offset of the next byte from the start of the document.
+        len: usize,
+        page_offsets: Vec<u32>,
+        anchors: Vec<Anchor>,
+        ended: bool,
+
+        // Track the cursor as far as it can be known without font metrics in order to locate
+        // anchors.
+        x: u16,
+        y: u16,
+        line_height: u16,
+    }
+
+    impl PageEncoder {
+        fn new(at: usize) -> Result<Self, Error> {
+            Ok(PageEncoder {
+                len: at,
+                page_offsets: alloc::vec![offset(at)?],
+                anchors: Vec::new(),
+                ended: false,
+                x: 0,
+                y: 0,
+                line_height: 0,
+            })
+        }
+
+        /// Encodes a single command. Commands that follow an `End` command are ignored.
+        fn encode<W: io::Write, S: AsRef<str> + Clone>(
+            &mut self,
+            w: &mut W,
+            c: &Command<S>,
+        ) -> Result<usize, Error> {
+            if self.ended {
+                return Ok(0);
+            }
+
+            let mut n = 0;
             match c {
                 Command::Nop => n += write_all(w, &[0x80])?,
                 Command::HTab => n += write_all(w, &[0x09])?,
-                Command::LineBreak => n += write_all(w, &[0x0a])?,
+                Command::LineBreak => {
+                    n += write_all(w, &[0x0a])?;
+                    self.x = 0;
+                    self.y = self.y.saturating_add(self.line_height);
+                }
                 Command::VTab => n += write_all(w, &[0x0b])?,
                 Command::PageBreak => {
                     n += write_all(w, &[0x0c])?;
 
-                    // TODO: make this checked
-                    page_offsets.push((at + n) as u32);
+                    self.page_offsets.push(offset(self.len + n)?);
+                    (self.x, self.y) = (0, 0);
                 }
                 Command::Show { str } => n += write_all(w, str.as_ref().as_bytes())?,
                 Command::Advance { dx } => {
                     n += write_all(w, &[0x81])? + leb128::write::unsigned(w, *dx as u64)?;
+                    self.x = self.x.saturating_add(*dx);
                 }
-                Command::SetCursor { x, y } => {
+                Command::SetCursor { x: cx, y: cy } => {
                     n += write_all(w, &[0x82])?;
-                    n += leb128::write::unsigned(w, *x as u64)?;
-                    n += leb128::write::unsigned(w, *y as u64)?;
+                    n += leb128::write::unsigned(w, *cx as u64)?;
+                    n += leb128::write::unsigned(w, *cy as u64)?;
+                    (self.x, self.y) = (*cx, *cy);
+                }
+                Command::MoveBy { dx, dy } => {
+                    n += write_all(w, &[0x8b])?
+                        + leb128::write::signed(w, *dx as i64)?
+                        + leb128::write::signed(w, *dy as i64)?;
+                    self.x = self.x.saturating_add_signed(*dx);
+                    self.y = self.y.saturating_add_signed(*dy);
+                }
+                Command::FillRect { w: width, h } => {
+                    n += write_all(w, &[0x8c])?
+                        + leb128::write::unsigned(w, *width as u64)?
+                        + leb128::write::unsigned(w, *h as u64)?;
+                }
+                Command::ShowPositioned { glyphs } => {
+                    n += write_all(w, &[0x8d])? + leb128::write::unsigned(w, glyphs.len() as u64)?;
+                    for (c, dx) in glyphs {
+                        n += leb128::write::unsigned(w, *c as u64)?
+                            + leb128::write::signed(w, *dx as i64)?;
+                        self.x = self.x.saturating_add_signed(*dx);
+                    }
                 }
                 Command::SetStyle { s } => {
                     n += write_all(w, &[0x83])? + leb128::write::unsigned(w, *s as u64)?;
@@ -364,41 +1502,364 @@ pub mod write {
                     n += write_all(w, &[0x84])? + write_all(w, &r.to_le_bytes())?;
                 }
                 Command::SetLineMetrics { height, baseline } => {
+                    self.line_height = *height;
                     n += write_all(w, &[0x85])?
                         + leb128::write::unsigned(w, *height as u64)?
                         + leb128::write::unsigned(w, *baseline as u64)?;
                 }
-                _ => {}
+                Command::SetColor { r, g, b } => {
+                    n += write_all(w, &[0x86, *r, *g, *b])?;
+                }
+                Command::SetDecoration {
+                    underline,
+                    strikethrough,
+                } => {
+                    let flags = (*underline as u8) | (*strikethrough as u8) << 1;
+                    n += write_all(w, &[0x87, flags])?;
+                }
+                Command::DrawImage {
+                    width,
+                    height,
+                    data_offset,
+                } => {
+                    n += write_all(w, &[0x88])?
+                        + leb128::write::unsigned(w, *width as u64)?
+                        + leb128::write::unsigned(w, *height as u64)?
+                        + leb128::write::unsigned(w, *data_offset as u64)?;
+                }
+                Command::Link { target_offset, len } => {
+                    n += write_all(w, &[0x89])?
+                        + leb128::write::unsigned(w, *target_offset as u64)?
+                        + leb128::write::unsigned(w, *len as u64)?;
+                }
+                Command::Anchor { id } => {
+                    n += write_all(w, &[0x8a])? + leb128::write::unsigned(w, *id as u64)?;
+                    self.anchors
+                        .push((*id, (self.page_offsets.len() - 1) as u32, self.x, self.y));
+                }
+                Command::End => {
+                    n += write_all(w, &[0xbf])?;
+                    self.ended = true;
+                }
             };
+            self.len += n;
+            Ok(n)
+        }
+
+        /// Terminates the command stream if necessary and returns the page offsets, the anchors,
+        /// and the length of the command stream.
+        fn finish<W: io::Write>(
+            mut self,
+            w: &mut W,
+            at: usize,
+        ) -> Result<(Vec<u32>, Vec<Anchor>, usize), Error> {
+            self.encode::<W, &str>(w, &Command::End)?;
+            Ok((self.page_offsets, self.anchors, self.len - at))
         }
-        n += write_all(w, &[0xbf])?;
-        Ok((page_offsets, n))
     }
 
-    fn encode_trailer<W: io::Write>(w: &mut W, pages: Vec<u32>) -> Result<usize, io::Error> {
+    fn encode_pages<W: io::Write, S: AsRef<str> + Clone>(
+        w: &mut W,
+        at: usize,
+        pages: &[Command<S>],
+    ) -> Result<(Vec<u32>, Vec<Anchor>, usize), Error> {
+        let mut encoder = PageEncoder::new(at)?;
+        for c in pages {
+            encoder.encode(w, c)?;
+        }
+        encoder.finish(w, at)
+    }
+
+    fn encode_trailer<W: io::Write>(
+        w: &mut W,
+        version: u8,
+        pages: Vec<u32>,
+        urls: &StringPool,
+        anchors: Vec<Anchor>,
+    ) -> Result<usize, Error> {
         // Encode page vector
         let mut n = leb128::write::unsigned(w, pages.len() as u64)?;
         for p in pages {
             n += leb128::write::unsigned(w, p as u64)?;
         }
 
-        // TODO: make this checked
-        let offset = (-(n as i32)).to_le_bytes();
-        n += write_all(w, &offset[..])?;
+        if version >= 2 {
+            // Encode URL pool
+            let urls = urls.as_bytes();
+            n += leb128::write::unsigned(w, urls.len() as u64)?;
+            n += write_all(w, urls)?;
+
+            // Encode anchor vector
+            n += leb128::write::unsigned(w, anchors.len() as u64)?;
+            for (id, page, x, y) in anchors {
+                n += leb128::write::unsigned(w, id as u64)?;
+                n += leb128::write::unsigned(w, page as u64)?;
+                n += leb128::write::unsigned(w, x as u64)?;
+                n += leb128::write::unsigned(w, y as u64)?;
+            }
+        }
+
+        let offset = i32::try_from(n).map_err(|_| Error::OffsetOverflow)?;
+        n += write_all(w, &(-offset).to_le_bytes())?;
 
         Ok(n)
     }
 
+    fn check_header(version: u8, h: &Header) -> Result<(), Error> {
+        if !(MIN_VERSION..=VERSION).contains(&version) {
+            return Err(Error::UnsupportedVersion(version));
+        }
+        if version < 2 && (!h.images.is_empty() || h.language.is_some()) {
+            return Err(Error::UnsupportedFeature);
+        }
+        if version < 3 && h.revision.is_some() {
+            return Err(Error::UnsupportedFeature);
+        }
+        if version < 4 && !h.fonts.is_empty() {
+            return Err(Error::UnsupportedFeature);
+        }
+        if version < 5 && !h.metadata.is_empty() {
+            return Err(Error::UnsupportedFeature);
+        }
+        if version < 6 && h.page.is_some() {
+            return Err(Error::UnsupportedFeature);
+        }
+        Ok(())
+    }
+
+    /// Writes a document in the given version of the format. `h.version` is ignored.
+    ///
+    /// Fails without writing anything if the version is not supported or if the document uses
+    /// features that postdate it.
     pub fn doc<W: io::Write, S: AsRef<str> + Clone>(
         w: &mut W,
+        version: u8,
         h: &Header,
+        urls: &StringPool,
         pages: &[Command<S>],
-    ) -> Result<usize, io::Error> {
-        let header_len = encode_header(w, h)?;
-        let (page_offsets, commands_len) = encode_pages(w, header_len, pages)?;
-        let trailer_len = encode_trailer(w, page_offsets)?;
+    ) -> Result<usize, Error> {
+        check_header(version, h)?;
+        if version < 2 && !urls.is_empty() {
+            return Err(Error::UnsupportedFeature);
+        }
+        if pages.iter().any(|c| c.min_version() > version) {
+            return Err(Error::UnsupportedFeature);
+        }
+
+        let header_len = encode_header(w, version, h)?;
+        let (page_offsets, anchors, commands_len) = encode_pages(w, header_len, pages)?;
+        let trailer_len = encode_trailer(w, version, page_offsets, urls, anchors)?;
         Ok(header_len + commands_len + trailer_len)
     }
+
+    /// A document to merge: its header, URL pool, and pages.
+    pub type MergeSource<'a, S> = (&'a Header, &'a StringPool, &'a [Vec<Command<S>>]);
+
+    /// Merges documents into one whose pages are those of each document in turn, returning the
+    /// merged header, URL pool, and commands, which can be written using `doc`.
+    ///
+    /// The merged style vector holds each distinct style once, and each document's `SetStyle`,
+    /// `DrawImage`, `Link`, and `Anchor` commands are remapped to refer to the merged header and
+    /// pool. Embedded fonts are merged by name; if documents embed different fonts with the same
+    /// name, the first is kept. The title, language, metadata, and page geometry are those of the
+    /// first document that has them, and the merged document has no revision.
+    ///
+    /// Each page is given as it is decoded by `read::page`, i.e. ending with its `PageBreak` or
+    /// `End` command.
+    pub fn merge<S: Clone>(
+        docs: &[MergeSource<S>],
+    ) -> Result<(Header, StringPool, Vec<Command<S>>), Error> {
+        let mut header = Header {
+            version: 0,
+            title: String::new(),
+            styles: Vec::new(),
+            images: ImageTable::new(),
+            language: None,
+            revision: None,
+            fonts: Vec::new(),
+            metadata: Metadata::default(),
+            page: None,
+        };
+        let mut urls = StringPool::new();
+        let mut commands = Vec::new();
+        let mut anchor_base: u32 = 0;
+        let mut page_count = 0;
+
+        for (h, pool, pages) in docs {
+            header.version = header.version.max(h.version);
+            if header.title.is_empty() {
+                header.title = h.title.clone();
+            }
+            if header.language.is_none() {
+                header.language = h.language.clone();
+            }
+            if header.metadata.is_empty() {
+                header.metadata = h.metadata.clone();
+            }
+            if header.page.is_none() {
+                header.page = h.page;
+            }
+            for font in &h.fonts {
+                if !header.fonts.iter().any(|f| f.name == font.name) {
+                    header.fonts.push(font.clone());
+                }
+            }
+
+            let styles: Vec<u16> = h
+                .styles
+                .iter()
+                .map(|style| {
+                    let index = match header.styles.iter().position(|s| s == style) {
+                        Some(index) => index,
+                        None => {
+                            header.styles.push(style.clone());
+                            header.styles.len() - 1
+                        }
+                    };
+                    u16::try_from(index).map_err(|_| Error::OffsetOverflow)
+                })
+                .collect::<Result<_, _>>()?;
+
+            // Image tables and URL pools are concatenated, so their entries move by the length of
+            // those that precede them.
+            let image_base = offset(header.images.data.len())?;
+            header.images.data.extend_from_slice(&h.images.data);
+            let url_base = offset(urls.data.len())?;
+            urls.data.extend_from_slice(&pool.data);
+
+            let mut max_anchor = None;
+            for page in pages.iter() {
+                if page_count > 0 {
+                    commands.push(Command::PageBreak);
+                }
+                page_count += 1;
+                for command in page {
+                    let command = match command {
+                        Command::PageBreak | Command::End => break,
+                        Command::SetStyle { s } => Command::SetStyle {
+                            s: *styles.get(*s as usize).ok_or(Error::OffsetOverflow)?,
+                        },
+                        Command::DrawImage {
+                            width,
+                            height,
+                            data_offset,
+                        } => Command::DrawImage {
+                            width: *width,
+                            height: *height,
+                            data_offset: data_offset
+                                .checked_add(image_base)
+                                .ok_or(Error::OffsetOverflow)?,
+                        },
+                        Command::Link { target_offset, len } => Command::Link {
+                            target_offset: target_offset
+                                .checked_add(url_base)
+                                .ok_or(Error::OffsetOverflow)?,
+                            len: *len,
+                        },
+                        Command::Anchor { id } => {
+                            max_anchor = max_anchor.max(Some(*id));
+                            let id = u16::try_from(anchor_base + *id as u32)
+                                .map_err(|_| Error::OffsetOverflow)?;
+                            Command::Anchor { id }
+                        }
+                        command => command.clone(),
+                    };
+                    commands.push(command);
+                }
+            }
+            if let Some(max_anchor) = max_anchor {
+                anchor_base += max_anchor as u32 + 1;
+            }
+        }
+
+        Ok((header, urls, commands))
+    }
+
+    /// Writes a document incrementally, one command at a time.
+    ///
+    /// The header, including the complete style vector, is written by `begin`. Commands are
+    /// written as they are pushed, so only the page offsets and anchors are retained until
+    /// `finish` writes the trailer.
+    pub struct Writer<W: io::Write> {
+        w: W,
+        version: u8,
+        header_len: usize,
+        encoder: PageEncoder,
+    }
+
+    impl<W: io::Write> Writer<W> {
+        /// Writes the header in the given version of the format. `h.version` is ignored.
+        pub fn begin(mut w: W, version: u8, h: &Header) -> Result<Self, Error> {
+            check_header(version, h)?;
+            let header_len = encode_header(&mut w, version, h)?;
+            Ok(Writer {
+                w,
+                version,
+                header_len,
+                encoder: PageEncoder::new(header_len)?,
+            })
+        }
+
+        /// Writes a command. Fails if the command postdates the target version. Commands that
+        /// follow an `End` command are ignored.
+        pub fn push<S: AsRef<str> + Clone>(&mut self, command: &Command<S>) -> Result<(), Error> {
+            if command.min_version() > self.version {
+                return Err(Error::UnsupportedFeature);
+            }
+            self.encoder.encode(&mut self.w, command)?;
+            Ok(())
+        }
+
+        pub fn page_break(&mut self) -> Result<(), Error> {
+            self.push::<&str>(&Command::PageBreak)
+        }
+
+        /// Terminates the command stream, writes the trailer, and returns the underlying writer
+        /// along with the total length of the document.
+        pub fn finish(mut self, urls: &StringPool) -> Result<(W, usize), Error> {
+            if self.version < 2 && !urls.is_empty() {
+                return Err(Error::UnsupportedFeature);
+            }
+
+            let (page_offsets, anchors, commands_len) =
+                self.encoder.finish(&mut self.w, self.header_len)?;
+            let trailer_len =
+                encode_trailer(&mut self.w, self.version, page_offsets, urls, anchors)?;
+            Ok((self.w, self.header_len + commands_len + trailer_len))
+        }
+    }
+
+    /// An in-memory `io::Write` target that appends to a `Vec<u8>`. Lets documents be written
+    /// to memory without `std`.
+    #[derive(Debug, Default, Clone)]
+    pub struct VecWriter {
+        buf: Vec<u8>,
+    }
+
+    impl VecWriter {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        pub fn as_slice(&self) -> &[u8] {
+            &self.buf
+        }
+
+        pub fn into_inner(self) -> Vec<u8> {
+            self.buf
+        }
+    }
+
+    impl io::Write for VecWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.buf.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
 }
 
 // https://tools.ietf.org/html/rfc3629
@@ -421,3 +1882,367 @@ const UTF8_CHAR_WIDTH: &[u8; 256] = &[
     3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, // E
     4, 4, 4, 4, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // F
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{format, vec};
+    use no_std_io::io;
+
+    fn header() -> Header {
+        Header {
+            version: VERSION,
+            title: "Test".into(),
+            styles: vec![Style {
+                font_name: "Regular".into(),
+                em_px: 16,
+            }],
+            images: ImageTable::new(),
+            language: None,
+            revision: None,
+            fonts: Vec::new(),
+            metadata: Metadata::default(),
+            page: None,
+        }
+    }
+
+    // Documents are written to a `VecWriter` so that the tests also run without `std`.
+    fn encode(header: &Header, commands: &[Command<&str>]) -> Vec<u8> {
+        let mut w = write::VecWriter::new();
+        write::doc(&mut w, VERSION, header, &StringPool::new(), commands).unwrap();
+        w.into_inner()
+    }
+
+    /// Returns the header of an encoded document and the offset of its first command.
+    fn read_header(bytes: &[u8]) -> (Header, usize) {
+        let mut r = io::Cursor::new(bytes);
+        let header = read::header(&mut r).unwrap();
+        (header, r.position() as usize)
+    }
+
+    #[test]
+    fn corrupt_font_lengths_fail_without_allocating() {
+        let mut h = header();
+        h.fonts.push(EmbeddedFont {
+            name: "F".into(),
+            data: vec![1, 2, 3],
+        });
+        let bytes = encode(&h, &[]);
+
+        // Claim that the font's 3 bytes are 4 GiB long, and end the input after the length.
+        let font = [1, 1, b'F', 3, 1, 2, 3];
+        let at = bytes.windows(font.len()).position(|w| w == font).unwrap();
+        let mut corrupt = bytes[..at + 3].to_vec();
+        corrupt.extend_from_slice(&[0xff, 0xff, 0xff, 0xff, 0x0f]);
+        assert!(read::header(&mut io::Cursor::new(&corrupt)).is_err());
+    }
+
+    #[test]
+    fn corrupt_metadata_lengths_fail_without_allocating() {
+        let mut h = header();
+        h.metadata.author = Some("A".into());
+        let bytes = encode(&h, &[]);
+
+        // Claim that the author's 1 byte is 4 GiB long, and end the input after the length.
+        let entry = b"\x06author\x01A";
+        let at = bytes.windows(entry.len()).position(|w| w == entry).unwrap();
+        let mut corrupt = bytes[..at + 7].to_vec();
+        corrupt.extend_from_slice(&[0xff, 0xff, 0xff, 0xff, 0x0f]);
+        assert!(read::header(&mut io::Cursor::new(&corrupt)).is_err());
+    }
+
+    #[test]
+    fn truncated_commands_are_invalid() {
+        let mut h = header();
+        let data_offset = h.images.push(ImageFormat::Gray8, &[0; 4]);
+        let commands: Vec<Command<&str>> = vec![
+            Command::Advance { dx: 300 },
+            Command::SetCursor { x: 300, y: 300 },
+            Command::SetStyle { s: 0 },
+            Command::SetAdjustmentRatio { r: 1.5 },
+            Command::SetLineMetrics {
+                height: 300,
+                baseline: 200,
+            },
+            Command::SetColor { r: 1, g: 2, b: 3 },
+            Command::SetDecoration {
+                underline: true,
+                strikethrough: false,
+            },
+            Command::DrawImage {
+                width: 300,
+                height: 300,
+                data_offset,
+            },
+            Command::Link {
+                target_offset: 300,
+                len: 300,
+            },
+            Command::Anchor { id: 300 },
+            Command::MoveBy { dx: -300, dy: 300 },
+            Command::FillRect { w: 300, h: 300 },
+            Command::ShowPositioned {
+                glyphs: vec![('a', -300), ('é', 300)],
+            },
+        ];
+
+        for command in commands {
+            let bytes = encode(&h, core::slice::from_ref(&command));
+            let (header, start) = read_header(&bytes);
+            let (decoded, len) = read::decode_command(&header, &bytes[start..]).unwrap();
+            assert_eq!(format!("{decoded:?}"), format!("{command:?}"));
+
+            for prefix in 1..=8.min(len - 1) {
+                let source = &bytes[start..start + prefix];
+                assert!(
+                    matches!(
+                        read::decode_command(&header, source),
+                        Err(read::Error::InvalidEncoding)
+                    ),
+                    "{command:?} truncated to {prefix} bytes"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn arbitrary_commands_do_not_panic() {
+        let h = header();
+        assert!(matches!(
+            read::decode_command(&h, &[]),
+            Err(read::Error::InvalidEncoding)
+        ));
+        for code in 0..=u8::MAX {
+            for fill in [0x00, 0x7f, 0x80, 0xff] {
+                for len in 1..=8 {
+                    let mut source = vec![fill; len];
+                    source[0] = code;
+                    let _ = read::decode_command(&h, &source);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn explicit_end_is_not_doubled() {
+        let h = header();
+        let implicit = encode(&h, &[Command::Show { str: "a" }]);
+        let explicit = encode(&h, &[Command::Show { str: "a" }, Command::End]);
+        assert_eq!(explicit, implicit);
+
+        let (_, start) = read_header(&explicit);
+        let mut r = io::Cursor::new(&explicit[..]);
+        read::seek_trailer(&mut r).unwrap();
+        let end = r.position() as usize;
+        let ends = explicit[start..end].iter().filter(|&&b| b == 0xbf).count();
+        assert_eq!(ends, 1);
+    }
+
+    #[test]
+    fn concatenated_documents() {
+        let mut first = header();
+        first.title = "First".into();
+        let mut second = header();
+        second.title = "Second".into();
+
+        let mut bytes = encode(&first, &[Command::Show { str: "one" }]);
+        bytes.extend(encode(
+            &second,
+            &[
+                Command::Show { str: "two" },
+                Command::PageBreak,
+                Command::Show { str: "three" },
+            ],
+        ));
+
+        let documents = read::documents(&mut io::Cursor::new(&bytes[..])).unwrap();
+        assert_eq!(documents.len(), 2);
+        assert_eq!(documents[0].header.title, "First");
+        assert_eq!(documents[0].trailer.pages.len(), 1);
+        assert_eq!(documents[1].header.title, "Second");
+        assert_eq!(documents[1].trailer.pages.len(), 2);
+
+        let second = &documents[1];
+        let offset = second.trailer.pages[1] as usize;
+        let page = read::page(&second.header, &second.bytes[offset..]).unwrap();
+        assert!(matches!(page[0], Command::Show { str: "three" }));
+    }
+
+    #[test]
+    fn vec_writer_round_trip() {
+        let mut h = header();
+        h.language = Some("en".into());
+
+        let mut w = write::VecWriter::new();
+        let commands: [Command<&str>; 3] = [
+            Command::SetStyle { s: 0 },
+            Command::Show { str: "hello" },
+            Command::LineBreak,
+        ];
+        let len = write::doc(&mut w, VERSION, &h, &StringPool::new(), &commands).unwrap();
+        let bytes = w.as_slice();
+        assert_eq!(len, bytes.len());
+
+        let mut r = io::Cursor::new(bytes);
+        let header = read::header(&mut r).unwrap();
+        assert_eq!(header.title, "Test");
+        assert_eq!(header.language.as_deref(), Some("en"));
+        read::seek_trailer(&mut r).unwrap();
+        let trailer = read::trailer(&header, &mut r).unwrap();
+        read::validate(&header, &trailer, bytes.len()).unwrap();
+
+        let page = read::page(&header, &bytes[trailer.pages[0] as usize..]).unwrap();
+        assert!(matches!(page[0], Command::SetStyle { s: 0 }));
+        assert!(matches!(page[1], Command::Show { str: "hello" }));
+        assert!(matches!(page[2], Command::LineBreak));
+    }
+
+    #[test]
+    fn language_round_trip() {
+        let mut tagged = header();
+        tagged.language = Some("pt-BR".into());
+        let bytes = encode(&tagged, &[Command::Show { str: "olá" }]);
+        assert_eq!(read_header(&bytes).0.language.as_deref(), Some("pt-BR"));
+
+        let bytes = encode(&header(), &[Command::Show { str: "hi" }]);
+        assert_eq!(read_header(&bytes).0.language, None);
+    }
+
+    #[test]
+    fn revision_round_trip() {
+        let mut revised = header();
+        revised.revision = Some(0x0123_4567_89ab_cdef);
+        let bytes = encode(&revised, &[Command::Show { str: "hi" }]);
+        assert_eq!(read_header(&bytes).0.revision, Some(0x0123_4567_89ab_cdef));
+
+        let bytes = encode(&header(), &[Command::Show { str: "hi" }]);
+        assert_eq!(read_header(&bytes).0.revision, None);
+    }
+
+    /// Returns the warnings for the pages of an encoded document.
+    fn warnings(bytes: &[u8]) -> Vec<(u32, read::Warning)> {
+        let documents = read::documents(&mut io::Cursor::new(bytes)).unwrap();
+        let doc = &documents[0];
+        read::verify(&doc.header, &doc.trailer, &doc.bytes).unwrap()
+    }
+
+    #[test]
+    fn verify_flags_show_before_style() {
+        let metrics = Command::SetLineMetrics {
+            height: 20,
+            baseline: 4,
+        };
+
+        // The first page may use the header's first style, but must set its line metrics.
+        let bytes = encode(&header(), &[metrics.clone(), Command::Show { str: "a" }]);
+        assert_eq!(warnings(&bytes), vec![]);
+        let bytes = encode(&header(), &[Command::Show { str: "a" }, metrics.clone()]);
+        assert_eq!(warnings(&bytes), vec![(0, read::Warning::ShowBeforeStyle)]);
+
+        // Later pages must set both.
+        let bytes = encode(
+            &header(),
+            &[
+                metrics.clone(),
+                Command::Show { str: "a" },
+                Command::PageBreak,
+                Command::Show { str: "b" },
+                Command::PageBreak,
+                metrics.clone(),
+                Command::Show { str: "c" },
+                Command::PageBreak,
+                Command::SetStyle { s: 0 },
+                metrics,
+                Command::Show { str: "d" },
+            ],
+        );
+        assert_eq!(
+            warnings(&bytes),
+            vec![
+                (1, read::Warning::ShowBeforeStyle),
+                (2, read::Warning::ShowBeforeStyle),
+            ]
+        );
+    }
+
+    /// A 30px font whose glyphs are all 10px wide.
+    #[cfg(feature = "layout")]
+    #[derive(Clone)]
+    struct TestStyle;
+
+    #[cfg(feature = "layout")]
+    impl fonts::FontStyle for TestStyle {
+        fn font_name(&self) -> &str {
+            "Regular"
+        }
+
+        fn em_px(&self) -> u16 {
+            30
+        }
+
+        fn line_height(&self) -> u16 {
+            36
+        }
+
+        fn baseline(&self) -> u16 {
+            6
+        }
+    }
+
+    #[cfg(feature = "layout")]
+    impl layout::FontStyle for TestStyle {
+        fn measure_string(&self, text: &str) -> layout::TextMetrics {
+            use embedded_graphics::{geometry::Point, geometry::Size, primitives::Rectangle};
+            let width = 10 * text.chars().count() as u32;
+            layout::TextMetrics {
+                bounding_box: Rectangle::new(Point::new(0, 0), Size::new(width, 36)),
+            }
+        }
+    }
+
+    #[cfg(feature = "layout")]
+    struct TestFonts;
+
+    #[cfg(feature = "layout")]
+    impl layout::Fonts for TestFonts {
+        type Style = TestStyle;
+
+        fn get_style(&self, _style: &Style) -> Option<TestStyle> {
+            Some(TestStyle)
+        }
+    }
+
+    #[cfg(feature = "layout")]
+    #[test]
+    fn links_follow_the_cursor() {
+        let mut urls = StringPool::new();
+        let target_offset = urls.push("https://example.com");
+        let commands: [Command<&str>; 7] = [
+            Command::SetAdjustmentRatio { r: 1.0 },
+            Command::Show { str: "a\u{a0}" },
+            Command::MoveBy { dx: 5, dy: 0 },
+            Command::Link {
+                target_offset,
+                len: 3,
+            },
+            Command::Show { str: "b c" },
+            Command::LineBreak,
+            Command::Show { str: "d" },
+        ];
+        let mut w = write::VecWriter::new();
+        write::doc(&mut w, VERSION, &header(), &urls, &commands).unwrap();
+
+        let documents = read::documents(&mut io::Cursor::new(w.as_slice())).unwrap();
+        let doc = &documents[0];
+        let source = &doc.bytes[doc.trailer.pages[0] as usize..];
+        let links = read::links(&doc.header, &doc.trailer, TestFonts, source).unwrap();
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].url, "https://example.com");
+
+        // The non-breaking space is unadjusted and the adjusted space is 15px wide, so the link
+        // spans 10 + 15 + 10px from x = 10 + 10 + 5.
+        let bounds = links[0].bounding_box;
+        assert_eq!((bounds.top_left.x, bounds.top_left.y), (25, 0));
+        assert_eq!((bounds.size.width, bounds.size.height), (35, 36));
+    }
+}
@@ -0,0 +1,226 @@
+//! A font backend for `embedded_graphics` bitmap fonts, for devices that can't afford TrueType
+//! rasterization.
+
+use crate::{fonts, Style};
+
+#[cfg(feature = "layout")]
+use crate::layout;
+
+#[cfg(feature = "layout")]
+use embedded_graphics::{geometry::Size, primitives::rectangle::Rectangle};
+
+#[cfg(feature = "display")]
+use crate::display;
+
+#[cfg(feature = "display")]
+use embedded_graphics::{
+    draw_target::DrawTarget, geometry::OriginDimensions, image::GetPixel, pixelcolor::BinaryColor,
+    Pixel,
+};
+
+#[cfg(any(feature = "layout", feature = "display"))]
+use embedded_graphics::geometry::Point;
+
+extern crate alloc;
+use alloc::vec::Vec;
+use embedded_graphics::mono_font::MonoFont;
+
+/// A set of named bitmap fonts.
+///
+/// Each `MonoFont` has a single size. Several fonts may be added under the same name, in which
+/// case styles resolve to the font whose character height is closest to the requested size.
+#[derive(Default)]
+pub struct MonoFonts<'a> {
+    fonts: Vec<(&'a str, &'a MonoFont<'a>)>,
+}
+
+impl<'a> MonoFonts<'a> {
+    pub fn new() -> Self {
+        MonoFonts { fonts: Vec::new() }
+    }
+
+    pub fn add(&mut self, name: &'a str, font: &'a MonoFont<'a>) {
+        self.fonts.push((name, font));
+    }
+
+    pub fn get_style(&self, style: &Style) -> Option<MonoFontStyle<'a>> {
+        self.fonts
+            .iter()
+            .filter(|(name, _)| *name == style.font_name)
+            .min_by_key(|(_, font)| font.character_size.height.abs_diff(style.em_px as u32))
+            .map(|&(name, font)| MonoFontStyle { name, font })
+    }
+}
+
+#[cfg(feature = "layout")]
+impl<'a, 'b> layout::Fonts for &'a MonoFonts<'b>
+where
+    'b: 'a,
+{
+    type Style = MonoFontStyle<'b>;
+
+    fn get_style(&self, style: &Style) -> Option<Self::Style> {
+        MonoFonts::get_style(self, style)
+    }
+}
+
+#[cfg(feature = "display")]
+impl<'a, 'b> display::Fonts for &'a MonoFonts<'b>
+where
+    'b: 'a,
+{
+    type Style = MonoFontStyle<'b>;
+
+    fn get_style(&self, style: &Style) -> Option<Self::Style> {
+        MonoFonts::get_style(self, style)
+    }
+}
+
+#[derive(Clone)]
+pub struct MonoFontStyle<'a> {
+    name: &'a str,
+    font: &'a MonoFont<'a>,
+}
+
+impl<'a> MonoFontStyle<'a> {
+    /// The distance between the origins of adjacent glyphs.
+    pub fn advance(&self) -> u32 {
+        self.font.character_size.width + self.font.character_spacing
+    }
+}
+
+impl<'a> fonts::FontStyle for MonoFontStyle<'a> {
+    fn font_name(&self) -> &str {
+        self.name
+    }
+
+    fn em_px(&self) -> u16 {
+        self.font.character_size.height as u16
+    }
+
+    fn line_height(&self) -> u16 {
+        self.font.character_size.height as u16
+    }
+
+    fn baseline(&self) -> u16 {
+        (self.font.character_size.height - self.font.baseline) as u16
+    }
+}
+
+#[cfg(feature = "layout")]
+impl<'a> layout::FontStyle for MonoFontStyle<'a> {
+    fn measure_string(&self, text: &str) -> layout::TextMetrics {
+        let width = text.chars().count() as u32 * self.advance();
+        let bounding_box = Rectangle::new(
+            Point::new(0, 0),
+            Size::new(width, self.font.character_size.height),
+        );
+
+        layout::TextMetrics { bounding_box }
+    }
+}
+
+#[cfg(feature = "display")]
+impl<'a> display::FontStyle for MonoFontStyle<'a> {
+    fn glyph_advance(&self, _c: char) -> i32 {
+        self.advance() as i32
+    }
+
+    fn draw_glyph<C: display::Color, Draw: DrawTarget<Color = C>>(
+        &self,
+        draw: &mut Draw,
+        origin: Point,
+        color: C,
+        _over: C,
+        _gamma: f32,
+        c: char,
+    ) -> Result<Point, Draw::Error> {
+        let font = self.font;
+        let size = font.character_size;
+
+        // Locate the glyph's cell in the font's glyph atlas.
+        let index = font.glyph_mapping.index(c) as u32;
+        let columns = (font.image.size().width / size.width).max(1);
+        let cell = Point::new(
+            ((index % columns) * size.width) as i32,
+            ((index / columns) * size.height) as i32,
+        );
+
+        let top_left = origin - Point::new(0, font.baseline as i32);
+        let pixels = (0..size.height)
+            .flat_map(|y| (0..size.width).map(move |x| Point::new(x as i32, y as i32)))
+            .filter(|p| font.image.pixel(cell + *p) == Some(BinaryColor::On))
+            .map(|p| Pixel(top_left + p, color));
+        draw.draw_iter(pixels)?;
+
+        Ok(origin + Point::new(self.advance() as i32, 0))
+    }
+}
+
+#[cfg(all(test, feature = "display"))]
+mod tests {
+    use super::*;
+    use crate::{
+        display::{trace, Trace, TraceEvent},
+        Command, Header, ImageTable, Metadata, VERSION,
+    };
+    use alloc::vec;
+    use embedded_graphics::{
+        geometry::Size, mono_font::ascii::FONT_6X10, pixelcolor::Gray8, primitives::Rectangle,
+    };
+
+    #[test]
+    fn glyphs_advance_by_a_fixed_width() {
+        let mut fonts = MonoFonts::new();
+        fonts.add("Mono", &FONT_6X10);
+        let style = Style {
+            font_name: "Mono".into(),
+            em_px: 10,
+        };
+        let header = Header {
+            version: VERSION,
+            title: "Test".into(),
+            styles: vec![style.clone()],
+            images: ImageTable::new(),
+            language: None,
+            revision: None,
+            fonts: Vec::new(),
+            metadata: Metadata::default(),
+            page: None,
+        };
+        let commands = [Command::SetStyle { s: 0 }, Command::Show { str: "Wil" }];
+
+        let mut events = Trace::default();
+        trace(
+            &mut events,
+            Point::zero(),
+            Rectangle::new(Point::zero(), Size::new(100, 20)),
+            false,
+            Gray8::BLACK,
+            &fonts,
+            fonts.get_style(&style).unwrap(),
+            &header,
+            &commands,
+        )
+        .unwrap();
+
+        // Wide and narrow glyphs alike advance by the font's 6px cell.
+        let glyphs: Vec<(char, Point)> = events
+            .events
+            .iter()
+            .filter_map(|(event, at)| match event {
+                TraceEvent::GlyphAt { c, .. } => Some((*c, *at)),
+                _ => None,
+            })
+            .collect();
+        let y = glyphs[0].1.y;
+        assert_eq!(
+            glyphs,
+            [
+                ('W', Point::new(0, y)),
+                ('i', Point::new(6, y)),
+                ('l', Point::new(12, y)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,251 @@
+//! Tests of the `edf` command-line tool, which run the built binary.
+#![cfg(feature = "std")]
+
+use std::ffi::OsStr;
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// A device with a 360x560px content box.
+const DEVICE_CONFIG: &str = "\
+ppi = 100
+width_px = 400
+height_px = 600
+top_margin_px = 20
+left_margin_px = 20
+bottom_margin_px = 20
+right_margin_px = 20
+";
+
+/// A scratch directory for a test's files, which is removed when it is dropped.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("edf-cli-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    /// Writes a file into the directory, returning its path.
+    fn write(&self, name: &str, contents: impl AsRef<[u8]>) -> PathBuf {
+        let path = self.0.join(name);
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn join(&self, name: &str) -> PathBuf {
+        self.0.join(name)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Runs `edf` with the given arguments, panicking if it fails.
+fn edf<I: IntoIterator<Item = S>, S: AsRef<OsStr>>(args: I) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_edf"))
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "edf failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+/// Lays out a markdown source with the bundled font, returning the path of the document.
+fn mk_markdown(dir: &TempDir, name: &str, source: &str) -> PathBuf {
+    let device = dir.write("device.toml", DEVICE_CONFIG);
+    let input = dir.write(&format!("{name}.md"), source);
+    let output = dir.join(&format!("{name}.edf"));
+    edf([
+        OsStr::new("mk"),
+        input.as_os_str(),
+        OsStr::new("-d"),
+        device.as_os_str(),
+        OsStr::new("-o"),
+        output.as_os_str(),
+    ]);
+    output
+}
+
+/// Writes a single-page document with the given commands, returning its path.
+fn write_doc(dir: &TempDir, name: &str, commands: &[edf::Command<&str>]) -> PathBuf {
+    let header = edf::Header {
+        version: edf::VERSION,
+        title: "Test".into(),
+        styles: vec![edf::Style {
+            font_name: "Regular".into(),
+            em_px: 16,
+        }],
+        images: edf::ImageTable::new(),
+        language: None,
+        revision: None,
+        fonts: Vec::new(),
+        metadata: edf::Metadata::default(),
+        page: None,
+    };
+    let mut w = edf::write::VecWriter::new();
+    edf::write::doc(
+        &mut w,
+        edf::VERSION,
+        &header,
+        &edf::StringPool::new(),
+        commands,
+    )
+    .unwrap();
+    dir.write(&format!("{name}.edf"), w.into_inner())
+}
+
+fn read_header(path: &Path) -> edf::Header {
+    let bytes = fs::read(path).unwrap();
+    edf::read::header(&mut Cursor::new(&bytes)).unwrap()
+}
+
+#[test]
+fn mk_records_the_source_revision() {
+    let dir = TempDir::new("revision");
+    let first = read_header(&mk_markdown(&dir, "first", "Hello, world."));
+    let again = read_header(&mk_markdown(&dir, "again", "Hello, world."));
+    let changed = read_header(&mk_markdown(&dir, "changed", "Hello, world!"));
+
+    assert!(first.revision.is_some());
+    assert_eq!(first.revision, again.revision);
+    assert_ne!(first.revision, changed.revision);
+}
+
+#[test]
+fn dump_flags_cursors_outside_the_page() {
+    let dir = TempDir::new("dump-bounds");
+    let device = dir.write("device.toml", DEVICE_CONFIG);
+    let doc = write_doc(
+        &dir,
+        "doc",
+        &[
+            edf::Command::SetLineMetrics {
+                height: 20,
+                baseline: 4,
+            },
+            edf::Command::SetCursor { x: 10, y: 10 },
+            edf::Command::SetCursor { x: 500, y: 10 },
+        ],
+    );
+
+    let output = edf([
+        OsStr::new("dump"),
+        doc.as_os_str(),
+        OsStr::new("-d"),
+        device.as_os_str(),
+    ]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert!(
+        lines.contains(&"- `SetCursor { x: 10, y: 10 }`"),
+        "{stdout}"
+    );
+    assert!(
+        lines.contains(&"- `SetCursor { x: 500, y: 10 }` (outside page bounds 360x560)"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn hex_annotates_the_magic_number_and_commands() {
+    let dir = TempDir::new("hex");
+    let doc = write_doc(
+        &dir,
+        "doc",
+        &[
+            edf::Command::SetLineMetrics {
+                height: 20,
+                baseline: 4,
+            },
+            edf::Command::SetCursor { x: 10, y: 10 },
+        ],
+    );
+    let bytes = fs::read(&doc).unwrap();
+    let mut r = Cursor::new(&bytes);
+    let header = edf::read::header(&mut r).unwrap();
+    edf::read::seek_trailer(&mut r).unwrap();
+    let page = edf::read::trailer(&header, &mut r).unwrap().pages[0];
+
+    let output = edf([OsStr::new("hex"), doc.as_os_str()]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    let magic = format!("0e df {:02x} 00", edf::VERSION);
+    assert_eq!(
+        lines[1],
+        format!("00000000  {magic:47}  magic, version {}", edf::VERSION)
+    );
+
+    // The first command of the page is annotated on the row that holds its bytes.
+    let first = lines.iter().position(|line| *line == "# Page 1").unwrap() + 1;
+    assert_eq!(
+        lines[first],
+        format!(
+            "{page:08x}  {:47}  SetLineMetrics {{ height: 20, baseline: 4 }}",
+            "85 14 04"
+        )
+    );
+}
+
+#[test]
+fn embedded_fonts_render_without_the_font_files() {
+    let dir = TempDir::new("embed-fonts");
+    let device = dir.write("device.toml", DEVICE_CONFIG);
+    let literata = include_bytes!("../src/bin/edf/assets/Literata-Regular.ttf");
+    let font = dir.write("Body.ttf", literata);
+    let font_config = dir.write("fonts.toml", "[fonts]\nBody = \"Body.ttf\"\n");
+    let markdown_config = dir.write(
+        "markdown.toml",
+        "[regular]\nfont_name = \"Body\"\npoint_size = 12.0\n",
+    );
+    let input = dir.write("doc.md", "Hello, world.");
+    let doc = dir.join("doc.edf");
+    edf([
+        OsStr::new("mk"),
+        input.as_os_str(),
+        OsStr::new("-d"),
+        device.as_os_str(),
+        OsStr::new("-f"),
+        font_config.as_os_str(),
+        OsStr::new("-c"),
+        markdown_config.as_os_str(),
+        OsStr::new("--embed-fonts"),
+        OsStr::new("-o"),
+        doc.as_os_str(),
+    ]);
+    fs::remove_file(&font).unwrap();
+    fs::remove_file(&font_config).unwrap();
+
+    // The font is subset to the document's characters.
+    let header = read_header(&doc);
+    assert_eq!(header.fonts.len(), 1);
+    assert_eq!(header.fonts[0].name, "Body");
+    assert!(header.fonts[0].data.len() < literata.len());
+
+    // The bundled font is named "regular", so the page can only be drawn with the embedded one.
+    let pages = dir.join("pages");
+    edf([
+        OsStr::new("render"),
+        doc.as_os_str(),
+        OsStr::new("-p"),
+        OsStr::new("1"),
+        OsStr::new("--format"),
+        OsStr::new("ppm"),
+        OsStr::new("-o"),
+        pages.as_os_str(),
+    ]);
+    let ppm = fs::read(pages.join("page-0001.ppm")).unwrap();
+    let header_len = b"P6\n400 600\n255\n".len();
+    assert!(ppm[..header_len].starts_with(b"P6\n400 600\n"));
+    assert!(ppm[header_len..].iter().any(|luma| *luma != 255));
+}